@@ -0,0 +1,172 @@
+//! Assets panel: the images referenced by the tree (assets.rs) with thumbnails,
+//! usage counts, missing files and unused files under the asset root.
+//! a path (or a prefix with "prefix") is relinked to another one as one undoable step.
+//! the list is refreshed when the history is changed, "rescan" reloads the thumbnails too.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::rc::Rc;
+use gtk::prelude::*;
+use gtk::gdk;
+use gtk::{Box, Button, CheckButton, ContentFit, Entry, Image, Label, ListBox, Orientation,
+          Picture, PolicyType, ScrolledWindow, SelectionMode};
+
+use crate::assets::{self, AssetEntry};
+use crate::render;
+use crate::operation_history::OperationHistory;
+use crate::scenario_node_object::first_root;
+
+const THUMBNAIL_W: i32 = 64;
+const THUMBNAIL_H: i32 = 40;
+
+// AssetPanel //////////////////////////////////////////////
+struct AssetPanel {
+    root      : PathBuf,
+    list_box  : ListBox,
+    summary   : Label,
+    entries   : RefCell<Vec<AssetEntry>>, // in the order of the rows
+    thumbnails: RefCell<HashMap<String, Option<gdk::Texture>>>,
+}
+impl AssetPanel {
+    // thumbnail ///////////////////////////////////////////
+    fn thumbnail(&self, e: &AssetEntry) -> gtk::Widget {
+        let texture= self.thumbnails.borrow_mut()
+            .entry(e.path.clone())
+            .or_insert_with(|| gdk::Texture::from_filename(self.root.join(&e.path)).ok())
+            .clone();
+        let w: gtk::Widget= match texture {
+            Some(t) => Picture::builder()
+                .paintable(&t)
+                .content_fit(ContentFit::Contain)
+                .can_shrink(true)
+                .build().upcast(),
+            None => Image::from_icon_name("image-missing").upcast(),
+        };
+        w.set_size_request(THUMBNAIL_W, THUMBNAIL_H);
+        w
+    }
+    // refresh /////////////////////////////////////////////
+    fn refresh(&self, history: &OperationHistory) {
+        let entries= assets::scan( first_root(&history.tree_list_model()), &self.root );
+        self.list_box.remove_all();
+        for e in entries.iter() {
+            let status= match (e.exists, e.users.len()) {
+                (true,  0) => String::from("unused"),
+                (true,  n) => format!("used {}", n),
+                (false, n) => format!("missing, used {}", n),
+            };
+            let label= Label::new(Some(&format!("{}  ({})", e.path, status)));
+            label.set_xalign(0.0);
+            label.set_tooltip_text( Some(&e.users.iter().map(|i| format!("id:{}", i)).collect::<Vec<_>>().join(", ")) );
+            if !e.exists          { label.add_css_class("asset_missing"); }
+            if e.users.is_empty() { label.add_css_class("asset_unused"); }
+
+            let row= Box::new(Orientation::Horizontal, 4);
+            row.append(&self.thumbnail(e));
+            row.append(&label);
+            self.list_box.append(&row);
+        }
+        self.summary.set_label( &format!("{}: {} images, {} missing, {} unused",
+                                         self.root.display(),
+                                         entries.len(),
+                                         entries.iter().filter(|e| !e.exists).count(),
+                                         entries.iter().filter(|e| e.users.is_empty()).count()) );
+        *self.entries.borrow_mut()= entries;
+    }
+}
+// build_asset_panel ///////////////////////////////////////
+pub fn build_asset_panel(history: Rc<OperationHistory>, root: PathBuf) -> Box {
+    let panel= Rc::new( AssetPanel{
+        root,
+        list_box  : ListBox::new(),
+        summary   : Label::builder().xalign(0.0).build(),
+        entries   : RefCell::new(Vec::new()),
+        thumbnails: RefCell::new(HashMap::new()),
+    } );
+    panel.list_box.set_selection_mode(SelectionMode::Single);
+
+    let rescan= Button::with_label("rescan");
+    let from  = Entry::builder().placeholder_text("from").hexpand(true).build();
+    let to    = Entry::builder().placeholder_text("to").hexpand(true).build();
+    let prefix= CheckButton::with_label("prefix");
+    let relink= Button::with_label("relink");
+    let result= Label::builder().xalign(0.0).build();
+
+    // a row -> from
+    let panel_for_row= Rc::downgrade(&panel);
+    let from_for_row = from.clone();
+    panel.list_box.connect_row_selected(move |_, row| {
+        let (Some(p), Some(r)) = (panel_for_row.upgrade(), row) else { return };
+        let path= p.entries.borrow().get(r.index() as usize).map(|e| e.path.clone());
+        if let Some(path) = path {
+            from_for_row.set_text(&path); }
+    });
+    // rescan //////////////////////////////////////////////
+    let panel_for_rescan  = Rc::downgrade(&panel);
+    let history_for_rescan= Rc::downgrade(&history);
+    rescan.connect_clicked(move |_| {
+        if let (Some(p), Some(h)) = (panel_for_rescan.upgrade(), history_for_rescan.upgrade()) {
+            p.thumbnails.borrow_mut().clear();
+            render::clear_images();
+            p.refresh(&h);
+        }
+    });
+    // relink //////////////////////////////////////////////
+    let panel_for_relink  = Rc::downgrade(&panel);
+    let history_for_relink= Rc::downgrade(&history);
+    let result_for_relink = result.clone();
+    let (from_for_relink, to_for_relink, prefix_for_relink)= (from.clone(), to.clone(), prefix.clone());
+    relink.connect_clicked(move |_| {
+        let (Some(p), Some(h)) = (panel_for_relink.upgrade(), history_for_relink.upgrade()) else { return };
+        let (f, t)= (from_for_relink.text().trim().to_string(), to_for_relink.text().trim().to_string());
+        if f.is_empty() || t.is_empty() {
+            result_for_relink.set_label("from and to are needed");
+            return;
+        }
+        let n= match assets::relink(&h, &f, &t, prefix_for_relink.is_active()) {
+            Ok(n)  => n,
+            Err(e) => {
+                h.report(&e);
+                result_for_relink.set_label( &format!("not relinked: {}", e) );
+                return;
+            },
+        };
+        let mut msg= format!("{} nodes relinked", n);
+        if !prefix_for_relink.is_active() && !p.root.join(&t).is_file() {
+            msg+= &format!(", {} is not found", t); }
+        result_for_relink.set_label(&msg);
+    });
+
+    let panel_for_history  = panel.clone(); // the panel lives as long as the history
+    let history_for_history= Rc::downgrade(&history);
+    history.connect_changed(move || {
+        if let Some(h) = history_for_history.upgrade() {
+            panel_for_history.refresh(&h);
+        }
+    });
+    panel.refresh(&history);
+
+    let header= Box::new(Orientation::Horizontal, 4);
+    panel.summary.set_hexpand(true);
+    header.append(&panel.summary);
+    header.append(&rescan);
+
+    let relink_box= Box::new(Orientation::Horizontal, 4);
+    relink_box.append(&from);
+    relink_box.append(&to);
+    relink_box.append(&prefix);
+    relink_box.append(&relink);
+
+    let vbox= Box::new(Orientation::Vertical, 2);
+    vbox.append(&header);
+    vbox.append(&ScrolledWindow::builder()
+                .hscrollbar_policy(PolicyType::Never)
+                .min_content_width(200)
+                .vexpand(true)
+                .child(&panel.list_box)
+                .build());
+    vbox.append(&relink_box);
+    vbox.append(&result);
+    vbox
+}
@@ -0,0 +1,129 @@
+//! image assets: Scene.bgimg and Ovimg.path
+//!
+//! the paths are relative to the asset root (ASSET_DIR next to the scenario file).
+//! scan() lists every referenced image with the ids of its users and whether the file exists,
+//! and the image files under the root which are not referenced.
+//! relink() rewrites the references of a path (or of every path under a prefix,
+//! e.g. a renamed directory) as one undoable transaction.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::scenario_node::{ScenarioNode, Item};
+use crate::scenario_node_object::{Touched, first_root, set_value_at, refresh_rows};
+use crate::scenario_query::pre_order_forest;
+use crate::operation_history::{OperationHistory, OperationHistoryItem};
+use crate::tree_error::TreeResult;
+
+pub const ASSET_DIR: &str = "assets";
+const IMAGE_EXTENSIONS: [&str; 6] = ["png", "jpg", "jpeg", "gif", "webp", "bmp"];
+
+// image_ref ///////////////////////////////////////////////
+/// the image path of a scene / ovimg and its field name
+pub fn image_ref(v: &Item) -> Option<(&'static str, String)> {
+    match v {
+        Item::Scene(s) => s.bgimg.clone().filter(|p| !p.is_empty()).map(|p| ("bgimg", p)),
+        Item::Ovimg(o) => Some(o.path.clone()).filter(|p| !p.is_empty()).map(|p| ("path", p)),
+        _              => None,
+    }
+}
+fn with_image_ref(v: &Item, path: String) -> Item {
+    let mut v= v.clone();
+    match &mut v {
+        Item::Scene(s) => s.bgimg= Some(path),
+        Item::Ovimg(o) => o.path = path,
+        _              => (),
+    }
+    v
+}
+// AssetEntry //////////////////////////////////////////////
+#[derive(Debug, Clone)]
+pub struct AssetEntry {
+    pub path  : String,   // relative to the asset root
+    pub users : Vec<i32>, // ids of the referencing nodes, empty if unused
+    pub exists: bool,
+}
+// image_files /////////////////////////////////////////////
+/// the image files under `dir`, relative to `root`
+fn image_files(root: &Path, dir: &Path, files: &mut Vec<String>) {
+    let Ok(entries) = fs::read_dir(dir) else { return };
+    for e in entries.flatten() {
+        let p= e.path();
+        if p.is_dir() {
+            image_files(root, &p, files);
+        } else if p.extension().and_then(|x| x.to_str())
+            .is_some_and(|x| IMAGE_EXTENSIONS.contains(&x.to_lowercase().as_str())) {
+            if let Ok(rel) = p.strip_prefix(root) {
+                files.push( rel.to_string_lossy().replace('\\', "/") ); }
+        }
+    }
+}
+// scan ////////////////////////////////////////////////////
+/// the referenced images and the unused image files, sorted by path
+pub fn scan(first_root: Option<Rc<ScenarioNode>>, root: &Path) -> Vec<AssetEntry> {
+    let mut users: BTreeMap<String, Vec<i32>>= BTreeMap::new();
+    for n in pre_order_forest(first_root) {
+        if let Some((_, p)) = image_ref(&n.value.borrow()) {
+            users.entry(p).or_default().push(n.id.get()); }
+    }
+    let mut files= Vec::new();
+    image_files(root, root, &mut files);
+    for f in files {
+        users.entry(f).or_default(); }
+
+    users.into_iter()
+        .map(|(path, users)| AssetEntry{ exists: root.join(&path).is_file(), path, users })
+        .collect()
+}
+// relink //////////////////////////////////////////////////
+/// the new path of `path`: `from` is replaced by `to`,
+/// with `prefix` every path under `from` is rewritten too
+pub fn relinked(path: &str, from: &str, to: &str, prefix: bool) -> Option<String> {
+    if path == from {
+        return Some(to.to_string()); }
+    if prefix && !from.is_empty() {
+        let dir= from.trim_end_matches('/');
+        if let Some(rest) = path.strip_prefix(dir).and_then(|r| r.strip_prefix('/')) {
+            return Some( format!("{}/{}", to.trim_end_matches('/'), rest) ); }
+    }
+    None
+}
+/// rewrite the references, returns the number of the rewritten nodes.
+/// when a row does not follow, the nodes rewritten so far are undone
+pub fn relink(history: &OperationHistory, from: &str, to: &str, prefix: bool) -> TreeResult<usize> {
+    let tlm    = history.tree_list_model();
+    let targets: Vec<(Rc<ScenarioNode>, &'static str, String)>= pre_order_forest( first_root(&tlm) )
+        .filter_map(|n| {
+            let (field, p)= image_ref(&n.value.borrow())?;
+            let new= relinked(&p, from, to, prefix)?;
+            Some( (n.clone(), field, new) )
+        })
+        .collect();
+    if targets.is_empty() {
+        return Ok(0); }
+
+    history.begin( &format!("relink {} to {}", from, to) );
+    let mut touched= Touched::new();
+    for (n, field, new) in targets.iter() {
+        let old= n.value.borrow().clone();
+        let v  = with_image_ref(&old, new.clone());
+        match set_value_at(&tlm, &n.index_path(), v.clone()) {
+            Ok(t)  => touched.extend(t),
+            Err(e) => {
+                refresh_rows(&tlm, &touched);
+                return history.abort().and(Err(e));
+            },
+        }
+        history.push( OperationHistoryItem::new_set_value(n, field, old, v) );
+    }
+    refresh_rows(&tlm, &touched);
+    history.commit();
+    Ok(targets.len())
+}
+// asset_root //////////////////////////////////////////////
+/// ASSET_DIR next to `scenario_path`
+pub fn asset_root(scenario_path: &Path) -> PathBuf {
+    scenario_path.parent().unwrap_or(Path::new("")).join(ASSET_DIR)
+}
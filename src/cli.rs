@@ -0,0 +1,141 @@
+//! subcommands which work without a display
+//!
+//!   validate <file>...            the hierarchy and label checks (validate.rs), fails on an issue
+//!   dump     <file>               the tree in the ScenarioNode::dump style
+//!   convert  <in> <out>           between JSON and XML (scenario_xml.rs), by the extensions
+//!   stats    <file>...            the number of nodes per kind
+//!   render   <file> <dir> [WxH]   a PNG of each click state (player.rs): <dir>/0001.png, ...
+//!   diff     <old> <new>          the structural diff (scenario_diff.rs), fails when they differ
+//!   merge    <base> <ours> <theirs> <out>
+//!                                 the three-way merge into <out>, fails on a conflict
+//!
+//! a file is XML when its extension is ".xml", JSON otherwise (scenario_file.rs).
+//! without a subcommand the GTK app is launched.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use gtk::glib::ExitCode;
+
+use crate::scenario_node::ItemKind;
+use crate::scenario_query::pre_order_forest;
+use crate::{scenario_file, scenario_diff, validate, assets, player, render};
+use crate::scenario_file::{read_file, write_file};
+use crate::render::Canvas;
+use crate::text_source::SourceCache;
+
+const USAGE: &str = "\
+usage: reorderable_list_with_gtk4 [<command> <args>]
+  validate <file>...            the hierarchy and label checks
+  dump     <file>               print the tree
+  convert  <in> <out>           convert between JSON and XML (.json / .xml)
+  stats    <file>...            count the nodes per kind
+  render   <file> <dir> [WxH]   write a PNG of each click state
+  diff     <old> <new>          print the changes by node id
+  merge    <base> <ours> <theirs> <out>
+                                three-way merge, the conflicts are printed
+without a command the GUI is launched";
+
+const COMMANDS: [&str; 7] = ["validate", "dump", "convert", "stats", "render", "diff", "merge"];
+
+fn parse_canvas(s: &str) -> Option<Canvas> {
+    let (w, h)= s.split_once('x')?;
+    let canvas= Canvas{ w: w.trim().parse().ok()?, h: h.trim().parse().ok()? };
+    (canvas.w > 0 && canvas.h > 0).then_some(canvas)
+}
+// commands ////////////////////////////////////////////////
+fn validate_files(paths: &[String]) -> io::Result<bool> {
+    let mut ok= true;
+    for p in paths {
+        let (roots, _)= scenario_file::load(Path::new(p))?;
+        let issues= validate::validate(roots.first().cloned());
+        for i in issues.iter() {
+            println!("{}: {}", p, i); }
+        ok&= issues.is_empty();
+    }
+    Ok(ok)
+}
+fn dump(path: &str) -> io::Result<bool> {
+    let (roots, _)= scenario_file::load(Path::new(path))?;
+    if let Some(r) = roots.first() {
+        r.dump(0); }
+    Ok(true)
+}
+fn convert(input: &str, output: &str) -> io::Result<bool> {
+    let (roots, next_id)= scenario_file::load(Path::new(input))?;
+    scenario_file::save(Path::new(output), roots.first().cloned(), next_id)?;
+    Ok(true)
+}
+fn stats(paths: &[String]) -> io::Result<bool> {
+    for p in paths {
+        let (roots, _)= scenario_file::load(Path::new(p))?;
+        let mut count= [0usize; ItemKind::ALL.len()];
+        for n in pre_order_forest(roots.first().cloned()) {
+            let kind= n.value.borrow().kind();
+            count[ ItemKind::ALL.iter().position(|k| *k == kind).unwrap_or(0) ]+= 1;
+        }
+        let line: Vec<String>= ItemKind::ALL.iter().zip(count.iter())
+            .map(|(k, c)| format!("{}:{}", k.name(), c))
+            .collect();
+        println!("{}: {} total:{}", p, line.join(" "), count.iter().sum::<usize>());
+    }
+    Ok(true)
+}
+fn render_pngs(path: &str, dir: &str, canvas: Canvas) -> io::Result<bool> {
+    let path= Path::new(path);
+    let (roots, _)= scenario_file::load(path)?;
+    let sources   = SourceCache::new( path.parent().unwrap_or(Path::new("")) );
+    let asset_root= assets::asset_root(path);
+    let units= player::click_units(roots.first().cloned());
+    fs::create_dir_all(dir)?;
+    for i in 0..units.len() {
+        let out= Path::new(dir).join( format!("{:04}.png", i + 1) );
+        render::export_units_png(player::click_state(&units, i), &sources, &asset_root, canvas, &out)?;
+        println!("{}", out.display());
+    }
+    Ok(true)
+}
+fn diff(old: &str, new: &str) -> io::Result<bool> {
+    let (old, new)= (read_file(Path::new(old))?, read_file(Path::new(new))?);
+    let changes= scenario_diff::diff(&old.nodes, &new.nodes);
+    for c in changes.iter() {
+        println!("{}", c); }
+    Ok(changes.is_empty())
+}
+fn merge(base: &str, ours: &str, theirs: &str, out: &str) -> io::Result<bool> {
+    let m= scenario_diff::merge( &read_file(Path::new(base))?, &read_file(Path::new(ours))?,
+                                 &read_file(Path::new(theirs))? );
+    write_file(Path::new(out), &m.file)?;
+    for (from, to) in m.renumbered.iter() {
+        println!("id:{} of theirs is renumbered to id:{}", from, to); }
+    for c in m.conflicts.iter() {
+        println!("conflict {}", c); }
+    Ok(m.conflicts.is_empty())
+}
+// run /////////////////////////////////////////////////////
+/// runs the subcommand in `args` (without the program name),
+/// None when there is no subcommand
+pub fn run(args: &[String]) -> Option<ExitCode> {
+    let (cmd, rest)= args.split_first()?;
+    let result= match (cmd.as_str(), rest) {
+        ("-h" | "--help" | "help", _)       => { println!("{}", USAGE); return Some(ExitCode::SUCCESS) },
+        ("validate", [_, ..])               => validate_files(rest),
+        ("dump",     [p])                   => dump(p),
+        ("convert",  [i, o])                => convert(i, o),
+        ("stats",    [_, ..])               => stats(rest),
+        ("render",   [p, d])                => render_pngs(p, d, Canvas::DEFAULT),
+        ("render",   [p, d, s])             => match parse_canvas(s) {
+            Some(c) => render_pngs(p, d, c),
+            None    => { eprintln!("bad size: {} (e.g. 1920x1080)", s); return Some(ExitCode::FAILURE) },
+        },
+        ("diff",     [a, b])                => diff(a, b),
+        ("merge",    [b, o, t, out])        => merge(b, o, t, out),
+        (c, _) if COMMANDS.contains(&c)     => { eprintln!("{}", USAGE); return Some(ExitCode::FAILURE) },
+        _                                   => return None,
+    };
+    match result {
+        Ok(true)  => Some(ExitCode::SUCCESS),
+        Ok(false) => Some(ExitCode::FAILURE),
+        Err(e)    => { eprintln!("{}: {}", cmd, e); Some(ExitCode::FAILURE) },
+    }
+}
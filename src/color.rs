@@ -0,0 +1,212 @@
+//! Color: 8 bit r, g, b and a
+//!
+//! alpha model:
+//! - `a` is the straight (not premultiplied) alpha of the color, 255 is opaque
+//! - Mat.a / Ovimg.a is the opacity of the element, the drawn alpha is col.a * a / 255
+//!   (render.rs), an ovimg has no color and uses its opacity only
+//!
+//! text form (the files, the markup, the labels): `#rrggbb`, `#rrggbbaa` if a != 255.
+//! parse() accepts `#rgb`, `#rgba`, `#rrggbb`, `#rrggbbaa` and the CSS color names.
+//! the old form `{"r": .., "g": .., "b": ..}` (optional "a") of the files is still read,
+//! a component out of 0..=255 is an error.
+
+use std::fmt;
+use std::str::FromStr;
+use serde::{Serialize, Deserialize};
+use gtk::gdk;
+
+// Color ///////////////////////////////////////////////////
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "ColorRepr", into = "String")]
+pub struct Color {
+    pub r : u8,
+    pub g : u8,
+    pub b : u8,
+    pub a : u8,
+}
+// ColorError //////////////////////////////////////////////
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorError(pub String);
+impl fmt::Display for ColorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "bad color: {}", self.0)
+    }
+}
+impl Color {
+    pub const BLACK: Color = Color::rgb(0, 0, 0);
+    pub const WHITE: Color = Color::rgb(255, 255, 255);
+
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Color {
+        Color{ r, g, b, a: 255 }
+    }
+    pub const fn rgba(r: u8, g: u8, b: u8, a: u8) -> Color {
+        Color{ r, g, b, a }
+    }
+    // parse ///////////////////////////////////////////////
+    /// "#f00", "#f008", "#ff0000", "#ff000080" or a CSS color name
+    pub fn parse(s: &str) -> Result<Color, ColorError> {
+        let bad= || ColorError(s.to_string());
+        let s= s.trim();
+        let Some(hex) = s.strip_prefix('#') else {
+            return CSS_NAMES.iter()
+                .find(|(n, _)| n.eq_ignore_ascii_case(s))
+                .map(|(_, c)| *c)
+                .ok_or_else(bad);
+        };
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(bad()); }
+        let digit= |i: usize, n: usize| u8::from_str_radix(&hex[i..i + n], 16).unwrap_or(0);
+        match hex.len() {
+            3 | 4 => {
+                let d= |i: usize| digit(i, 1) * 17;
+                Ok( Color{ r: d(0), g: d(1), b: d(2), a: if hex.len() == 4 { d(3) } else { 255 } } )
+            },
+            6 | 8 => {
+                let d= |i: usize| digit(i * 2, 2);
+                Ok( Color{ r: d(0), g: d(1), b: d(2), a: if hex.len() == 8 { d(3) } else { 255 } } )
+            },
+            _ => Err(bad()),
+        }
+    }
+    // alpha / luminance ///////////////////////////////////
+    /// a in 0.0..=1.0
+    pub fn alpha(&self) -> f64 {
+        self.a as f64 / 255.0
+    }
+    /// (r, g, b) in 0.0..=1.0
+    pub fn to_f64(self) -> (f64, f64, f64) {
+        (self.r as f64 / 255.0, self.g as f64 / 255.0, self.b as f64 / 255.0)
+    }
+    /// 0..=255, the alpha is ignored
+    pub fn luminance(&self) -> f64 {
+        0.299 * self.r as f64 + 0.587 * self.g as f64 + 0.114 * self.b as f64
+    }
+    // gdk::RGBA ///////////////////////////////////////////
+    pub fn to_rgba(self) -> gdk::RGBA {
+        let (r, g, b)= self.to_f64();
+        gdk::RGBA::new(r as f32, g as f32, b as f32, self.alpha() as f32)
+    }
+    /// the components are rounded to 8 bit
+    pub fn from_rgba(c: &gdk::RGBA) -> Color {
+        let q= |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+        Color{ r: q(c.red()), g: q(c.green()), b: q(c.blue()), a: q(c.alpha()) }
+    }
+}
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)?;
+        if self.a != 255 {
+            write!(f, "{:02x}", self.a)?; }
+        Ok(())
+    }
+}
+impl FromStr for Color {
+    type Err = ColorError;
+    fn from_str(s: &str) -> Result<Color, ColorError> {
+        Color::parse(s)
+    }
+}
+// serde ///////////////////////////////////////////////////
+/// the forms in the files
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ColorRepr {
+    Text(String),
+    Components{ r: u32, g: u32, b: u32, a: Option<u32> }, // the old form
+}
+impl TryFrom<ColorRepr> for Color {
+    type Error = ColorError;
+    fn try_from(c: ColorRepr) -> Result<Color, ColorError> {
+        match c {
+            ColorRepr::Text(s) => Color::parse(&s),
+            ColorRepr::Components{ r, g, b, a } => {
+                let c= |v: u32| u8::try_from(v).map_err(|_| ColorError(format!("{} is out of 0..=255", v)));
+                Ok( Color{ r: c(r)?, g: c(g)?, b: c(b)?, a: c(a.unwrap_or(255))? } )
+            },
+        }
+    }
+}
+impl From<Color> for String {
+    fn from(c: Color) -> String {
+        c.to_string()
+    }
+}
+// CSS_NAMES ///////////////////////////////////////////////
+/// the named colors of CSS Color Module Level 4
+const CSS_NAMES: [(&str, Color); 149] = [
+    ("aliceblue",            Color::rgb(240, 248, 255)), ("antiquewhite",         Color::rgb(250, 235, 215)),
+    ("aqua",                 Color::rgb(  0, 255, 255)), ("aquamarine",           Color::rgb(127, 255, 212)),
+    ("azure",                Color::rgb(240, 255, 255)), ("beige",                Color::rgb(245, 245, 220)),
+    ("bisque",               Color::rgb(255, 228, 196)), ("black",                Color::rgb(  0,   0,   0)),
+    ("blanchedalmond",       Color::rgb(255, 235, 205)), ("blue",                 Color::rgb(  0,   0, 255)),
+    ("blueviolet",           Color::rgb(138,  43, 226)), ("brown",                Color::rgb(165,  42,  42)),
+    ("burlywood",            Color::rgb(222, 184, 135)), ("cadetblue",            Color::rgb( 95, 158, 160)),
+    ("chartreuse",           Color::rgb(127, 255,   0)), ("chocolate",            Color::rgb(210, 105,  30)),
+    ("coral",                Color::rgb(255, 127,  80)), ("cornflowerblue",       Color::rgb(100, 149, 237)),
+    ("cornsilk",             Color::rgb(255, 248, 220)), ("crimson",              Color::rgb(220,  20,  60)),
+    ("cyan",                 Color::rgb(  0, 255, 255)), ("darkblue",             Color::rgb(  0,   0, 139)),
+    ("darkcyan",             Color::rgb(  0, 139, 139)), ("darkgoldenrod",        Color::rgb(184, 134,  11)),
+    ("darkgray",             Color::rgb(169, 169, 169)), ("darkgreen",            Color::rgb(  0, 100,   0)),
+    ("darkgrey",             Color::rgb(169, 169, 169)), ("darkkhaki",            Color::rgb(189, 183, 107)),
+    ("darkmagenta",          Color::rgb(139,   0, 139)), ("darkolivegreen",       Color::rgb( 85, 107,  47)),
+    ("darkorange",           Color::rgb(255, 140,   0)), ("darkorchid",           Color::rgb(153,  50, 204)),
+    ("darkred",              Color::rgb(139,   0,   0)), ("darksalmon",           Color::rgb(233, 150, 122)),
+    ("darkseagreen",         Color::rgb(143, 188, 143)), ("darkslateblue",        Color::rgb( 72,  61, 139)),
+    ("darkslategray",        Color::rgb( 47,  79,  79)), ("darkslategrey",        Color::rgb( 47,  79,  79)),
+    ("darkturquoise",        Color::rgb(  0, 206, 209)), ("darkviolet",           Color::rgb(148,   0, 211)),
+    ("deeppink",             Color::rgb(255,  20, 147)), ("deepskyblue",          Color::rgb(  0, 191, 255)),
+    ("dimgray",              Color::rgb(105, 105, 105)), ("dimgrey",              Color::rgb(105, 105, 105)),
+    ("dodgerblue",           Color::rgb( 30, 144, 255)), ("firebrick",            Color::rgb(178,  34,  34)),
+    ("floralwhite",          Color::rgb(255, 250, 240)), ("forestgreen",          Color::rgb( 34, 139,  34)),
+    ("fuchsia",              Color::rgb(255,   0, 255)), ("gainsboro",            Color::rgb(220, 220, 220)),
+    ("ghostwhite",           Color::rgb(248, 248, 255)), ("gold",                 Color::rgb(255, 215,   0)),
+    ("goldenrod",            Color::rgb(218, 165,  32)), ("gray",                 Color::rgb(128, 128, 128)),
+    ("green",                Color::rgb(  0, 128,   0)), ("greenyellow",          Color::rgb(173, 255,  47)),
+    ("grey",                 Color::rgb(128, 128, 128)), ("honeydew",             Color::rgb(240, 255, 240)),
+    ("hotpink",              Color::rgb(255, 105, 180)), ("indianred",            Color::rgb(205,  92,  92)),
+    ("indigo",               Color::rgb( 75,   0, 130)), ("ivory",                Color::rgb(255, 255, 240)),
+    ("khaki",                Color::rgb(240, 230, 140)), ("lavender",             Color::rgb(230, 230, 250)),
+    ("lavenderblush",        Color::rgb(255, 240, 245)), ("lawngreen",            Color::rgb(124, 252,   0)),
+    ("lemonchiffon",         Color::rgb(255, 250, 205)), ("lightblue",            Color::rgb(173, 216, 230)),
+    ("lightcoral",           Color::rgb(240, 128, 128)), ("lightcyan",            Color::rgb(224, 255, 255)),
+    ("lightgoldenrodyellow", Color::rgb(250, 250, 210)), ("lightgray",            Color::rgb(211, 211, 211)),
+    ("lightgreen",           Color::rgb(144, 238, 144)), ("lightgrey",            Color::rgb(211, 211, 211)),
+    ("lightpink",            Color::rgb(255, 182, 193)), ("lightsalmon",          Color::rgb(255, 160, 122)),
+    ("lightseagreen",        Color::rgb( 32, 178, 170)), ("lightskyblue",         Color::rgb(135, 206, 250)),
+    ("lightslategray",       Color::rgb(119, 136, 153)), ("lightslategrey",       Color::rgb(119, 136, 153)),
+    ("lightsteelblue",       Color::rgb(176, 196, 222)), ("lightyellow",          Color::rgb(255, 255, 224)),
+    ("lime",                 Color::rgb(  0, 255,   0)), ("limegreen",            Color::rgb( 50, 205,  50)),
+    ("linen",                Color::rgb(250, 240, 230)), ("magenta",              Color::rgb(255,   0, 255)),
+    ("maroon",               Color::rgb(128,   0,   0)), ("mediumaquamarine",     Color::rgb(102, 205, 170)),
+    ("mediumblue",           Color::rgb(  0,   0, 205)), ("mediumorchid",         Color::rgb(186,  85, 211)),
+    ("mediumpurple",         Color::rgb(147, 112, 219)), ("mediumseagreen",       Color::rgb( 60, 179, 113)),
+    ("mediumslateblue",      Color::rgb(123, 104, 238)), ("mediumspringgreen",    Color::rgb(  0, 250, 154)),
+    ("mediumturquoise",      Color::rgb( 72, 209, 204)), ("mediumvioletred",      Color::rgb(199,  21, 133)),
+    ("midnightblue",         Color::rgb( 25,  25, 112)), ("mintcream",            Color::rgb(245, 255, 250)),
+    ("mistyrose",            Color::rgb(255, 228, 225)), ("moccasin",             Color::rgb(255, 228, 181)),
+    ("navajowhite",          Color::rgb(255, 222, 173)), ("navy",                 Color::rgb(  0,   0, 128)),
+    ("oldlace",              Color::rgb(253, 245, 230)), ("olive",                Color::rgb(128, 128,   0)),
+    ("olivedrab",            Color::rgb(107, 142,  35)), ("orange",               Color::rgb(255, 165,   0)),
+    ("orangered",            Color::rgb(255,  69,   0)), ("orchid",               Color::rgb(218, 112, 214)),
+    ("palegoldenrod",        Color::rgb(238, 232, 170)), ("palegreen",            Color::rgb(152, 251, 152)),
+    ("paleturquoise",        Color::rgb(175, 238, 238)), ("palevioletred",        Color::rgb(219, 112, 147)),
+    ("papayawhip",           Color::rgb(255, 239, 213)), ("peachpuff",            Color::rgb(255, 218, 185)),
+    ("peru",                 Color::rgb(205, 133,  63)), ("pink",                 Color::rgb(255, 192, 203)),
+    ("plum",                 Color::rgb(221, 160, 221)), ("powderblue",           Color::rgb(176, 224, 230)),
+    ("purple",               Color::rgb(128,   0, 128)), ("rebeccapurple",        Color::rgb(102,  51, 153)),
+    ("red",                  Color::rgb(255,   0,   0)), ("rosybrown",            Color::rgb(188, 143, 143)),
+    ("royalblue",            Color::rgb( 65, 105, 225)), ("saddlebrown",          Color::rgb(139,  69,  19)),
+    ("salmon",               Color::rgb(250, 128, 114)), ("sandybrown",           Color::rgb(244, 164,  96)),
+    ("seagreen",             Color::rgb( 46, 139,  87)), ("seashell",             Color::rgb(255, 245, 238)),
+    ("sienna",               Color::rgb(160,  82,  45)), ("silver",               Color::rgb(192, 192, 192)),
+    ("skyblue",              Color::rgb(135, 206, 235)), ("slateblue",            Color::rgb(106,  90, 205)),
+    ("slategray",            Color::rgb(112, 128, 144)), ("slategrey",            Color::rgb(112, 128, 144)),
+    ("snow",                 Color::rgb(255, 250, 250)), ("springgreen",          Color::rgb(  0, 255, 127)),
+    ("steelblue",            Color::rgb( 70, 130, 180)), ("tan",                  Color::rgb(210, 180, 140)),
+    ("teal",                 Color::rgb(  0, 128, 128)), ("thistle",              Color::rgb(216, 191, 216)),
+    ("tomato",               Color::rgb(255,  99,  71)), ("transparent",          Color::rgba(0, 0, 0, 0)),
+    ("turquoise",            Color::rgb( 64, 224, 208)), ("violet",               Color::rgb(238, 130, 238)),
+    ("wheat",                Color::rgb(245, 222, 179)), ("white",                Color::rgb(255, 255, 255)),
+    ("whitesmoke",           Color::rgb(245, 245, 245)), ("yellow",               Color::rgb(255, 255,   0)),
+    ("yellowgreen",          Color::rgb(154, 205,  50)),
+];
@@ -0,0 +1,127 @@
+//! consistency of the ListStores (the rows) with the ScenarioNode links
+//!
+//! each ListStore of the TreeListModel mirrors the children of a node (the root store: the root level),
+//! ScenarioNodeObject.seq is kept by hand (adj_seq). check() walks the stores which are created
+//! (a collapsed row has none) along the neighbor chains and reports
+//!
+//!   - a row which is not the node at that index of the chain (order), a missing row, an extra row
+//!   - a seq which is not the index of the row
+//!   - a broken link: a parent which does not hold the child, a neighbor whose previous is another node,
+//!     a chain which loops
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fmt::Write;
+use std::rc::Rc;
+use gtk::gio;
+use gtk::prelude::*;
+use gtk::{TreeListModel, TreeListRow};
+
+use crate::scenario_node::{ScenarioNode, BranchType};
+use crate::scenario_node_object::{ScenarioNodeObject, first_root, root_store, row_at_path};
+use crate::scenario_query;
+
+// Mismatch ////////////////////////////////////////////////
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    pub path: Vec<usize>, // the index in the store, or in the chain for a link
+    pub id  : i32,
+    pub msg : String,
+}
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} id:{}: {}", self.path, self.id, self.msg)
+    }
+}
+fn mismatch(path: &[usize], id: i32, msg: String) -> Mismatch {
+    Mismatch{ path: path.to_vec(), id, msg }
+}
+// chain ///////////////////////////////////////////////////
+/// `first` and its neighbors, Err when the chain loops
+fn chain(first: Option<Rc<ScenarioNode>>) -> Result<Vec<Rc<ScenarioNode>>, Vec<Rc<ScenarioNode>>> {
+    let mut seen= HashSet::new();
+    let mut nodes= Vec::new();
+    let mut n= first;
+    while let Some(x) = n {
+        if !seen.insert(Rc::as_ptr(&x)) {
+            return Err(nodes); }
+        n= x.get_neighbor();
+        nodes.push(x);
+    }
+    Ok(nodes)
+}
+// check_links /////////////////////////////////////////////
+fn check_links(parent: Option<&Rc<ScenarioNode>>, nodes: &[Rc<ScenarioNode>], path: &[usize],
+               found: &mut Vec<Mismatch>) {
+    for (i, n) in nodes.iter().enumerate() {
+        let p= [path, &[i]].concat();
+        let same_parent= match (n.get_parent_node(), parent) {
+            (Some(a), Some(b)) => Rc::ptr_eq(&a, b),
+            (None, None)       => true,
+            _                  => false,
+        };
+        if !same_parent {
+            found.push( mismatch(&p, n.id.get(), String::from("the parent link is not the holder of the chain")) ); }
+        let prev_ok= match i {
+            0 => n.get_bt() == BranchType::Child,
+            _ => n.get_parent().is_some_and(|prev| Rc::ptr_eq(&prev, &nodes[i - 1])),
+        };
+        if !prev_ok {
+            found.push( mismatch(&p, n.id.get(), String::from("the previous link is not the previous in the chain")) ); }
+    }
+}
+// check_store /////////////////////////////////////////////
+/// `store` holds the rows of `nodes`, `row_of` is the row of an index
+fn check_store(store: &gio::ListStore, parent: Option<&Rc<ScenarioNode>>, first: Option<Rc<ScenarioNode>>,
+               path: &[usize], row_of: &dyn Fn(u32) -> Option<TreeListRow>, found: &mut Vec<Mismatch>) {
+    let nodes= chain(first).unwrap_or_else(|nodes| {
+        let id= nodes.last().map_or(0, |n| n.id.get());
+        found.push( mismatch(path, id, String::from("the neighbor chain loops")) );
+        nodes
+    });
+    check_links(parent, &nodes, path, found);
+
+    let n_items= store.n_items() as usize;
+    for i in 0..n_items.max(nodes.len()) {
+        let p= [path, &[i]].concat();
+        let sno= store.item(i as u32).and_downcast::<ScenarioNodeObject>();
+        match (sno, nodes.get(i)) {
+            (Some(s), Some(n)) => {
+                if s.get_seq() != i as i32 {
+                    found.push( mismatch(&p, s.get_id(), format!("seq is {}", s.get_seq())) ); }
+                if !Rc::ptr_eq(&s.get_node(), n) {
+                    found.push( mismatch(&p, s.get_id(), format!("the row is not id:{} of the chain", n.id.get())) );
+                    continue;
+                }
+                let Some(row) = row_of(i as u32) else { continue };
+                if let Some(children) = row.children().and_downcast::<gio::ListStore>() {
+                    check_store(&children, Some(n), n.get_child(), &p, &|j| row.child_row(j), found);
+                }
+            },
+            (Some(s), None) => found.push( mismatch(&p, s.get_id(), String::from("the row is not in the chain")) ),
+            (None, Some(n)) => found.push( mismatch(&p, n.id.get(), String::from("no row")) ),
+            (None, None)    => (),
+        }
+    }
+}
+// check ///////////////////////////////////////////////////
+pub fn check(tlm: &TreeListModel) -> Vec<Mismatch> {
+    let mut found= Vec::new();
+    check_store(&root_store(tlm), None, first_root(tlm), &[], &|i| tlm.child_row(i), &mut found);
+    found
+}
+// dump ////////////////////////////////////////////////////
+/// each node in pre-order with its row: "[path] node | row seq:n"
+pub fn dump(tlm: &TreeListModel) -> String {
+    let mut s= String::new();
+    for n in scenario_query::pre_order_forest(first_root(tlm)) {
+        let path= n.index_path();
+        let row= match row_at_path(tlm, &path).and_then(|r| r.item().and_downcast::<ScenarioNodeObject>()) {
+            Some(o) if Rc::ptr_eq(&o.get_node(), &n) => format!("row seq:{}", o.get_seq()),
+            Some(o)                                  => format!("row of id:{}", o.get_id()),
+            None                                     => String::from("no row"),
+        };
+        let _= writeln!(s, "{}{:?} {} | {}", "  ".repeat(path.len() - 1), path, n, row);
+    }
+    s
+}
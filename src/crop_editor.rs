@@ -0,0 +1,361 @@
+//! Crop editor: Scene.crop on the bgimg of the selected scene
+//!
+//! the image is scaled to fit the area, the outside of the crop is darkened.
+//! drag a corner handle to resize, the inside to move, the outside to draw a new crop.
+//! the spin buttons are bound to crop.pos / crop.dim (image pixels, a length in percent keeps its mode).
+//! with an aspect preset the ratio is kept while resizing, "canvas" is the output resolution.
+//! a drag is one undoable step, consecutive edits by the spin buttons are coalesced.
+
+use std::cell::{Cell, RefCell};
+use std::path::PathBuf;
+use std::rc::{Rc, Weak};
+use gtk::prelude::*;
+use gtk::gdk_pixbuf::Pixbuf;
+use gtk::{Box, Button, DrawingArea, DropDown, GestureDrag, Label, Orientation, SingleSelection, SpinButton};
+
+use crate::scenario_node::{ScenarioNode, Item, Scene, CropInfo, Position, Dimension, Length};
+use crate::operation_history::{OperationHistory, OperationHistoryItem};
+use crate::scenario_node_object::selected_node;
+use crate::render::{CANVAS_W, CANVAS_H};
+
+const HANDLE: f64 = 8.0; // half size of a handle in the widget coordinates
+/// name, ratio (w, h)
+const ASPECT_PRESETS: [(&str, Option<(i32, i32)>); 5] = [
+    ("free",              None),
+    ("canvas",            Some((CANVAS_W, CANVAS_H))),
+    ("canvas (portrait)", Some((CANVAS_H, CANVAS_W))),
+    ("4:3",               Some((4, 3))),
+    ("1:1",               Some((1, 1))),
+];
+
+// Rect ////////////////////////////////////////////////////
+/// a rectangle in the image coordinates
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Rect {
+    x: f64,
+    y: f64,
+    w: f64,
+    h: f64,
+}
+impl Rect {
+    fn from_crop(c: &CropInfo, iw: f64, ih: f64) -> Rect {
+        let ((x, y), (w, h))= (c.pos.resolve(iw, ih), c.dim.resolve(iw, ih));
+        Rect{ x, y, w, h }
+    }
+    /// the crop in the image of (iw, ih), in the modes (pixel / percent) of `like`
+    fn to_crop(self, like: Option<&CropInfo>, iw: f64, ih: f64) -> CropInfo {
+        let like= like.cloned().unwrap_or(CropInfo{ pos: Position::px(0.0, 0.0), dim: Dimension::px(0.0, 0.0) });
+        let l= |l: Length, v: f64, size: f64| l.with_resolved(v, size).rounded();
+        CropInfo{
+            pos: Position { x: l(like.pos.x, self.x.round().max(0.0), iw), y: l(like.pos.y, self.y.round().max(0.0), ih) },
+            dim: Dimension{ w: l(like.dim.w, self.w.round().max(1.0), iw), h: l(like.dim.h, self.h.round().max(1.0), ih) },
+        }
+    }
+    /// the rectangle between the fixed point (ax, ay) and the moving point (px, py),
+    /// in the image of (iw, ih), with `ratio` w / h if given
+    fn from_points(ax: f64, ay: f64, px: f64, py: f64, iw: f64, ih: f64, ratio: Option<f64>) -> Rect {
+        let (px, py)= (px.clamp(0.0, iw), py.clamp(0.0, ih));
+        let (sx, sy)= (if px >= ax { 1.0 } else { -1.0 }, if py >= ay { 1.0 } else { -1.0 });
+        let (mut w, mut h)= ((px - ax).abs().max(1.0), (py - ay).abs().max(1.0));
+        if let Some(r) = ratio {
+            let h_max= if sy > 0.0 { ih - ay } else { ay };
+            h= w / r;
+            if h > h_max {
+                h= h_max;
+                w= h * r;
+            }
+        }
+        Rect{ x: if sx > 0.0 { ax } else { ax - w }, y: if sy > 0.0 { ay } else { ay - h }, w, h }
+    }
+}
+// Grip ////////////////////////////////////////////////////
+#[derive(Debug, Clone, Copy)]
+enum Grip {
+    Move,
+    Resize{ ax: f64, ay: f64, px: f64, py: f64 }, // fixed corner, grabbed point
+}
+struct Drag {
+    grip : Grip,
+    start: Rect,
+    old  : Item, // the value before the drag
+}
+// CropEditor //////////////////////////////////////////////
+struct CropEditor {
+    root     : PathBuf, // asset root
+    history  : Weak<OperationHistory>,
+    selection: SingleSelection,
+    area     : DrawingArea,
+    spins    : [SpinButton; 4], // x, y, w, h
+    preset   : DropDown,
+    info     : Label,
+    image    : RefCell<Option<(String, Option<Pixbuf>)>>, // the last loaded bgimg
+    drag     : RefCell<Option<Drag>>,
+    updating : Cell<bool>, // true while the spin buttons are set from the node
+}
+impl CropEditor {
+    // selected_scene //////////////////////////////////////
+    fn selected_scene(&self) -> Option<(Rc<ScenarioNode>, Scene)> {
+        let node= selected_node(&self.selection)?;
+        let s= match &*node.value.borrow() {
+            Item::Scene(s) => s.clone(),
+            _              => return None,
+        };
+        Some( (node, s) )
+    }
+    /// the bgimg of the scene, loaded once for each path
+    fn pixbuf(&self, s: &Scene) -> Option<Pixbuf> {
+        let path= s.bgimg.clone()?;
+        let mut image= self.image.borrow_mut();
+        if image.as_ref().map(|(p, _)| p) != Some(&path) {
+            *image= Some( (path.clone(), Pixbuf::from_file(self.root.join(&path)).ok()) ); }
+        image.as_ref()?.1.clone()
+    }
+    fn ratio(&self) -> Option<f64> {
+        ASPECT_PRESETS.get(self.preset.selected() as usize)
+            .and_then(|(_, r)| *r)
+            .map(|(w, h)| w as f64 / h as f64)
+    }
+    /// the crop of `s`, the whole image without crop
+    fn rect_of(s: &Scene, iw: f64, ih: f64) -> Rect {
+        s.crop.as_ref().map_or(Rect{ x: 0.0, y: 0.0, w: iw, h: ih }, |c| Rect::from_crop(c, iw, ih))
+    }
+    /// (scale, offset x, offset y) from the image to the widget
+    fn transform(&self, iw: f64, ih: f64) -> (f64, f64, f64) {
+        let (aw, ah)= (self.area.width() as f64, self.area.height() as f64);
+        let scale= (aw / iw).min(ah / ih);
+        (scale, (aw - iw * scale) / 2.0, (ah - ih * scale) / 2.0)
+    }
+    // set_crop / commit ///////////////////////////////////
+    /// set the crop without the history, while dragging
+    fn set_crop(node: &ScenarioNode, crop: Option<CropInfo>) {
+        let mut v= node.value.borrow().clone();
+        if let Item::Scene(s) = &mut v {
+            s.crop= crop; }
+        node.set_value(v);
+    }
+    /// push the change from `old` to the current value
+    fn commit(&self, node: &ScenarioNode, old: Item) {
+        let new= node.value.borrow().clone();
+        let changed= match (&old, &new) {
+            (Item::Scene(a), Item::Scene(b)) => a.crop != b.crop,
+            _                                => false,
+        };
+        if let (true, Some(h)) = (changed, self.history.upgrade()) {
+            h.push( OperationHistoryItem::new_set_value(node, "crop", old, new) ); }
+    }
+    /// set and commit
+    fn edit(&self, crop: Option<CropInfo>) {
+        let Some((node, _)) = self.selected_scene() else { return };
+        let old= node.value.borrow().clone();
+        CropEditor::set_crop(&node, crop);
+        self.commit(&node, old);
+        self.load();
+    }
+    // load ////////////////////////////////////////////////
+    /// show the crop of the selected scene
+    fn load(&self) {
+        let scene= self.selected_scene();
+        let pixbuf= scene.as_ref().and_then(|(_, s)| self.pixbuf(s));
+        let (iw, ih)= pixbuf.as_ref().map_or((0.0, 0.0), |p| (p.width() as f64, p.height() as f64));
+        for s in self.spins.iter() {
+            s.set_sensitive(pixbuf.is_some()); }
+
+        self.updating.set(true);
+        if let (Some((_, s)), Some(_)) = (&scene, &pixbuf) {
+            let r= CropEditor::rect_of(s, iw, ih);
+            for (spin, (v, max)) in self.spins.iter().zip([(r.x, iw), (r.y, ih), (r.w, iw), (r.h, ih)]) {
+                spin.set_range(0.0, max);
+                spin.set_value(v);
+            }
+        }
+        self.updating.set(false);
+
+        self.info.set_label( &match (&scene, &pixbuf) {
+            (None, _)                => String::from("select a scene"),
+            (Some((_, s)), None)     => format!("no image: {}", s.bgimg.as_deref().unwrap_or("(none)")),
+            (Some((_, s)), Some(_))  => format!("{} x {}{}", iw, ih, if s.crop.is_none() { ", no crop" } else { "" }),
+        } );
+        self.area.queue_draw();
+    }
+    // draw ////////////////////////////////////////////////
+    fn draw(&self, cr: &gtk::cairo::Context) {
+        cr.set_source_rgb(0.3, 0.3, 0.3);
+        cr.paint().ok();
+        let Some((_, s)) = self.selected_scene() else { return };
+        let Some(p) = self.pixbuf(&s) else { return };
+        let (iw, ih)= (p.width() as f64, p.height() as f64);
+        let (scale, ox, oy)= self.transform(iw, ih);
+
+        cr.save().ok();
+        cr.translate(ox, oy);
+        cr.scale(scale, scale);
+        cr.set_source_pixbuf(&p, 0.0, 0.0);
+        cr.paint().ok();
+        cr.restore().ok();
+
+        // darken the outside of the crop
+        let r= CropEditor::rect_of(&s, iw, ih);
+        let (x, y, w, h)= (ox + r.x * scale, oy + r.y * scale, r.w * scale, r.h * scale);
+        cr.set_fill_rule(gtk::cairo::FillRule::EvenOdd);
+        cr.rectangle(ox, oy, iw * scale, ih * scale);
+        cr.rectangle(x, y, w, h);
+        cr.set_source_rgba(0.0, 0.0, 0.0, 0.5);
+        cr.fill().ok();
+
+        cr.set_source_rgb(1.0, 0.6, 0.0);
+        cr.set_line_width(1.0);
+        cr.rectangle(x, y, w, h);
+        cr.stroke().ok();
+        for (hx, hy) in [(x, y), (x + w, y), (x, y + h), (x + w, y + h)] {
+            cr.rectangle(hx - HANDLE / 2.0, hy - HANDLE / 2.0, HANDLE, HANDLE); }
+        cr.fill().ok();
+    }
+    // drag ////////////////////////////////////////////////
+    fn drag_begin(&self, wx: f64, wy: f64) {
+        let Some((node, s)) = self.selected_scene() else { return };
+        let Some(p) = self.pixbuf(&s) else { return };
+        let (iw, ih)= (p.width() as f64, p.height() as f64);
+        let (scale, ox, oy)= self.transform(iw, ih);
+        let (px, py)= ((wx - ox) / scale, (wy - oy) / scale); // in the image
+        let r= CropEditor::rect_of(&s, iw, ih);
+
+        let near= |a: f64, b: f64| (a - b).abs() * scale <= HANDLE;
+        let corner= [(r.x, r.y), (r.x + r.w, r.y), (r.x, r.y + r.h), (r.x + r.w, r.y + r.h)].into_iter()
+            .find(|(cx, cy)| near(*cx, px) && near(*cy, py));
+        let grip= match corner {
+            Some((cx, cy)) => Grip::Resize{ ax: 2.0 * r.x + r.w - cx, ay: 2.0 * r.y + r.h - cy, px: cx, py: cy },
+            None if (r.x..=r.x + r.w).contains(&px) && (r.y..=r.y + r.h).contains(&py) => Grip::Move,
+            None => Grip::Resize{ ax: px.clamp(0.0, iw), ay: py.clamp(0.0, ih), px, py },
+        };
+        *self.drag.borrow_mut()= Some( Drag{ grip, start: r, old: node.value.borrow().clone() } );
+    }
+    fn drag_update(&self, dx: f64, dy: f64) {
+        let Some((node, s)) = self.selected_scene() else { return };
+        let Some(p) = self.pixbuf(&s) else { return };
+        let (iw, ih)= (p.width() as f64, p.height() as f64);
+        let (scale, _, _)= self.transform(iw, ih);
+        let (dx, dy)= (dx / scale, dy / scale);
+
+        let r= match self.drag.borrow().as_ref() {
+            None => return,
+            Some(Drag{ grip: Grip::Move, start, .. }) => Rect{
+                x: (start.x + dx).clamp(0.0, (iw - start.w).max(0.0)),
+                y: (start.y + dy).clamp(0.0, (ih - start.h).max(0.0)),
+                ..*start
+            },
+            Some(Drag{ grip: Grip::Resize{ ax, ay, px, py }, .. }) =>
+                Rect::from_points(*ax, *ay, px + dx, py + dy, iw, ih, self.ratio()),
+        };
+        CropEditor::set_crop(&node, Some(r.to_crop(s.crop.as_ref(), iw, ih)));
+        self.load();
+    }
+    fn drag_end(&self) {
+        let Some(d) = self.drag.borrow_mut().take() else { return };
+        if let Some((node, _)) = self.selected_scene() {
+            self.commit(&node, d.old); }
+    }
+    // spin / preset ///////////////////////////////////////
+    /// a spin button is changed, `i`: 0 x, 1 y, 2 w, 3 h
+    fn spin_changed(&self, i: usize) {
+        if self.updating.get() {
+            return; }
+        let Some((_, s)) = self.selected_scene() else { return };
+        let Some(p) = self.pixbuf(&s) else { return };
+        let (iw, ih)= (p.width() as f64, p.height() as f64);
+        let v: Vec<f64>= self.spins.iter().map(|s| s.value()).collect();
+        let mut r= Rect{ x: v[0], y: v[1], w: v[2].max(1.0), h: v[3].max(1.0) };
+        if let Some(ratio) = self.ratio() {
+            if i == 3 { r.w= r.h * ratio; } else { r.h= r.w / ratio; }
+        }
+        r= Rect::from_points(r.x.min(iw - 1.0), r.y.min(ih - 1.0), r.x + r.w, r.y + r.h, iw, ih, self.ratio());
+        self.edit( Some(r.to_crop(s.crop.as_ref(), iw, ih)) );
+    }
+    /// apply the ratio of the preset to the current crop
+    fn preset_changed(&self) {
+        let (Some(ratio), Some((_, s))) = (self.ratio(), self.selected_scene()) else { return };
+        let Some(p) = self.pixbuf(&s) else { return };
+        let (iw, ih)= (p.width() as f64, p.height() as f64);
+        let r= CropEditor::rect_of(&s, iw, ih);
+        let r= Rect::from_points(r.x, r.y, r.x + r.w, r.y + r.h, iw, ih, Some(ratio));
+        self.edit( Some(r.to_crop(s.crop.as_ref(), iw, ih)) );
+    }
+}
+// build_crop_editor ///////////////////////////////////////
+pub fn build_crop_editor(history: Rc<OperationHistory>, selection: SingleSelection, root: PathBuf) -> Box {
+    let spin= || SpinButton::with_range(0.0, 1.0, 1.0);
+    let names: Vec<&str>= ASPECT_PRESETS.iter().map(|(n, _)| *n).collect();
+    let editor= Rc::new( CropEditor{
+        root,
+        history  : Rc::downgrade(&history),
+        selection: selection.clone(),
+        area     : DrawingArea::builder().content_width(320).content_height(180).vexpand(true).build(),
+        spins    : [spin(), spin(), spin(), spin()],
+        preset   : DropDown::from_strings(&names),
+        info     : Label::builder().xalign(0.0).build(),
+        image    : RefCell::new(None),
+        drag     : RefCell::new(None),
+        updating : Cell::new(false),
+    } );
+
+    let e= Rc::downgrade(&editor);
+    editor.area.set_draw_func(move |_, cr, _, _| {
+        if let Some(e) = e.upgrade() { e.draw(cr); }
+    });
+    let gesture= GestureDrag::new();
+    let e= Rc::downgrade(&editor);
+    gesture.connect_drag_begin(move |_, x, y| {
+        if let Some(e) = e.upgrade() { e.drag_begin(x, y); }
+    });
+    let e= Rc::downgrade(&editor);
+    gesture.connect_drag_update(move |_, dx, dy| {
+        if let Some(e) = e.upgrade() { e.drag_update(dx, dy); }
+    });
+    let e= Rc::downgrade(&editor);
+    gesture.connect_drag_end(move |_, _, _| {
+        if let Some(e) = e.upgrade() { e.drag_end(); }
+    });
+    editor.area.add_controller(gesture);
+
+    for (i, s) in editor.spins.iter().enumerate() {
+        let e= Rc::downgrade(&editor);
+        s.connect_value_changed(move |_| {
+            if let Some(e) = e.upgrade() { e.spin_changed(i); }
+        });
+    }
+    let e= Rc::downgrade(&editor);
+    editor.preset.connect_selected_notify(move |_| {
+        if let Some(e) = e.upgrade() { e.preset_changed(); }
+    });
+    let clear= Button::with_label("no crop");
+    let e= Rc::downgrade(&editor);
+    clear.connect_clicked(move |_| {
+        if let Some(e) = e.upgrade() { e.edit(None); }
+    });
+
+    let e= Rc::downgrade(&editor);
+    selection.connect_selection_changed(move |_, _, _| {
+        if let Some(e) = e.upgrade() { e.load(); }
+    });
+    let editor_for_history= editor.clone(); // the editor lives as long as the history
+    history.connect_changed(move || {
+        if editor_for_history.drag.borrow().is_none() {
+            editor_for_history.load(); }
+    });
+    editor.load();
+
+    let fields= Box::new(Orientation::Horizontal, 2);
+    for (name, s) in ["x", "y", "w", "h"].iter().zip(editor.spins.iter()) {
+        fields.append(&Label::new(Some(name)));
+        fields.append(s);
+    }
+    let tools= Box::new(Orientation::Horizontal, 4);
+    tools.append(&editor.preset);
+    tools.append(&clear);
+    tools.append(&editor.info);
+
+    let vbox= Box::new(Orientation::Vertical, 2);
+    vbox.append(&editor.area);
+    vbox.append(&fields);
+    vbox.append(&tools);
+    vbox
+}
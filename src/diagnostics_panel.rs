@@ -0,0 +1,122 @@
+//! Diagnostics panel: the consistency check of the rows (consistency.rs) and the dump of the tree.
+//! with "check each operation" (on in a debug build) the check runs whenever the history is changed,
+//! a mismatch is printed and listed here. clicking a mismatch selects its node.
+//! "dump" shows every node with its row and prints it.
+
+use std::cell::RefCell;
+use std::rc::{Rc, Weak};
+use gtk::prelude::*;
+use gtk::{Box, Button, CheckButton, Label, ListBox, Orientation, PolicyType, ScrolledWindow, SelectionMode,
+          SingleSelection, TextView, WrapMode};
+
+use crate::consistency::{self, Mismatch};
+use crate::operation_history::OperationHistory;
+use crate::scenario_node::ScenarioNode;
+use crate::scenario_node_object::{first_root, select_node};
+
+// DiagnosticsPanel ////////////////////////////////////////
+pub struct DiagnosticsPanel {
+    history  : Weak<OperationHistory>,
+    selection: SingleSelection,
+    widget   : Box,
+    each     : CheckButton,
+    status   : Label,
+    list_box : ListBox,
+    text     : TextView,
+    found    : RefCell<Vec<Mismatch>>, // in the order of the lines
+}
+impl DiagnosticsPanel {
+    pub fn new(history: &Rc<OperationHistory>, selection: SingleSelection) -> Rc<DiagnosticsPanel> {
+        let panel= Rc::new( DiagnosticsPanel{
+            history  : Rc::downgrade(history),
+            selection,
+            widget   : Box::new(Orientation::Vertical, 2),
+            each     : CheckButton::builder().label("check each operation").active(cfg!(debug_assertions)).build(),
+            status   : Label::builder().xalign(0.0).build(),
+            list_box : ListBox::new(),
+            text     : TextView::builder().editable(false).monospace(true).wrap_mode(WrapMode::None).build(),
+            found    : RefCell::new(Vec::new()),
+        } );
+        panel.list_box.set_selection_mode(SelectionMode::Single);
+
+        let check= Button::with_label("check");
+        let dump = Button::with_label("dump");
+        let p= Rc::downgrade(&panel);
+        check.connect_clicked(move |_| {
+            if let Some(p) = p.upgrade() { p.check(); }
+        });
+        let p= Rc::downgrade(&panel);
+        dump.connect_clicked(move |_| {
+            if let Some(p) = p.upgrade() { p.dump(); }
+        });
+        let p= Rc::downgrade(&panel);
+        panel.list_box.connect_row_selected(move |_, row| {
+            if let (Some(p), Some(r)) = (p.upgrade(), row) { p.select(r.index() as usize); }
+        });
+        let p= Rc::downgrade(&panel);
+        history.connect_changed(move || {
+            if let Some(p) = p.upgrade() {
+                if p.each.is_active() { p.check(); }
+            }
+        });
+
+        let bar= Box::new(Orientation::Horizontal, 4);
+        bar.append(&check);
+        bar.append(&dump);
+        bar.append(&panel.each);
+        panel.widget.append(&bar);
+        panel.widget.append(&panel.status);
+        panel.widget.append(&ScrolledWindow::builder()
+                            .hscrollbar_policy(PolicyType::Automatic)
+                            .min_content_height(80)
+                            .child(&panel.list_box)
+                            .build());
+        panel.widget.append(&ScrolledWindow::builder()
+                            .hscrollbar_policy(PolicyType::Automatic)
+                            .vexpand(true)
+                            .child(&panel.text)
+                            .build());
+        panel
+    }
+    pub fn widget(&self) -> &Box {
+        &self.widget
+    }
+    // check ///////////////////////////////////////////////
+    /// the number of mismatches
+    pub fn check(&self) -> usize {
+        let Some(h) = self.history.upgrade() else { return 0 };
+        let found= consistency::check(&h.tree_list_model());
+        for m in found.iter() {
+            println!("inconsistent: {}", m); }
+        self.status.set_label( &match found.len() {
+            0 => String::from("consistent"),
+            n => format!("{} mismatches", n),
+        } );
+        self.list_box.remove_all();
+        for m in found.iter() {
+            let label= Label::builder().label(m.to_string()).xalign(0.0).build();
+            label.add_css_class("markup_error");
+            self.list_box.append(&label);
+        }
+        let n= found.len();
+        *self.found.borrow_mut()= found;
+        n
+    }
+    // dump ////////////////////////////////////////////////
+    pub fn dump(&self) {
+        let Some(h) = self.history.upgrade() else { return };
+        self.check();
+        let text= consistency::dump(&h.tree_list_model());
+        println!("--------------------");
+        print!("{}", text);
+        self.text.buffer().set_text(&text);
+    }
+    // select //////////////////////////////////////////////
+    fn select(&self, line: usize) {
+        let (Some(h), Some(path)) = (self.history.upgrade(), self.found.borrow().get(line).map(|m| m.path.clone()))
+            else { return };
+        let tlm= h.tree_list_model();
+        if let Some(n) = first_root(&tlm).and_then(|r| ScenarioNode::node_at_path(r, &path)) {
+            select_node(&tlm, &self.selection, &n); }
+    }
+}
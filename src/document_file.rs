@@ -0,0 +1,215 @@
+//! the file of a document: open / save / save as, the recent files and the title of the window
+//!
+//! a window holds one document, "open" and a recent file open it in a new window.
+//! the asset root and the text sources are resolved from the file the window is opened with.
+//! the document is modified while the history is not at the saved index (OperationHistory::is_modified):
+//! the title shows "*" before the file name, and closing the window asks to save it.
+//! the recent files are kept in <user config dir>/isv2/recent.json, the newest first.
+//! a document has a recovery slot for the autosave (recovery.rs).
+
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::{Rc, Weak};
+use gtk::prelude::*;
+use gtk::{gio, glib};
+use gtk::{AlertDialog, FileDialog, FileFilter, Window};
+
+use crate::scenario_file;
+use crate::scenario_node_object::{ScenarioNodeObject, first_root, root_store};
+use crate::operation_history::OperationHistory;
+use crate::recovery::{self, Recovery};
+
+pub const APP_NAME  : &str  = "isv2";
+pub const MAX_RECENT: usize = 10;
+
+// recent files ////////////////////////////////////////////
+fn recent_path() -> PathBuf {
+    glib::user_config_dir().join(APP_NAME).join("recent.json")
+}
+pub fn recent_files() -> Vec<PathBuf> {
+    fs::read_to_string(recent_path()).ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+/// `path` becomes the newest one
+pub fn add_recent(path: &Path) {
+    let path= path.canonicalize().unwrap_or(path.to_path_buf());
+    let mut files= recent_files();
+    files.retain(|p| *p != path);
+    files.insert(0, path);
+    files.truncate(MAX_RECENT);
+    let result= fs::create_dir_all( recent_path().parent().expect("in a directory") )
+        .and_then(|_| serde_json::to_string_pretty(&files).map_err(io::Error::from))
+        .and_then(|json| fs::write(recent_path(), json));
+    if let Err(e) = result {
+        println!("recent files are not saved: {}", e);
+    }
+}
+// dialogs /////////////////////////////////////////////////
+fn filters() -> gio::ListStore {
+    let filter= FileFilter::new();
+    filter.set_name(Some("scenario (*.json, *.xml)"));
+    filter.add_pattern("*.json");
+    filter.add_pattern("*.xml");
+    let store= gio::ListStore::new::<FileFilter>();
+    store.append(&filter);
+    store
+}
+/// `f` is called with the chosen file
+pub fn choose_file<F: FnOnce(PathBuf) + 'static>(window: &Window, f: F) {
+    FileDialog::builder()
+        .title("open")
+        .modal(true)
+        .filters(&filters())
+        .build()
+        .open(Some(window), gio::Cancellable::NONE, move |result| {
+            if let Ok(Some(path)) = result.map(|file| file.path()) {
+                f(path); }
+        });
+}
+pub fn show_error(window: &Window, message: &str, e: &io::Error) {
+    println!("{}: {}", message, e);
+    AlertDialog::builder()
+        .message(message)
+        .detail(e.to_string())
+        .build()
+        .show(Some(window));
+}
+// DocumentFile ////////////////////////////////////////////
+pub struct DocumentFile {
+    path   : RefCell<Option<PathBuf>>, // None: untitled
+    slot   : RefCell<PathBuf>,         // the recovery slot
+    history: Weak<OperationHistory>,
+}
+impl DocumentFile {
+    pub fn new(history: &Rc<OperationHistory>) -> Rc<DocumentFile> {
+        Rc::new( DocumentFile{
+            path   : RefCell::new(None),
+            slot   : RefCell::new(recovery::new_slot()),
+            history: Rc::downgrade(history),
+        } )
+    }
+    pub fn path(&self) -> Option<PathBuf> {
+        self.path.borrow().clone()
+    }
+    pub fn recovery_slot(&self) -> PathBuf {
+        self.slot.borrow().clone()
+    }
+    pub fn is_modified(&self) -> bool {
+        self.history.upgrade().is_some_and(|h| h.is_modified())
+    }
+    fn name(&self) -> String {
+        self.path.borrow().as_ref()
+            .and_then(|p| p.file_name())
+            .map_or(String::from("untitled"), |n| n.to_string_lossy().to_string())
+    }
+    /// "*name - isv2" while modified
+    pub fn title(&self) -> String {
+        format!("{}{} - {}", if self.is_modified() { "*" } else { "" }, self.name(), APP_NAME)
+    }
+    // open ////////////////////////////////////////////////
+    /// replace the tree and the history with the ones of `path`
+    pub fn open(&self, path: &Path) -> io::Result<()> {
+        self.load(path)?;
+        *self.path.borrow_mut()= Some(path.to_path_buf());
+        add_recent(path);
+        Ok(())
+    }
+    /// replace the tree and the history with the ones of a recovery slot, the document is not saved yet.
+    /// the slot becomes the one of the document, the previous slot is removed
+    pub fn restore(&self, r: &Recovery) -> io::Result<()> {
+        let Some(h) = self.history.upgrade() else { return Ok(()) };
+        self.load( &recovery::scenario_path(&r.slot) )?;
+        *self.path.borrow_mut()= r.info.path.clone();
+        h.set_saved(r.info.saved);
+        recovery::remove( &self.slot.replace(r.slot.clone()) )
+    }
+    fn load(&self, path: &Path) -> io::Result<()> {
+        let Some(h) = self.history.upgrade() else { return Ok(()) };
+        let (roots, next_id)= scenario_file::load(path)?;
+
+        let doc= h.document();
+        doc.set_next_id(next_id);
+        let renumbered= doc.assign_unique_ids(roots.first().cloned());
+        if renumbered > 0 {
+            println!("{} duplicated ids are renumbered", renumbered);
+        }
+        let store= root_store(&h.tree_list_model());
+        store.remove_all();
+        for (i, r) in roots.iter().enumerate() {
+            let sno= ScenarioNodeObject::new_from(r.clone());
+            sno.set_seq(i as i32);
+            store.append(&sno);
+        }
+        if renumbered > 0 { // the items of the history refer to the old ids
+            println!("history is not loaded: the ids are renumbered");
+            h.clear();
+        } else if let Err(e) = h.load_from( &scenario_file::history_path(path) ) {
+            println!("history is not loaded: {}", e);
+            h.clear();
+        }
+        Ok(())
+    }
+    // save ////////////////////////////////////////////////
+    /// save the tree and the history to `path`, which becomes the file of the document
+    pub fn save_to(&self, path: &Path) -> io::Result<()> {
+        let Some(h) = self.history.upgrade() else { return Ok(()) };
+        scenario_file::save(path, first_root(&h.tree_list_model()), h.document().get_next_id())?;
+        h.save_to( &scenario_file::history_path(path) )?;
+        *self.path.borrow_mut()= Some(path.to_path_buf());
+        h.mark_saved();
+        add_recent(path);
+        Ok(())
+    }
+    /// save to the file, an untitled document asks for one. `then` is called when saved
+    pub fn save<F: FnOnce() + 'static>(self: &Rc<Self>, window: &Window, then: F) {
+        let Some(path) = self.path() else { return self.save_as(window, then) };
+        match self.save_to(&path) {
+            Ok(()) => then(),
+            Err(e) => show_error(window, "not saved", &e),
+        }
+    }
+    pub fn save_as<F: FnOnce() + 'static>(self: &Rc<Self>, window: &Window, then: F) {
+        let dialog= FileDialog::builder()
+            .title("save as")
+            .modal(true)
+            .filters(&filters())
+            .initial_name( self.path().map_or(String::from("scenario.json"), |_| self.name()) )
+            .build();
+        let file  = self.clone();
+        let parent= window.clone();
+        dialog.save(Some(window), gio::Cancellable::NONE, move |result| {
+            let Ok(Some(path)) = result.map(|f| f.path()) else { return };
+            match file.save_to(&path) {
+                Ok(()) => then(),
+                Err(e) => show_error(&parent, "not saved", &e),
+            }
+        });
+    }
+    // connect_close ///////////////////////////////////////
+    /// closing `window` with a modified document asks to save it
+    pub fn connect_close(self: &Rc<Self>, window: &Window) {
+        let file= Rc::downgrade(self);
+        window.connect_close_request(move |w| {
+            let Some(file) = file.upgrade() else { return glib::Propagation::Proceed };
+            if !file.is_modified() {
+                return glib::Propagation::Proceed; }
+            let dialog= AlertDialog::builder()
+                .message( format!("save the changes to {}?", file.name()) )
+                .detail("the changes are lost unless they are saved")
+                .buttons(["cancel", "discard", "save"])
+                .cancel_button(0)
+                .default_button(2)
+                .build();
+            let w= w.clone();
+            dialog.choose(Some(&w.clone()), gio::Cancellable::NONE, move |result| match result {
+                Ok(1) => w.destroy(),
+                Ok(2) => { let w2= w.clone(); file.save(&w, move || w2.destroy()) },
+                _     => (),
+            });
+            glib::Propagation::Stop
+        });
+    }
+}
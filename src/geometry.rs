@@ -0,0 +1,120 @@
+//! Length: a coordinate or a size of Position / Dimension
+//!
+//!   40, -120, 12.5   pixels, may be negative (e.g. a mat starting off-screen) or fractional
+//!   "50%"            percent of the canvas (the image for a crop), x / w of the width, y / h of the height
+//!
+//! a length is resolved to pixels at render / export time (render.rs),
+//! so one scenario is drawn on 1280x720 and on 1920x1080.
+//! the old files (unsigned integers) are read as pixels.
+
+use std::fmt;
+use std::str::FromStr;
+use serde::{Serialize, Deserialize};
+
+// Length //////////////////////////////////////////////////
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "LengthRepr", into = "LengthRepr")]
+pub enum Length {
+    Px(f64),
+    Percent(f64),
+}
+// LengthError /////////////////////////////////////////////
+#[derive(Debug, Clone, PartialEq)]
+pub struct LengthError(pub String);
+impl fmt::Display for LengthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "bad length: {}", self.0)
+    }
+}
+impl Default for Length {
+    fn default() -> Length {
+        Length::Px(0.0)
+    }
+}
+impl Length {
+    // parse ///////////////////////////////////////////////
+    /// "40", "-12.5", "40px" or "50%"
+    pub fn parse(s: &str) -> Result<Length, LengthError> {
+        let bad= || LengthError(s.to_string());
+        let s= s.trim();
+        let (num, percent)= match s.strip_suffix('%') {
+            Some(n) => (n, true),
+            None    => (s.strip_suffix("px").unwrap_or(s), false),
+        };
+        let v= num.trim().parse::<f64>().ok().filter(|v| v.is_finite()).ok_or_else(bad)?;
+        Ok( if percent { Length::Percent(v) } else { Length::Px(v) } )
+    }
+    // resolve /////////////////////////////////////////////
+    /// pixels on the canvas of `size` (the width or the height)
+    pub fn resolve(self, size: f64) -> f64 {
+        match self {
+            Length::Px(v)      => v,
+            Length::Percent(p) => size * p / 100.0,
+        }
+    }
+    /// the length of the same mode which resolves to `px`
+    pub fn with_resolved(self, px: f64, size: f64) -> Length {
+        match self {
+            Length::Px(_)                    => Length::Px(px),
+            Length::Percent(_) if size > 0.0 => Length::Percent(px * 100.0 / size),
+            Length::Percent(p)               => Length::Percent(p),
+        }
+    }
+    /// to whole pixels or to 0.01%, for the values set by dragging
+    pub fn rounded(self) -> Length {
+        match self {
+            Length::Px(v)      => Length::Px(v.round()),
+            Length::Percent(p) => Length::Percent((p * 100.0).round() / 100.0),
+        }
+    }
+    pub fn is_percent(&self) -> bool {
+        matches!(self, Length::Percent(_))
+    }
+}
+impl From<f64> for Length {
+    fn from(v: f64) -> Length {
+        Length::Px(v)
+    }
+}
+impl fmt::Display for Length {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Length::Px(v)      => write!(f, "{}", v),
+            Length::Percent(p) => write!(f, "{}%", p),
+        }
+    }
+}
+impl FromStr for Length {
+    type Err = LengthError;
+    fn from_str(s: &str) -> Result<Length, LengthError> {
+        Length::parse(s)
+    }
+}
+// serde ///////////////////////////////////////////////////
+/// pixels as a number (an integer if whole), percent as "50%"
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum LengthRepr {
+    Int(i64),
+    Num(f64),
+    Text(String),
+}
+impl TryFrom<LengthRepr> for Length {
+    type Error = LengthError;
+    fn try_from(l: LengthRepr) -> Result<Length, LengthError> {
+        match l {
+            LengthRepr::Int(v)  => Ok( Length::Px(v as f64) ),
+            LengthRepr::Num(v)  => Ok( Length::Px(v) ),
+            LengthRepr::Text(s) => Length::parse(&s),
+        }
+    }
+}
+impl From<Length> for LengthRepr {
+    fn from(l: Length) -> LengthRepr {
+        match l {
+            Length::Px(v) if v.fract() == 0.0 && v.abs() < 1e15 => LengthRepr::Int(v as i64),
+            Length::Px(v)                                       => LengthRepr::Num(v),
+            Length::Percent(_)                                  => LengthRepr::Text(l.to_string()),
+        }
+    }
+}
@@ -0,0 +1,23 @@
+//! the allowed parent / child kinds (see scenario_node.rs)
+//!
+//!   (root)  group, scene
+//!   group   group, scene
+//!   scene   page, pmat
+//!   page    mat, ovimg
+//!   mat, ovimg, pmat have no children
+
+use crate::scenario_node::ItemKind;
+
+// allowed_children ////////////////////////////////////////
+/// the kinds allowed under `parent`, None is the root level
+pub fn allowed_children(parent: Option<ItemKind>) -> &'static [ItemKind] {
+    match parent {
+        None | Some(ItemKind::Group) => &[ItemKind::Group, ItemKind::Scene],
+        Some(ItemKind::Scene)        => &[ItemKind::Page,  ItemKind::Pmat],
+        Some(ItemKind::Page)         => &[ItemKind::Mat,   ItemKind::Ovimg],
+        Some(_)                      => &[],
+    }
+}
+pub fn allows(parent: Option<ItemKind>, child: ItemKind) -> bool {
+    allowed_children(parent).contains(&child)
+}
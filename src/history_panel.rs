@@ -0,0 +1,77 @@
+//! History panel: lists the items of OperationHistory.
+//! row 0 is the initial state, row n is the state after the n-th item.
+//! clicking a row jumps to that state by undo/redo.
+//! the spin button above the list sets the number of items kept.
+
+use std::rc::{Rc, Weak};
+use gtk::prelude::*;
+use gtk::{Box, Label, ListBox, Orientation, PolicyType, ScrolledWindow, SelectionMode, SpinButton};
+
+use crate::operation_history::OperationHistory;
+
+// refresh /////////////////////////////////////////////////
+fn refresh(list_box: &ListBox, history: &OperationHistory){
+    list_box.remove_all();
+
+    let initial= Label::new(Some("(initial)"));
+    initial.set_xalign(0.0);
+    list_box.append(&initial);
+
+    for (i, d) in history.descriptions().iter().enumerate() {
+        let label= Label::new(Some(&format!("{}: {}", i + 1, d)));
+        label.set_xalign(0.0);
+        if (i as i32) >= history.get_index() { // undone items
+            label.add_css_class("history_undone");
+        }
+        list_box.append(&label);
+    }
+    list_box.select_row( list_box.row_at_index(history.get_index()).as_ref() );
+}
+/// the range of the limit set by the spin button
+const MAX_HISTORY_LIMIT: f64 = 100_000.0;
+
+// build_history_panel /////////////////////////////////////
+pub fn build_history_panel(history: Rc<OperationHistory>) -> Box {
+    let list_box= ListBox::new();
+    list_box.set_selection_mode(SelectionMode::Single);
+    list_box.set_activate_on_single_click(true);
+
+    let history_weak: Weak<OperationHistory>= Rc::downgrade(&history);
+    list_box.connect_row_activated(move |_, row| {
+        if let Some(h) = history_weak.upgrade() {
+            h.jump_to( row.index() );
+        }
+    });
+
+    let list_box_for_history= list_box.clone();
+    let history_weak= Rc::downgrade(&history);
+    history.connect_changed(move || {
+        if let Some(h) = history_weak.upgrade() {
+            refresh(&list_box_for_history, &h);
+        }
+    });
+    refresh(&list_box, &history);
+
+    let limit= SpinButton::with_range(1.0, MAX_HISTORY_LIMIT, 10.0);
+    limit.set_value(history.get_limit() as f64);
+    limit.set_tooltip_text(Some("the number of items kept, the oldest ones are dropped"));
+    let history_weak= Rc::downgrade(&history);
+    limit.connect_value_changed(move |s| {
+        if let Some(h) = history_weak.upgrade() {
+            h.set_limit(s.value_as_int() as usize);
+        }
+    });
+    let limit_box= Box::new(Orientation::Horizontal, 4);
+    limit_box.append(&Label::new(Some("keep")));
+    limit_box.append(&limit);
+
+    let panel= Box::new(Orientation::Vertical, 2);
+    panel.append(&limit_box);
+    panel.append(&ScrolledWindow::builder()
+                 .hscrollbar_policy(PolicyType::Never)
+                 .min_content_width(200)
+                 .vexpand(true)
+                 .child(&list_box)
+                 .build());
+    panel
+}
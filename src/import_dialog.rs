@@ -0,0 +1,215 @@
+//! "import" dialog: the changes of another scenario file are merged into the document.
+//!
+//! theirs is the file with the changes, base is the common ancestor of theirs and the document
+//! (e.g. the file before the branches were made).
+//! "compare" lists the changes from base to theirs and the conflicts of the three-way merge
+//! (scenario_diff.rs), "import" replaces the tree with the merged one as one undoable step.
+//! the conflicted rows are marked in the tree (ConflictMarks) until "clear marks".
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
+use gtk::prelude::*;
+use gtk::glib;
+use gtk::{Box, Button, Entry, Label, ListBox, Orientation, PolicyType, ScrolledWindow, SelectionMode,
+          SingleSelection, Window};
+
+use crate::scenario_diff::{self, MergeResult};
+use crate::scenario_file::{ScenarioFile, SCENARIO_FILE_VERSION, read_file, forest_to_records, from_record};
+use crate::operation_history::{Operation, OperationHistory, OperationHistoryItem};
+use crate::scenario_node_object::{Touched, first_root, root_store, insert_node_at, remove_node_at, refresh_rows,
+                                  select_node};
+use crate::tree_error::{TreeError, TreeResult};
+
+/// the conflicts shown on the rows, by node id
+pub type ConflictMarks = Rc<RefCell<HashMap<i32, String>>>;
+
+// merge_into //////////////////////////////////////////////
+/// the merge of the document (ours) and `theirs` from `base`
+fn merge_into(history: &OperationHistory, base: &ScenarioFile, theirs: &ScenarioFile) -> MergeResult {
+    let ours= ScenarioFile{
+        version: SCENARIO_FILE_VERSION,
+        next_id: history.document().get_next_id(),
+        nodes  : forest_to_records( first_root(&history.tree_list_model()) ),
+    };
+    scenario_diff::merge(base, &ours, theirs)
+}
+// apply ///////////////////////////////////////////////////
+/// replace the tree with the nodes of `file` as one undoable step, a failure undoes the step
+fn apply(history: &OperationHistory, file: &ScenarioFile) -> TreeResult<()> {
+    history.begin("import changes");
+    if let Err(e) = replace_roots(history, file) {
+        return history.abort().and(Err(e)); }
+    history.commit();
+
+    let doc= history.document();
+    doc.set_next_id( file.next_id.max(doc.get_next_id()) );
+    doc.reindex( first_root(&history.tree_list_model()) );
+    Ok(())
+}
+/// the nodes are removed and inserted at the root level, which touches no row
+fn replace_roots(history: &OperationHistory, file: &ScenarioFile) -> TreeResult<()> {
+    let tlm= history.tree_list_model();
+    for _ in 0..root_store(&tlm).n_items() {
+        let r= first_root(&tlm).ok_or( TreeError::NoNode(vec![0]) )?;
+        let h= OperationHistoryItem::new_remove(&r);
+        remove_node_at(&tlm, &[0])?;
+        history.push(h);
+    }
+    if let Some(r) = first_root(&tlm) { // the store did not follow the nodes
+        return Err( TreeError::StaleRow(r.id.get()) ); }
+    for (i, r) in file.nodes.iter().enumerate() {
+        let n= from_record(r);
+        insert_node_at(&tlm, &[i], n.clone())?;
+        history.push( OperationHistoryItem::new_add(Operation::AddRoot, &n) );
+    }
+    Ok(())
+}
+// ImportDialog ////////////////////////////////////////////
+struct ImportDialog {
+    history  : Rc<OperationHistory>,
+    selection: SingleSelection,
+    marks    : ConflictMarks,
+    theirs   : Entry,
+    base     : Entry,
+    list_box : ListBox,
+    status   : Label,
+    ids      : RefCell<Vec<i32>>, // the node of each line
+}
+impl ImportDialog {
+    /// (base, theirs)
+    fn read(&self) -> Option<(ScenarioFile, ScenarioFile)> {
+        let (base, theirs)= (self.base.text().trim().to_string(), self.theirs.text().trim().to_string());
+        if theirs.is_empty() || base.is_empty() {
+            self.status.set_label("theirs and base are needed");
+            return None;
+        }
+        let read= |p: &str| read_file(Path::new(p)).map_err(|e| format!("{}: {}", p, e));
+        match read(&base).and_then(|b| Ok( (b, read(&theirs)?) )) {
+            Ok(files) => Some(files),
+            Err(e)    => { self.status.set_label(&e); None },
+        }
+    }
+    fn show_lines(&self, lines: Vec<(i32, String, bool)>) {
+        self.list_box.remove_all();
+        for (_, line, conflict) in lines.iter() {
+            let label= Label::builder().label(line).xalign(0.0).build();
+            if *conflict {
+                label.add_css_class("merge_conflict"); }
+            self.list_box.append(&label);
+        }
+        *self.ids.borrow_mut()= lines.into_iter().map(|(id, _, _)| id).collect();
+    }
+    // compare /////////////////////////////////////////////
+    fn compare(&self) {
+        let Some((base, theirs)) = self.read() else { return };
+        let m= merge_into(&self.history, &base, &theirs);
+        let changes= scenario_diff::diff(&base.nodes, &theirs.nodes);
+        let mut lines: Vec<(i32, String, bool)>= m.conflicts.iter()
+            .map(|c| (c.id, format!("conflict {}", c), true))
+            .collect();
+        lines.extend( changes.iter().map(|c| (c.id(), c.to_string(), false)) );
+        self.status.set_label( &format!("{} changes, {} conflicts", changes.len(), m.conflicts.len()) );
+        self.show_lines(lines);
+    }
+    // import //////////////////////////////////////////////
+    fn import(&self) {
+        let Some((base, theirs)) = self.read() else { return };
+        let m= merge_into(&self.history, &base, &theirs);
+        if let Err(e) = apply(&self.history, &m.file) {
+            self.history.report(&e);
+            self.status.set_label( &format!("not imported: {}", e) );
+            return;
+        }
+        *self.marks.borrow_mut()= m.conflicts.iter().map(|c| (c.id, c.msg.clone())).collect();
+        refresh_rows(&self.history.tree_list_model(), &self.marked_nodes());
+
+        let mut lines: Vec<(i32, String, bool)>= m.renumbered.iter()
+            .map(|(from, to)| (*to, format!("id:{} of theirs is renumbered to id:{}", from, to), false))
+            .collect();
+        lines.extend( m.conflicts.iter().map(|c| (c.id, format!("conflict {}", c), true)) );
+        self.status.set_label( &format!("imported, {} conflicts", m.conflicts.len()) );
+        self.show_lines(lines);
+    }
+    fn clear_marks(&self) {
+        let marked= self.marked_nodes();
+        self.marks.borrow_mut().clear();
+        refresh_rows(&self.history.tree_list_model(), &marked);
+    }
+    /// the nodes of the marks, their rows show the conflicts
+    fn marked_nodes(&self) -> Touched {
+        self.marks.borrow().keys().filter_map(|id| self.history.lookup(*id)).collect()
+    }
+    // select //////////////////////////////////////////////
+    fn select(&self, line: usize) {
+        let Some(id) = self.ids.borrow().get(line).copied() else { return };
+        if let Some(n) = self.history.lookup(id) {
+            select_node(&self.history.tree_list_model(), &self.selection, &n); }
+    }
+}
+// open_import_dialog //////////////////////////////////////
+pub fn open_import_dialog(parent: Option<&Window>, history: Rc<OperationHistory>, selection: SingleSelection,
+                          marks: ConflictMarks) {
+    let dialog= Rc::new( ImportDialog{
+        history,
+        selection,
+        marks,
+        theirs  : Entry::builder().placeholder_text("theirs: the file with the changes").hexpand(true).build(),
+        base    : Entry::builder().placeholder_text("base: the common ancestor").hexpand(true).build(),
+        list_box: ListBox::new(),
+        status  : Label::builder().xalign(0.0).build(),
+        ids     : RefCell::new(Vec::new()),
+    } );
+    dialog.list_box.set_selection_mode(SelectionMode::Single);
+
+    let compare= Button::with_label("compare");
+    let import = Button::with_label("import");
+    let clear  = Button::with_label("clear marks");
+    let d= Rc::downgrade(&dialog);
+    compare.connect_clicked(move |_| {
+        if let Some(d) = d.upgrade() { d.compare(); }
+    });
+    let d= Rc::downgrade(&dialog);
+    import.connect_clicked(move |_| {
+        if let Some(d) = d.upgrade() { d.import(); }
+    });
+    let d= Rc::downgrade(&dialog);
+    clear.connect_clicked(move |_| {
+        if let Some(d) = d.upgrade() { d.clear_marks(); }
+    });
+    let d= Rc::downgrade(&dialog);
+    dialog.list_box.connect_row_selected(move |_, row| {
+        if let (Some(d), Some(r)) = (d.upgrade(), row) { d.select(r.index() as usize); }
+    });
+
+    let bar= Box::new(Orientation::Horizontal, 4);
+    bar.append(&compare);
+    bar.append(&import);
+    bar.append(&clear);
+    let vbox= Box::new(Orientation::Vertical, 2);
+    vbox.append(&dialog.theirs);
+    vbox.append(&dialog.base);
+    vbox.append(&bar);
+    vbox.append(&ScrolledWindow::builder()
+                .hscrollbar_policy(PolicyType::Automatic)
+                .min_content_height(240)
+                .min_content_width(480)
+                .vexpand(true)
+                .child(&dialog.list_box)
+                .build());
+    vbox.append(&dialog.status);
+
+    let window= Window::builder()
+        .title("import changes")
+        .child(&vbox)
+        .build();
+    window.set_transient_for(parent);
+
+    // the dialog lives as long as the window
+    window.connect_close_request(move |_| {
+        let _= &dialog;
+        glib::Propagation::Proceed
+    });
+    window.present();
+}
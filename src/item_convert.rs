@@ -0,0 +1,162 @@
+//! conversion between compatible item kinds
+//!
+//!   mat  <-> pmat  : every field is kept
+//!   mat  <-> ovimg : pos and a are kept
+//!   page  -> pmat  : the page must have only one child, a mat, which is merged into the pmat
+//!   pmat  -> page  : the fields go to a new mat, the first child of the page
+//!
+//! the new kind must be allowed under the parent of the node (hierarchy.rs),
+//! e.g. a mat in a page is not converted to a pmat.
+//! a conversion is recorded as one transaction of OperationHistory.
+
+use std::fmt;
+use std::rc::Rc;
+
+use crate::scenario_node::{ScenarioNode, Item, ItemKind, Mat, Ovimg, Color, Dimension};
+use crate::scenario_node_object::{Touched, first_root, set_value_at, insert_node_at, remove_node_at, refresh_rows};
+use crate::operation_history::{Operation, OperationHistory, OperationHistoryItem};
+use crate::hierarchy;
+use crate::tree_error::{TreeError, TreeResult};
+
+// ConvertError ////////////////////////////////////////////
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConvertError {
+    NoNode,                         // nothing at the path
+    Incompatible(ItemKind, ItemKind),
+    NotSingleMat,                   // page -> pmat needs exactly one mat child
+    NotAllowed(Option<ItemKind>, ItemKind), // the new kind under the parent kind (None: the root level)
+    Tree(TreeError),                // the rows did not follow, the conversion is undone
+}
+impl fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConvertError::NoNode             => write!(f, "no node"),
+            ConvertError::Incompatible(a, b) => write!(f, "{} cannot be converted to {}", a.name(), b.name()),
+            ConvertError::NotSingleMat       => write!(f, "the page must have only one mat"),
+            ConvertError::NotAllowed(p, k)   => write!(f, "a {} is not allowed in {}", k.name(),
+                                                       p.map_or("the root level", |p| p.name())),
+            ConvertError::Tree(e)            => write!(f, "{}", e),
+        }
+    }
+}
+// Plan ////////////////////////////////////////////////////
+enum Plan {
+    SetValue(Item),             // the value is replaced
+    MergeChild(Item),           // the only child is removed, then the value is replaced
+    SplitChild(Item, Box<Item>),// the value is replaced, then a child is added
+}
+// mat_from_ovimg //////////////////////////////////////////
+fn mat_from_ovimg(o: &Ovimg) -> Mat {
+    Mat {
+        col   : Color::BLACK,
+        pos   : o.pos.clone(),
+        dim   : Dimension::px(0.0, 0.0),
+        r     : 0,
+        a     : o.a,
+        src   : None,
+        lbl   : None,
+        lblref: None,
+        name  : String::new(),
+        txt   : String::new(),
+        v     : false,
+    }
+}
+// convert_value ///////////////////////////////////////////
+/// the value converted to `to` without changing the tree, None if not compatible
+pub fn convert_value(v: &Item, to: ItemKind) -> Option<Item> {
+    match (v, to) {
+        (Item::Mat(m),   ItemKind::Pmat)  => Some(Item::Pmat(m.clone())),
+        (Item::Pmat(m),  ItemKind::Mat)   => Some(Item::Mat(m.clone())),
+        (Item::Mat(m),   ItemKind::Ovimg) => Some(Item::Ovimg(Ovimg{ path: String::new(), pos: m.pos.clone(), a: m.a })),
+        (Item::Ovimg(o), ItemKind::Mat)   => Some(Item::Mat(mat_from_ovimg(o))),
+        _ => None,
+    }
+}
+// plan ////////////////////////////////////////////////////
+fn plan(node: &ScenarioNode, to: ItemKind) -> Result<Plan, ConvertError> {
+    let p= plan_value(node, to)?;
+    let parent= node.get_parent_node().map(|n| n.value.borrow().kind());
+    if !hierarchy::allows(parent, to) {
+        return Err(ConvertError::NotAllowed(parent, to)); }
+    Ok(p)
+}
+fn plan_value(node: &ScenarioNode, to: ItemKind) -> Result<Plan, ConvertError> {
+    let v= node.value.borrow().clone();
+    if let Some(c) = convert_value(&v, to) {
+        return Ok(Plan::SetValue(c)); }
+    match (&v, to) {
+        (Item::Page, ItemKind::Pmat) => {
+            match node.get_children().as_slice() {
+                [c] => match &*c.value.borrow() {
+                    Item::Mat(m) => Ok(Plan::MergeChild(Item::Pmat(m.clone()))),
+                    _            => Err(ConvertError::NotSingleMat),
+                },
+                _ => Err(ConvertError::NotSingleMat),
+            }
+        },
+        (Item::Pmat(m), ItemKind::Page) => Ok(Plan::SplitChild(Item::Page, Box::new(Item::Mat(m.clone())))),
+        _ => Err(ConvertError::Incompatible(v.kind(), to)),
+    }
+}
+// conversion_targets //////////////////////////////////////
+/// the kinds which `node` can be converted to, in its place
+pub fn conversion_targets(node: &ScenarioNode) -> Vec<ItemKind> {
+    ItemKind::ALL.iter().copied().filter(|k| can_convert(node, *k)).collect()
+}
+pub fn can_convert(node: &ScenarioNode, to: ItemKind) -> bool {
+    plan(node, to).is_ok()
+}
+// set_kind ////////////////////////////////////////////////
+fn set_kind(history: &OperationHistory, node: &ScenarioNode, path: &[usize], v: Item, t: &mut Touched) -> TreeResult<()> {
+    let old= node.value.borrow().clone();
+    t.extend( set_value_at(&history.tree_list_model(), path, v.clone())? );
+    history.push( OperationHistoryItem::new_set_value(node, "kind", old, v) );
+    Ok(())
+}
+// convert /////////////////////////////////////////////////
+/// convert the node at `path` to `to`, recorded as one undoable item.
+/// when the rows do not follow, the items done so far are undone
+pub fn convert(history: &OperationHistory, path: &[usize], to: ItemKind) -> Result<(), ConvertError> {
+    let tlm = history.tree_list_model();
+    let node= first_root(&tlm)
+        .and_then(|r| ScenarioNode::node_at_path(r, path))
+        .ok_or(ConvertError::NoNode)?;
+    let from= node.value.borrow().kind();
+    let plan= plan(&node, to)?;
+
+    history.begin( &format!("convert id:{} {} to {}", node.id.get(), from.name(), to.name()) );
+    let mut touched= Touched::new();
+    let result= apply(history, &node, path, plan, &mut touched);
+    refresh_rows(&tlm, &touched);
+    match result {
+        Ok(())  => { history.commit(); Ok(()) },
+        Err(e)  => history.abort().and(Err(e)).map_err(ConvertError::Tree),
+    }
+}
+fn apply(history: &OperationHistory, node: &Rc<ScenarioNode>, path: &[usize], plan: Plan, t: &mut Touched)
+         -> TreeResult<()> {
+    let tlm= history.tree_list_model();
+    let mut child_path= path.to_vec();
+    child_path.push(0);
+
+    match plan {
+        Plan::SetValue(v) => set_kind(history, node, path, v, t),
+        Plan::MergeChild(v) => {
+            if let Some(c) = node.get_child() {
+                let h= OperationHistoryItem::new_remove(&c);
+                t.extend( remove_node_at(&tlm, &child_path)?.1 );
+                history.push(h);
+            }
+            set_kind(history, node, path, v, t)
+        },
+        Plan::SplitChild(v, child_value) => {
+            set_kind(history, node, path, v, t)?;
+            let c: Rc<ScenarioNode>= ScenarioNode::new();
+            c.id.set( history.document().alloc_id() );
+            c.set_value(*child_value);
+            t.extend( insert_node_at(&tlm, &child_path, c.clone())? );
+            history.push( OperationHistoryItem::new_add(Operation::AddChild, &c) );
+            Ok(())
+        },
+    }
+}
@@ -1,25 +1,82 @@
 mod scenario_node;
+mod scenario_tree;
+mod color;
+mod geometry;
 mod scenario_node_object;
 mod isv2_button;
 mod operation_history;
 mod scenario_item_drag_object;
-
-use std::cell::Cell;
+mod history_panel;
+mod scenario_file;
+mod scenario_document;
+mod scenario_query;
+mod item_convert;
+mod normalize;
+mod markup;
+mod text_source;
+mod assets;
+mod asset_panel;
+mod crop_editor;
+mod render;
+mod preview;
+mod player;
+mod mat_editor;
+mod hierarchy;
+mod validate;
+mod scenario_xml;
+mod scenario_diff;
+mod import_dialog;
+mod document_file;
+mod recovery;
+mod tree_error;
+mod toast;
+mod consistency;
+mod diagnostics_panel;
+mod outline;
+mod move_dialog;
+mod cli;
 
 use crate::scenario_node_object::ScenarioNodeObject;
 use crate::scenario_node_object::adj_seq;
+use crate::scenario_node_object::check_store;
 use crate::scenario_node_object::add_neighbor;
 use crate::scenario_node_object::add_child;
 use crate::scenario_node_object::remove_node;
+use crate::scenario_node_object::insert_node_at;
+use crate::scenario_node_object::first_root;
+use crate::scenario_node_object::row_at_path;
+use crate::scenario_node_object::root_store;
+use crate::scenario_node_object::refresh_emptied;
 use crate::scenario_item_drag_object::ScenarioItemDragObject;
+use crate::scenario_document::ScenarioDocument;
 use crate::scenario_node::ScenarioNode;
 use crate::scenario_node::BranchType;
+use crate::scenario_node::ItemKind;
+use crate::scenario_node::{Item, Mat, Color, Position, Dimension};
 use crate::operation_history::Operation;
 use crate::operation_history::OperationHistory;
 use crate::operation_history::OperationHistoryItem;
 use crate::operation_history::TreeManipulationHandle;
 
 use crate::isv2_button::Isv2Button;
+use crate::history_panel::build_history_panel;
+use crate::preview::build_preview;
+use crate::render::Canvas;
+use crate::asset_panel::build_asset_panel;
+use crate::crop_editor::build_crop_editor;
+use crate::mat_editor::build_mat_editor;
+use crate::mat_editor::row_notes;
+use crate::text_source::SourceCache;
+use crate::scenario_node_object::refresh_row;
+use crate::scenario_node_object::selected_node;
+use crate::import_dialog::{ConflictMarks, open_import_dialog};
+use crate::document_file::DocumentFile;
+use crate::recovery::Recovery;
+use crate::tree_error::{TreeError, TreeResult};
+use crate::toast::Toast;
+use crate::diagnostics_panel::DiagnosticsPanel;
+use crate::outline::{build_breadcrumb, build_minimap};
+use crate::move_dialog::{Placement, open_move_dialog, stays};
 
 use gtk::gdk::Display;
 use gtk::{
@@ -27,8 +84,8 @@ use gtk::{
     ScrolledWindow, SignalListItemFactory, SingleSelection,
     TreeExpander, TreeListModel, TreeListRow, glib::object::Object, gio::ListModel,
     CssProvider,
-    Orientation, Box, Button,
-    Widget
+    Orientation, Box, Button, Paned, Notebook, Entry, DropDown, CheckButton, AlertDialog,
+    Widget, Popover, MenuButton, ToggleButton
 };
 use gtk::{prelude::*, ListItem, DragSource};
 
@@ -38,8 +95,10 @@ use gtk::gdk::DragAction;
 use gtk::DropTarget;
 use glib::value::*;
 
-use std::sync::atomic::{AtomicI32, Ordering};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 use std::rc::Rc;
+use std::path::{Path, PathBuf};
 
 // use xmltree::Element;
 // use xmltree::EmitterConfig;
@@ -50,12 +109,8 @@ use std::rc::Rc;
 // use pango::FontDescription;
 
 const APP_ID: &str = "org.gtk_rs.ImageScenarioView2";
+const EXPORT_PATH  : &str = "preview.png";
 
-// get_seq /////////////////////////////////////////////////
-fn get_seq() -> i32 {
-    static COUNT: AtomicI32 = AtomicI32::new(1000);
-    COUNT.fetch_add(1, Ordering::SeqCst)
-}
 // load_css ////////////////////////////////////////////////
 fn load_css() {
     // Load the CSS file and add it to the provider
@@ -71,31 +126,89 @@ fn load_css() {
 }
 // main ////////////////////////////////////////////////////
 fn main() -> glib::ExitCode {
+    let args: Vec<String>= std::env::args().skip(1).collect();
+    if let Some(code) = cli::run(&args) {
+        return code;
+    }
     println!("--------");
     ////////////////////////////////////////////////////////
     // Create a new application
-    let app = Application::builder().application_id(APP_ID).build();
-    app.connect_startup(|_| load_css());
-    app.connect_activate(build_ui);
+    let app = Application::builder()
+        .application_id(APP_ID)
+        .flags(gio::ApplicationFlags::HANDLES_OPEN)
+        .build();
+    // the recovery slots of a previous run, offered with the first window
+    let pending: Rc<RefCell<Vec<Recovery>>> = Rc::default();
+    let pending_for_startup = pending.clone();
+    app.connect_startup(move |_| {
+        load_css();
+        *pending_for_startup.borrow_mut()= recovery::pending();
+    });
+    let pending_for_activate = pending.clone();
+    app.connect_activate(move |app| {
+        build_ui(app, None);
+        offer_recovery(app, &pending_for_activate);
+    });
+    app.connect_open(move |app, files, _| { // the files of the command line
+        for f in files {
+            build_ui(app, f.path());
+        }
+        offer_recovery(app, &pending);
+    });
     app.run()
 }
+// offer_recovery //////////////////////////////////////////
+/// ask to restore the documents which were not saved by a previous run, each one in a new window.
+/// "later" keeps them for the next start
+fn offer_recovery(app: &Application, pending: &RefCell<Vec<Recovery>>) {
+    let list= pending.take();
+    if list.is_empty() { return; }
+    let Some(window) = app.active_window() else { return };
+    let names: Vec<String>= list.iter().map(|r| r.name()).collect();
+    let dialog= AlertDialog::builder()
+        .message( format!("restore {} unsaved documents?", list.len()) )
+        .detail( names.join("\n") )
+        .buttons(["later", "discard", "restore"])
+        .cancel_button(0)
+        .default_button(2)
+        .build();
+    let app= app.clone();
+    let window_for_discard= window.clone();
+    dialog.choose(Some(&window), gio::Cancellable::NONE, move |result| match result {
+        Ok(1) => for r in list.iter() {
+            if let Err(e) = recovery::remove(&r.slot) {
+                document_file::show_error(&window_for_discard, &format!("{} is not discarded", r.name()), &e); }
+        },
+        Ok(2) => for r in list.iter() {
+            let file= build_ui(&app, None);
+            if let Err(e) = file.restore(r) {
+                if let Some(w) = app.active_window() {
+                    document_file::show_error(&w, &format!("restoring {} failed", r.name()), &e); }
+            }
+        },
+        _     => (),
+    });
+}
 // append_neighbors ////////////////////////////////////////
 fn append_neighbors(model: &gio::ListStore, sn: Rc<ScenarioNode>, seq: i32){
-    let obj= ScenarioNodeObject::new_from(sn.clone());
-    obj.set_seq(seq);
-    model.append( &obj );
-    if let Some(nbr) = (*sn.neighbor.borrow_mut()).as_ref(){
-        append_neighbors(model, nbr.clone(), seq+1);
+    let mut n  = Some(sn);
+    let mut seq= seq;
+    while let Some(x) = n {
+        let obj= ScenarioNodeObject::new_from(x.clone());
+        obj.set_seq(seq);
+        model.append( &obj );
+        n  = x.get_neighbor();
+        seq+= 1;
     }
 }
 // my_creator //////////////////////////////////////////////
 fn my_creator(obj: &Object) -> Option<ListModel>{
 
     let sn= obj.downcast_ref::<ScenarioNodeObject>().expect("ScenarioNodeObject is expected");
-    if let Some(c) = (*sn.get_node().child.borrow_mut()).as_ref() {
+    if let Some(c) = sn.get_node().get_child() {
         let model = gio::ListStore::new::<ScenarioNodeObject>();
 
-        append_neighbors(&model, c.clone(), 0);
+        append_neighbors(&model, c, 0);
 
         Some(model.into())
     } else {
@@ -104,27 +217,6 @@ fn my_creator(obj: &Object) -> Option<ListModel>{
 
 }
 
-// expander_to_store ///////////////////////////////////////
-
-// TODO: 空になった直後のexpanderを開こうとするとクラッシュする
-fn expander_to_store(e: &TreeExpander, depth: u32) -> gio::ListStore {
-    if depth == 0 {
-        e.parent().unwrap()
-            .parent().and_downcast::<ListView>().expect("ListView is expected")
-            .model().unwrap() // SelectionModel
-            .downcast::<SingleSelection>().expect("SingleSelection")
-            .model().unwrap() // TreeListModel
-            .downcast::<TreeListModel>().expect("TreeListModel")
-            .model()          // ListModel
-            .downcast::<gio::ListStore>().expect("ListStore")
-    } else {
-        e.list_row().unwrap()
-            .parent().unwrap()   // depthが0の場合はこのunwrapが失敗するので，parent...経由ででListViewを取得する
-            .children().unwrap() // ListModel
-            .downcast::<gio::ListStore>().expect("ListStore")
-    }
-}
-
 // label_drop_remove_style //////////////////////////////////////
 fn label_drop_remove_style(w: Widget, u: bool, l: bool) {
     if u { w.add_css_class   ("indicate_upper"); }
@@ -151,338 +243,356 @@ fn label_drop_remove_style(w: Widget, u: bool, l: bool) {
 // ダメな場合は，child/neighborを入れ替えて試行
 
 // expander_to_dest_member /////////////////////////////////
-fn expander_to_dest_member2(e: &TreeExpander, root_store: gio::ListStore)
-                            -> TreeManipulationHandle{
-    let dest_row= e.list_row().unwrap();
-    let dest_sno= dest_row
-        .item().and_downcast::<ScenarioNodeObject>().expect("sno is expd");
-    let dest_depth= dest_row.depth();
-    let dest_store= expander_to_store(e, dest_depth);
-
-    let dest_parent_row   = row_to_parent_row(&dest_row);
-    let dest_parent_store = row_to_parent_store(&dest_parent_row, &root_store);
-    let dest_parent_sno   = get_parent_sno(&dest_sno, &dest_parent_row, &dest_store);
-
-    TreeManipulationHandle{
-        bt           : dest_sno.get_bt().into(),
-        row          : Some(dest_row.clone().into()),
-        sno          : Some(dest_sno.clone().into()),
-        store        : Some(dest_store.clone().into()),
-        depth        : Cell::new(dest_row.depth()),
-        size         : Cell::new(dest_store.n_items()),
-        parent_row   : Some(dest_parent_row.clone().into()),
-        parent_sno   : Some(dest_parent_sno.into()),
-        parent_store : Some(dest_parent_store.into()),
-    }
+fn expander_to_dest_member2(e: &TreeExpander, root: &gio::ListStore)
+                            -> TreeResult<TreeManipulationHandle>{
+    row_to_member(&e.list_row().ok_or(TreeError::NoRow)?, root)
 }
 // src_value_to_src_member /////////////////////////////////
 fn src_value_to_src_member2(v: &Value) ->
-    (TreeManipulationHandle, gio::ListStore, Rc<OperationHistory>){
+    TreeResult<(TreeManipulationHandle, Rc<OperationHistory>)>{
 
-        let drag_obj = v.get::<ScenarioItemDragObject>().expect("scn itm drag obj is expd");
+        let drag_obj = v.get::<ScenarioItemDragObject>().map_err(|_| TreeError::NoDragObject)?;
+        let history  = drag_obj.get_history().ok_or(TreeError::NoDragObject)?;
+        let root     = drag_obj.get_root_store().ok_or(TreeError::NoDragObject)?;
 
         let src_row = drag_obj
-            .get_list_item()
-            .item().and_downcast::<TreeListRow>().expect("tlrow is expected");
+            .get_list_item().ok_or(TreeError::NoDragObject)?
+            .item().and_downcast::<TreeListRow>().ok_or(TreeError::NoRow)?;
         let src_sno = src_row
-            .item().and_downcast::<ScenarioNodeObject>().expect("sno is expected");
-        let src_depth = src_row.depth();
-        let src_store = expander_to_store(&drag_obj
-                                          .get_list_item()
-                                          .child().expect("child")
-                                          .downcast::<TreeExpander>().expect("TreeExpander"),
-                                          src_depth);
-
-        let src_parent_row   = row_to_parent_row(&src_row);
-        let src_parent_store = row_to_parent_store(&src_parent_row, &drag_obj.get_root_store());
-        let src_parent_sno   = get_parent_sno(&src_sno, &src_parent_row, &src_store);
+            .item().and_downcast::<ScenarioNodeObject>().ok_or(TreeError::NotNode)?;
+        let src_store = row_to_parent_store(&src_row, &root)?;
+
+        let src_parent_row   = row_to_parent_row(&src_row)?;
+        let src_parent_sno   = get_parent_sno(&src_sno, &src_parent_row, &src_store)?;
 
         let hdl = TreeManipulationHandle{
-            bt           : src_sno.get_bt().into(),
             row          : Some(src_row.clone().into()),
             sno          : Some(src_sno.clone().into()),
             store        : Some(src_store.clone().into()),
-            depth        : Cell::new(src_depth),
-            size         : Cell::new(src_store.n_items()),
-            parent_row   : Some(src_parent_row.clone().into()),
             parent_sno   : Some(src_parent_sno.into()),
-            parent_store : Some(src_parent_store.into()),
         };
-        (hdl, drag_obj.get_root_store(), drag_obj.get_history())
+        Ok( (hdl, history) )
 }
-// add_node_to_empty_store /////////////////////////////
-fn add_node_to_empty_store(a: Isv2Button, sno: &ScenarioNodeObject) {
-    a.get_store().insert( 0, sno );
+// add_node_to_root ////////////////////////////////////////
+fn add_node_to_root(a: Isv2Button, sno: &ScenarioNodeObject) -> TreeResult<()> {
+    insert_node_at( &a.get_history().tree_list_model(), &[0], sno.get_node() )?; // the root level, no row is touched
+    Ok(())
 }
 // isv2button_to_dest_member ///////////////////////////////
-fn row_to_parent_store(row: &TreeListRow, root: &gio::ListStore) -> gio::ListStore {
+fn row_to_parent_store(row: &TreeListRow, root: &gio::ListStore) -> TreeResult<gio::ListStore> {
     if row.depth() > 0 {
-        row.parent().unwrap()
-            .children().unwrap() // ListModel
-            .downcast::<gio::ListStore>().expect("ListStore")
+        row.parent().ok_or(TreeError::NoParentRow)?
+            .children() // ListModel, None while the parent is collapsed
+            .and_downcast::<gio::ListStore>().ok_or(TreeError::NoStore)
     } else {
-        root.clone()
+        Ok( root.clone() )
     }
 }
-fn row_to_parent_row(r: &TreeListRow) -> TreeListRow {
+fn row_to_parent_row(r: &TreeListRow) -> TreeResult<TreeListRow> {
     if r.depth() == 0 { // root-root
-        r.clone()
+        Ok( r.clone() )
     } else { // (Child &&, depth > 0) or neighbor
-        r.parent().unwrap()
+        r.parent().ok_or(TreeError::NoParentRow)
     }
 }
 
 fn get_parent_sno(sno: &ScenarioNodeObject,
                   parent_row: &TreeListRow,
-                  store: &gio::ListStore) -> ScenarioNodeObject {
+                  store: &gio::ListStore) -> TreeResult<ScenarioNodeObject> {
     if sno.get_bt() == BranchType::Child {
-        parent_row.item().and_downcast::<ScenarioNodeObject>().expect("sno is expd").clone()
+        parent_row.item().and_downcast::<ScenarioNodeObject>().ok_or(TreeError::NotNode)
     } else {
-        let parent_item = store.item( sno.get_seq() as u32 - 1).unwrap();
-        parent_item
-            .downcast_ref::<ScenarioNodeObject>().unwrap().clone()
+        check_seq(sno, store)?;
+        store.item( (sno.get_seq() - 1) as u32 ) // a neighbor is not at 0, otherwise the item is None
+            .and_downcast::<ScenarioNodeObject>().ok_or(TreeError::StaleRow(sno.get_id()))
     }
 }
-fn isv2button_to_dest_member4(b: &Isv2Button) ->
-    Result<TreeManipulationHandle, &'static str> {
-
-        if b.get_selection().selected_item().is_none() {
-            return Err("not selected"); }
-
-        let root_store= b.get_selection() // selection is a member of Isv2Button
-            .model().unwrap() // TreeListModel
-            .downcast::<TreeListModel>().expect("TreeListModel")
-            .model()          // ListModel
-            .downcast::<gio::ListStore>().expect("ListStore");
+// check_seq ///////////////////////////////////////////////
+/// the row of `sno` is at its seq in `store`
+fn check_seq(sno: &ScenarioNodeObject, store: &gio::ListStore) -> TreeResult<()> {
+    match store.item( sno.get_seq() as u32 ).and_downcast::<ScenarioNodeObject>() {
+        Some(s) if s == *sno => Ok(()),
+        _                    => Err(TreeError::StaleRow(sno.get_id())),
+    }
+}
+fn isv2button_to_dest_member4(b: &Isv2Button) -> TreeResult<TreeManipulationHandle> {
 
-        let obj               = b.get_selection().selected_item().unwrap();
-        let dest_row          = obj.downcast_ref::<TreeListRow>().expect("TreeListRow is expected");
-        let dest_sno          = dest_row.item().and_downcast::<ScenarioNodeObject>().expect("sno is expd");
-        let dest_store        = row_to_parent_store(dest_row, &root_store);
+        let obj= b.get_selection().selected_item().ok_or(TreeError::NotSelected)?;
 
-        let dest_parent_row   = row_to_parent_row(dest_row);
-        let dest_parent_store = row_to_parent_store(&dest_parent_row, &root_store);
-        let dest_parent_sno   = get_parent_sno(&dest_sno, &dest_parent_row, &dest_store);
+        let root= root_store( &b.get_history().tree_list_model() );
 
-        let hdl = TreeManipulationHandle{
-            bt           : dest_sno.get_bt().into(),
-            row          : Some(dest_row.clone().into()),
-            sno          : Some(dest_sno.into()),
-            store        : Some(dest_store.clone().into()),
-            depth        : Cell::new(dest_row.depth()),
-            size         : Cell::new(dest_store.n_items()),
-            parent_row   : Some(dest_parent_row.clone().into()),
-            parent_sno   : Some(dest_parent_sno.into()),
-            parent_store : Some(dest_parent_store.into()),
-        };
-        Ok( hdl )
+        row_to_member(obj.downcast_ref::<TreeListRow>().ok_or(TreeError::NoRow)?, &root)
     }
+// row_to_member ///////////////////////////////////////////
+/// the row, node, store and parent (or previous) node of `row`
+fn row_to_member(row: &TreeListRow, root: &gio::ListStore) -> TreeResult<TreeManipulationHandle> {
+    let sno       = row.item().and_downcast::<ScenarioNodeObject>().ok_or(TreeError::NotNode)?;
+    let store     = row_to_parent_store(row, root)?;
+
+    let parent_row= row_to_parent_row(row)?;
+    let parent_sno= get_parent_sno(&sno, &parent_row, &store)?;
+
+    Ok( TreeManipulationHandle{
+        row          : Some(row.clone().into()),
+        sno          : Some(sno.into()),
+        store        : Some(store.into()),
+        parent_sno   : Some(parent_sno.into()),
+    } )
+}
 // detect_descendant ///////////////////////////////////////
 fn detect_descendant(parent: &TreeListRow, child: &TreeListRow) -> bool {
-    if parent == child {
-        return true; }
-    if child.parent().is_none() {
-        return false; }
-    return detect_descendant(parent, &child.parent().unwrap());
+    let mut row= Some(child.clone());
+    while let Some(r) = row {
+        if r == *parent {
+            return true; }
+        row= r.parent();
+    }
+    false
+}
+// finish_drop /////////////////////////////////////////////
+/// a failed drop is reported to the history of the dragged row, the tree is not changed
+fn finish_drop(d: &DropTarget, v: &Value, result: TreeResult<()>) -> bool {
+    label_drop_remove_style( d.widget(), false, false );
+    match result {
+        Ok(()) => true,
+        Err(e) => {
+            match v.get::<ScenarioItemDragObject>().ok().and_then(|o| o.get_history()) {
+                Some(h) => h.report(&e),
+                None    => println!("{}", e),
+            }
+            false
+        }
+    }
 }
 
 // expander_drop_function //////////////////////////////////
 fn expander_drop_function(d: &DropTarget, v: &Value, _x: f64, y: f64) -> bool{
+    let upper= y < (d.widget().height()/2).into();
+    let result= d.widget().downcast::<TreeExpander>().map_err(|_| TreeError::NoRow)
+        .and_then(|e| expander_drop(&e, v, upper));
+    finish_drop(d, v, result)
+}
+fn expander_drop(e: &TreeExpander, v: &Value, upper: bool) -> TreeResult<()>{
+    let (src_hdl, history) =
+        src_value_to_src_member2(v)?;
+    let dest_hdl =
+        expander_to_dest_member2(e, &root_store(&history.tree_list_model()))?;
+    move_node(&history, &src_hdl, &dest_hdl, if upper { Placement::Before } else { Placement::After })
+}
+// label_drop_function /////////////////////////////////////
+fn label_drop_function(d: &DropTarget, v: &Value, _x: f64, y: f64) -> bool{
+    let upper= y < (d.widget().height()/2).into();
+    let result= d.widget().parent().and_downcast::<TreeExpander>().ok_or(TreeError::NoRow)
+        .and_then(|e| label_drop(&e, v, upper));
+    finish_drop(d, v, result)
+}
+fn label_drop(e: &TreeExpander, v: &Value, upper: bool) -> TreeResult<()>{
+    let (src_hdl, history) =
+        src_value_to_src_member2(v)?;
+    let dest_hdl =
+        expander_to_dest_member2(e, &root_store(&history.tree_list_model()))?;
+    move_node(&history, &src_hdl, &dest_hdl, if upper { Placement::Before } else { Placement::FirstChild })
+}
+// move_node ///////////////////////////////////////////////
+/// move the row of `src_hdl` to `dest_hdl`, for a drop and "move to" (move_dialog.rs).
+/// LastChild is resolved by the caller to After the last child or FirstChild
+fn move_node(history : &OperationHistory,
+             src_hdl : &TreeManipulationHandle,
+             dest_hdl: &TreeManipulationHandle,
+             placement: Placement) -> TreeResult<()>{
     // obtain src
-    let (src_hdl, root_store, history) =
-        src_value_to_src_member2(v);
-    let src_row    = src_hdl.row.as_ref().unwrap();
-    let src_sno    = src_hdl.sno.as_ref().unwrap();
-    let src_store  = src_hdl.store.as_ref().unwrap();
+    let src_row    = src_hdl.get_row()?;
+    let src_sno    = src_hdl.get_sno()?;
+    let src_store  = src_hdl.get_store()?;
 
     // obtain dest
-    let dest_hdl =
-        expander_to_dest_member2( &d.widget()
-                                   .downcast::<TreeExpander>()
-                                   .expect("expander is expected"),
-                                   root_store);
-    let dest_sno        = dest_hdl.sno.as_ref().unwrap();
-    let dest_row        = dest_hdl.row.as_ref().unwrap();
-    let dest_parent_sno = dest_hdl.parent_sno.as_ref().unwrap();
-    let dest_store      = dest_hdl.store.as_ref().unwrap();
-
-    // check: move to descendant -> ignore
-    if detect_descendant(&src_row, &dest_row) {
-        println!("moving to descendant is ignored");
-        label_drop_remove_style( d.widget(), false, false );
-        return false;
-    }
-
-    let new_node= ScenarioNodeObject::new_from( src_sno.get_node() );
+    let tlm= history.tree_list_model();
+    let dest_sno        = dest_hdl.get_sno()?;
+    let dest_row        = dest_hdl.get_row()?;
+    let dest_parent_sno = dest_hdl.get_parent_sno()?;
+    let dest_store      = dest_hdl.get_store()?;
+    let dest_children   = match dest_row.children() { // None while dest is collapsed
+        Some(m) => Some( m.downcast::<gio::ListStore>().map_err(|_| TreeError::NoStore)? ),
+        None    => None,
+    };
+
+    // check before any change: move to descendant, rows out of date
+    if detect_descendant(src_row, dest_row) {
+        return Err(TreeError::Descendant(src_sno.get_id())); }
+    check_seq(src_sno, src_store)?;
+    check_seq(dest_sno, dest_store)?;
+    check_store(src_store)?; // adj_seq() does not fail half way
+    check_store(dest_store)?;
+    if let Some(s) = &dest_children {
+        check_store(s)?; }
+
+    let new_node  = ScenarioNodeObject::new_from( src_sno.get_node() );
+    let src_path  = src_sno.get_node().index_path();
+    let src_parent= src_sno.get_node().get_parent_node();
 
     let mut h= OperationHistoryItem::default();
 
-    if y < (d.widget().height()/2).into() { // upper half
-        if dest_sno.get_bt() == BranchType::Child { // parent に mv_to_child
-            if (*dest_sno.get_node().parent.borrow_mut()).upgrade().is_some() {
-                h.ope = Operation::MvToParentChild.into();
-                ScenarioNode::mv_to_child(dest_parent_sno.get_node(), new_node.get_node());
-            } else {
-                h.ope = Operation::MvToParent.into();
-                ScenarioNode::mv_to_parent(dest_sno.get_node(), new_node.get_node());
+    match placement {
+        Placement::Before => { // upper half
+            if dest_sno.get_bt() == BranchType::Child { // parent に mv_to_child
+                if dest_sno.get_node().get_parent().is_some() {
+                    h.ope = Operation::MvToParentChild.into();
+                    ScenarioNode::mv_to_child(dest_parent_sno.get_node(), new_node.get_node());
+                } else {
+                    h.ope = Operation::MvToParent.into();
+                    ScenarioNode::mv_to_parent(dest_sno.get_node(), new_node.get_node());
+                }
+            } else { // parent に mv_to_neighbor
+                h.ope = Operation::MvToParentNeighbor.into();
+                ScenarioNode::mv_to_neighbor(dest_parent_sno.get_node(), new_node.get_node());
             }
-        } else { // parent に mv_to_neighbor
-            h.ope = Operation::MvToParentNeighbor.into();
-            ScenarioNode::mv_to_neighbor(dest_parent_sno.get_node(), new_node.get_node());
-        }
-        new_node.set_seq( dest_sno.get_seq() );
-        adj_seq( &dest_store, dest_sno.get_seq(), 1 );
-        dest_store.insert( (dest_sno.get_seq() as u32) - 1, &new_node ); // -1: because +1 at previouse adj_seq()
-    } else { // lower-half -> dest に mv_to_neighbor
-        h.ope= Operation::MvToDestNeighbor.into();
-        ScenarioNode::mv_to_neighbor(dest_sno.get_node(), new_node.get_node());
-        new_node.set_seq( dest_sno.get_seq() + 1 );
-        adj_seq( &dest_store, dest_sno.get_seq() + 1, 1 );
-        dest_store.insert( (dest_sno.get_seq() as u32) + 1, &new_node );
+            new_node.set_seq( dest_sno.get_seq() );
+            adj_seq( dest_store, dest_sno.get_seq(), 1 )?;
+            dest_store.insert( (dest_sno.get_seq() as u32) - 1, &new_node ); // -1: because +1 at previouse adj_seq()
+        },
+        Placement::After => { // lower half of expander -> dest に mv_to_neighbor
+            h.ope= Operation::MvToDestNeighbor.into();
+            ScenarioNode::mv_to_neighbor(dest_sno.get_node(), new_node.get_node());
+            new_node.set_seq( dest_sno.get_seq() + 1 );
+            adj_seq( dest_store, dest_sno.get_seq() + 1, 1 )?;
+            dest_store.insert( (dest_sno.get_seq() as u32) + 1, &new_node );
+        },
+        Placement::FirstChild | Placement::LastChild => { // lower half of label -> dest child, LastChild is resolved before
+            h.ope= Operation::MvToDestChild.into();
+            ScenarioNode::mv_to_child(dest_sno.get_node(), new_node.get_node());
+            new_node.set_seq( 0 );
+            if let Some(s) = dest_children {
+                adj_seq( &s, 0, 1 )?;
+                s.insert( 0, &new_node );
+            }
+            else {
+                let dest_node= ScenarioNodeObject::new_from( dest_sno.get_node() );
+                dest_node.set_seq( dest_sno.get_seq() );
+                dest_store.remove( dest_sno.get_seq() as u32 );
+                dest_store.insert( dest_sno.get_seq() as u32, &dest_node );
+            }
+        },
     }
     // remove src
-    adj_seq(&src_store, src_sno.get_seq() + 1, -1);
+    adj_seq(src_store, src_sno.get_seq() + 1, -1)?;
     src_store.remove( src_sno.get_seq() as u32 );
+    refresh_emptied(&tlm, src_store, src_parent);
 
-    label_drop_remove_style( d.widget(), false, false );
-
-    h.src     = src_hdl;
-    h.dest    = dest_hdl;
-    h.new_sno = Some(new_node.clone().into());
-    history.push(h.clone());
+    h.id   = new_node.get_id();
+    h.src  = src_path;
+    h.dest = new_node.get_node().index_path();
+    history.push(h);
 
-    true
+    Ok(())
 }
-// label_drop_function /////////////////////////////////////
-fn label_drop_function(d: &DropTarget, v: &Value, _x: f64, y: f64) -> bool{
-
-    // obtain src
-    let (src_hdl, root_store, history) =
-        src_value_to_src_member2(v);
-    let src_row    = src_hdl.row.as_ref().unwrap();
-    let src_sno    = src_hdl.sno.as_ref().unwrap();
-    let src_store  = src_hdl.store.as_ref().unwrap();
-
-    // obtain dest
-    let dest_hdl =
-        expander_to_dest_member2( &d.widget()
-                                   .parent().and_downcast::<TreeExpander>()
-                                   .expect("expander is expected"),
-                                   root_store);
-    let dest_sno        = dest_hdl.sno.as_ref().unwrap();
-    let dest_row        = dest_hdl.row.as_ref().unwrap();
-    let dest_parent_sno = dest_hdl.parent_sno.as_ref().unwrap();
-    let dest_store      = dest_hdl.store.as_ref().unwrap();
-
-    // check: move to descendant -> ignore
-    if detect_descendant(&src_row, &dest_row) {
-        println!("moving to descendant is ignored");
-        label_drop_remove_style( d.widget(), false, false );
-        return false;
+// move_to /////////////////////////////////////////////////
+/// "move to" of move_dialog.rs: the rows of the nodes are made by expanding their ancestors,
+/// then moved by move_node as a drop does
+fn move_to(history: &OperationHistory, src_id: i32, dest_id: i32, placement: Placement) -> TreeResult<()>{
+    let tlm = history.tree_list_model();
+    let root= root_store(&tlm);
+    let src = history.lookup(src_id).ok_or(TreeError::NoId(src_id))?;
+    let dest= history.lookup(dest_id).ok_or(TreeError::NoId(dest_id))?;
+    if stays(&src, &dest, placement) {
+        return Ok(()); } // already there, move_node would record a move onto itself
+    let (dest, placement)= match (placement, dest.get_children().last()) {
+        (Placement::LastChild, Some(last)) => (last.clone(), Placement::After),
+        (Placement::LastChild, None)       => (dest, Placement::FirstChild),
+        (p, _)                             => (dest, p),
+    };
+    let src_row = expanded_row(&tlm, &src.index_path())?;
+    let dest_row= expanded_row(&tlm, &dest.index_path())?;
+    move_node(history, &row_to_member(&src_row, &root)?, &row_to_member(&dest_row, &root)?, placement)
+}
+/// the row at `path` with its ancestors expanded
+fn expanded_row(tlm: &TreeListModel, path: &[usize]) -> TreeResult<TreeListRow>{
+    for i in 1..path.len() {
+        if let Some(row) = row_at_path(tlm, &path[..i]) {
+            row.set_expanded(true); }
     }
+    row_at_path(tlm, path).ok_or_else(|| TreeError::NoNode(path.to_vec()))
+}
 
-    let new_node= ScenarioNodeObject::new_from( src_sno.get_node() );
-
-    let mut h= OperationHistoryItem::default();
-
-    if y < (d.widget().height()/2).into() { // upper-half
-        if dest_sno.get_bt() == BranchType::Child { // parent に mv_to_child
-            if (*dest_sno.get_node().parent.borrow_mut()).upgrade().is_some() {
-                h.ope = Operation::MvToParentChild.into();
-                ScenarioNode::mv_to_child(dest_parent_sno.get_node(), new_node.get_node());
-            } else {
-                h.ope = Operation::MvToParent.into();
-                ScenarioNode::mv_to_parent(dest_sno.get_node(), new_node.get_node());
-            }
-        } else { // parent に mv_to_neighbor
-            h.ope = Operation::MvToParentNeighbor.into();
-            ScenarioNode::mv_to_neighbor(dest_parent_sno.get_node(), new_node.get_node());
-        }
-        new_node.set_seq( dest_sno.get_seq() );
-        adj_seq( &dest_store, dest_sno.get_seq(), 1 );
-        dest_store.insert( (dest_sno.get_seq() as u32) - 1, &new_node ); // -1: because +1 at previouse adj_seq()
-
-        // remove src
-        adj_seq(&src_store, src_sno.get_seq() + 1, -1);
-        src_store.remove( src_sno.get_seq() as u32 );
-    } else { // lower-half -> dest child
-        h.ope= Operation::MvToDestChild.into();
-        ScenarioNode::mv_to_child(dest_sno.get_node(), new_node.get_node());
-        new_node.set_seq( 0 );
-        if let Some(m) = dest_row.children(){
-            let s= m.downcast::<gio::ListStore>().expect("ListStore");
-            adj_seq( &s, 0, 1 );
-            s.insert( 0, &new_node );
-        }
-        else {
-            let dest_node= ScenarioNodeObject::new_from( dest_sno.get_node() );
-            dest_node.set_seq( dest_sno.get_seq() );
-            dest_store.remove( dest_sno.get_seq() as u32 );
-            dest_store.insert( dest_sno.get_seq() as u32, &dest_node );
-        }
-
-        // remove src
-        adj_seq(&src_store, src_sno.get_seq() + 1, -1);
-        src_store.remove( src_sno.get_seq() as u32 );
+/// the ListItem of a bound row -> its node object and the handler of the seq
+type SeqHandlers = HashMap<Object, (ScenarioNodeObject, glib::SignalHandlerId)>;
+
+// set_row_label ///////////////////////////////////////////
+/// "id:3, seq:0, mat" with the notes of the item and the conflict of the last import
+fn set_row_label(label: &Label, sno: &ScenarioNodeObject, sources: &SourceCache, marks: &ConflictMarks) {
+    label.set_label( &( "id:".to_owned() +
+                         &sno.get_id().to_string() +
+                         ", seq:" +
+                         &sno.get_seq().to_string() +
+                         ", " +
+                         sno.get_node().value.borrow().kind().name() ) );
+    // markup error, state of src
+    for c in ["markup_error", "src_stale", "src_missing", "merge_conflict"] {
+        label.remove_css_class(c); }
+    for (note, css_class) in row_notes(&sno.get_node().value.borrow(), sources) {
+        label.set_label( &(label.label().to_string() + ", " + &note) );
+        label.add_css_class(css_class);
+    }
+    if let Some(msg) = marks.borrow().get(&sno.get_id()) {
+        label.set_label( &(label.label().to_string() + ", conflict: " + msg) );
+        label.add_css_class("merge_conflict");
     }
-
-    label_drop_remove_style( d.widget(), false, false );
-
-    h.src     = src_hdl;
-    h.dest    = dest_hdl;
-    h.new_sno = Some(new_node.clone().into());
-    history.push(h.clone());
-
-    true
 }
 
+// demo_mat ////////////////////////////////////////////////
+fn demo_mat(x: f64, txt: &str, v: bool) -> Item {
+    Item::Mat( Mat{
+        col   : Color::rgb(32, 32, 64),
+        pos   : Position::px(x, 40.0),
+        dim   : Dimension::px(560.0, 640.0),
+        r     : 16,
+        a     : 200,
+        src   : None,
+        lbl   : None,
+        lblref: None,
+        name  : String::new(),
+        txt   : txt.to_string(),
+        v,
+    } )
+}
+// build_demo //////////////////////////////////////////////
+/// the tree of an untitled window
+fn build_demo(model: &gio::ListStore, doc: &ScenarioDocument) {
+    let o_node1   = ScenarioNodeObject::new_with_seq_id(0, 1  );
+    let o_node2   = ScenarioNodeObject::new_with_seq_id(0, 2  ); ScenarioNode::mv_to_neighbor( o_node1.get_node(),   o_node2.get_node() );
+    let o_node3   = ScenarioNodeObject::new_with_seq_id(0, 3  ); ScenarioNode::mv_to_neighbor( o_node2.get_node(),   o_node3.get_node() );
+    let o_node31  = ScenarioNodeObject::new_with_seq_id(0, 31 ); ScenarioNode::mv_to_child   ( o_node3.get_node(),   o_node31.get_node() );
+    let o_node32  = ScenarioNodeObject::new_with_seq_id(0, 32 ); ScenarioNode::mv_to_neighbor( o_node31.get_node(),  o_node32.get_node() );
+    let o_node33  = ScenarioNodeObject::new_with_seq_id(0, 33 ); ScenarioNode::mv_to_neighbor( o_node32.get_node(),  o_node33.get_node() );
+    let o_node331 = ScenarioNodeObject::new_with_seq_id(0, 331); ScenarioNode::mv_to_child   ( o_node33.get_node(),  o_node331.get_node() );
+    let o_node332 = ScenarioNodeObject::new_with_seq_id(0, 332); ScenarioNode::mv_to_neighbor( o_node331.get_node(), o_node332.get_node() );
+    let o_node333 = ScenarioNodeObject::new_with_seq_id(0, 333); ScenarioNode::mv_to_neighbor( o_node332.get_node(), o_node333.get_node() );
+    let o_node34  = ScenarioNodeObject::new_with_seq_id(0, 34 ); ScenarioNode::mv_to_neighbor( o_node33.get_node(),  o_node34.get_node() );
+    let o_node35  = ScenarioNodeObject::new_with_seq_id(0, 35 ); ScenarioNode::mv_to_neighbor( o_node34.get_node(),  o_node35.get_node() );
+    let o_node36  = ScenarioNodeObject::new_with_seq_id(0, 36 ); ScenarioNode::mv_to_neighbor( o_node35.get_node(),  o_node36.get_node() );
+    let o_node361 = ScenarioNodeObject::new_with_seq_id(0, 361); ScenarioNode::mv_to_child   ( o_node36.get_node(),  o_node361.get_node() );
+    let o_node362 = ScenarioNodeObject::new_with_seq_id(0, 362); ScenarioNode::mv_to_neighbor( o_node361.get_node(), o_node362.get_node() );
+    let o_node363 = ScenarioNodeObject::new_with_seq_id(0, 363); ScenarioNode::mv_to_neighbor( o_node362.get_node(), o_node363.get_node() );
+    let o_node4   = ScenarioNodeObject::new_with_seq_id(0, 4  ); ScenarioNode::mv_to_neighbor( o_node3.get_node(),   o_node4.get_node() );
+    let o_node5   = ScenarioNodeObject::new_with_seq_id(0, 5  ); ScenarioNode::mv_to_neighbor( o_node4.get_node(),   o_node5.get_node() );
+    let o_node6   = ScenarioNodeObject::new_with_seq_id(0, 6  ); ScenarioNode::mv_to_neighbor( o_node5.get_node(),   o_node6.get_node() );
+
+    o_node331.set_vaue( demo_mat(680.0, "[ruby=わがはい]吾輩[/ruby]は猫である。[wait 500]名前はまだ無い。「どこで生れたか」とんと[b]見当[/b]がつかぬ。", true) );
+    o_node332.set_vaue( demo_mat(40.0, "I am a cat. As yet I have [color=#f80]no name[/color].", false) );
+
+    doc.assign_unique_ids( Some(o_node1.get_node()) ); // counter starts after the ids of the demo
+    append_neighbors( model, o_node1.get_node(), 0);
+}
 // build_ui ////////////////////////////////////////////////
-fn build_ui(app: &Application) {
+/// a window of the document of `path`, or of the demo when None
+fn build_ui(app: &Application, path: Option<PathBuf>) -> Rc<DocumentFile> {
 
     let model = gio::ListStore::new::<ScenarioNodeObject>();
-
-    let o_node1   = ScenarioNodeObject::new_with_seq_id(0, 1  );
-    let o_node2   = ScenarioNodeObject::new_with_seq_id(0, 2  ); o_node2.set_parent( o_node1.get_node() ); o_node2.set_bt(BranchType::Neighbor);
-    let o_node3   = ScenarioNodeObject::new_with_seq_id(0, 3  ); o_node3.set_parent( o_node2.get_node() ); o_node3.set_bt(BranchType::Neighbor);
-    let o_node31  = ScenarioNodeObject::new_with_seq_id(0, 31 ); o_node31.set_parent( o_node3.get_node() ); o_node31.set_bt(BranchType::Child);
-    let o_node32  = ScenarioNodeObject::new_with_seq_id(0, 32 ); o_node32.set_parent( o_node31.get_node() ); o_node32.set_bt(BranchType::Neighbor);
-    let o_node33  = ScenarioNodeObject::new_with_seq_id(0, 33 ); o_node33.set_parent( o_node32.get_node() ); o_node33.set_bt(BranchType::Neighbor);
-    let o_node331 = ScenarioNodeObject::new_with_seq_id(0, 331); o_node331.set_parent( o_node33.get_node() ); o_node331.set_bt(BranchType::Child);
-    let o_node332 = ScenarioNodeObject::new_with_seq_id(0, 332); o_node332.set_parent( o_node331.get_node() ); o_node332.set_bt(BranchType::Neighbor);
-    let o_node333 = ScenarioNodeObject::new_with_seq_id(0, 333); o_node333.set_parent( o_node332.get_node() ); o_node333.set_bt(BranchType::Neighbor);
-    let o_node34  = ScenarioNodeObject::new_with_seq_id(0, 34 ); o_node34.set_parent( o_node33.get_node() ); o_node34.set_bt(BranchType::Neighbor);
-    let o_node35  = ScenarioNodeObject::new_with_seq_id(0, 35 ); o_node35.set_parent( o_node34.get_node() ); o_node35.set_bt(BranchType::Neighbor);
-    let o_node36  = ScenarioNodeObject::new_with_seq_id(0, 36 ); o_node36.set_parent( o_node35.get_node() ); o_node36.set_bt(BranchType::Neighbor);
-    let o_node361 = ScenarioNodeObject::new_with_seq_id(0, 361); o_node361.set_parent( o_node36.get_node() ); o_node361.set_bt(BranchType::Child);
-    let o_node362 = ScenarioNodeObject::new_with_seq_id(0, 362); o_node362.set_parent( o_node361.get_node() ); o_node362.set_bt(BranchType::Neighbor);
-    let o_node363 = ScenarioNodeObject::new_with_seq_id(0, 363); o_node363.set_parent( o_node362.get_node() ); o_node363.set_bt(BranchType::Neighbor);
-    let o_node4   = ScenarioNodeObject::new_with_seq_id(0, 4  ); o_node4.set_parent( o_node3.get_node() ); o_node4.set_bt(BranchType::Neighbor);
-    let o_node5   = ScenarioNodeObject::new_with_seq_id(0, 5  ); o_node5.set_parent( o_node4.get_node() ); o_node5.set_bt(BranchType::Neighbor);
-    let o_node6   = ScenarioNodeObject::new_with_seq_id(0, 6  ); o_node6.set_parent( o_node5.get_node() ); o_node6.set_bt(BranchType::Neighbor);
-
-    o_node1.set_neighbor( o_node2.get_node() );
-    o_node2.set_neighbor( o_node3.get_node() );
-    o_node3.set_neighbor( o_node4.get_node() );
-    o_node4.set_neighbor( o_node5.get_node() );
-    o_node5.set_neighbor( o_node6.get_node() );
-
-    o_node3.set_child    ( o_node31.get_node() );
-    o_node31.set_neighbor( o_node32.get_node() );
-    o_node32.set_neighbor( o_node33.get_node() );
-
-    o_node33.set_child( o_node331.get_node() );
-    o_node331.set_neighbor( o_node332.get_node() );
-    o_node332.set_neighbor( o_node333.get_node() );
-
-    o_node36.set_child( o_node361.get_node() );
-    o_node361.set_neighbor( o_node362.get_node() );
-    o_node362.set_neighbor( o_node363.get_node() );
-
-    o_node33.set_neighbor( o_node34.get_node() );
-    o_node34.set_neighbor( o_node35.get_node() );
-    o_node35.set_neighbor( o_node36.get_node() );
-
-    append_neighbors( &model, o_node1.get_node(), 0);
+    let doc = Rc::new(ScenarioDocument::new());
+    if path.is_none() {
+        build_demo(&model, &doc);
+    }
+    // the asset root and the external texts are relative to the scenario file
+    let scenario_path = path.clone().unwrap_or_default();
 
     let tree_list_model = TreeListModel::new(model,
                                              false /* passthrough */,
@@ -494,10 +604,22 @@ fn build_ui(app: &Application) {
     let factory = SignalListItemFactory::new();
     let list_view = ListView::new(Some(selection_model.clone()), Some(factory.clone()));
 
-    let history = OperationHistory::new(list_view.clone());
+    let history = OperationHistory::new(list_view.clone(), doc);
     let history = Rc::new(history);
     let history_for_factory = history.clone();
 
+    // external texts of the mats
+    let sources = SourceCache::new( scenario_path.parent().unwrap_or(Path::new("")) );
+    let sources_for_factory = sources.clone();
+
+    // the conflicts of the last import, by node id
+    let marks: ConflictMarks = Default::default();
+    let marks_for_factory = marks.clone();
+
+    // the seq handler of each bound row, the factory is shared by the panes of the split view
+    let seq_handlers: Rc<RefCell<SeqHandlers>> = Default::default();
+    let seq_handlers_for_bind = seq_handlers.clone();
+
     // configuring factory /////////////////////////////////
     // setup handler ///////////////////////////////////////
     factory.connect_setup(move |_, list_item| {
@@ -532,10 +654,14 @@ fn build_ui(app: &Application) {
             .item()
             .and_downcast::<ScenarioNodeObject>()
             .expect("ScenarioNodeObject is expected");
-        label.set_label( &( "id:".to_owned() +
-                             &scn_object.get_id().to_string() +
-                             ", seq:" +
-                             &scn_object.get_seq().to_string() ) );
+        set_row_label(&label, &scn_object, &sources_for_factory, &marks_for_factory);
+        // adj_seq changes the seq without re-binding the row, the label follows it in every pane
+        let (label_for_seq, sources_for_seq, marks_for_seq)=
+            (label.clone(), sources_for_factory.clone(), marks_for_factory.clone());
+        let handler= scn_object.connect_seq_notify(move |o| {
+            set_row_label(&label_for_seq, o, &sources_for_seq, &marks_for_seq); });
+        if let Some((o, h)) = seq_handlers_for_bind.borrow_mut().insert(list_item.clone(), (scn_object.clone(), handler)) {
+            o.disconnect(h); }
         label.set_xalign(0.0);
         label.set_vexpand(true); label.set_hexpand(true);
 
@@ -576,11 +702,8 @@ fn build_ui(app: &Application) {
         // Expander(リスト行)に対するドロップ(notラベル部分)
         let drop_target2= DropTarget::new( ScenarioItemDragObject::static_type(), DragAction::COPY);
         drop_target2.connect_motion( |d, x, y|{
-            let c=
-                d.widget()
-                .downcast::<TreeExpander>()
-                .expect("expander is expected")
-                .child().unwrap().allocation();
+            let Some(c) = d.widget().downcast::<TreeExpander>().ok()
+                .and_then(|e| e.child()).map(|c| c.allocation()) else { return DragAction::COPY };
             let x32 = x as i32;
             let y32 = y as i32;
 
@@ -599,6 +722,12 @@ fn build_ui(app: &Application) {
         expander.add_controller(drop_target2);
     });
 
+    // unbind handler //////////////////////////////////////
+    factory.connect_unbind(move |_, list_item| {
+        if let Some((o, h)) = seq_handlers.borrow_mut().remove(list_item) {
+            o.disconnect(h); }
+    });
+
     let drop_target= DropTarget::new( ListItem::static_type(), DragAction::COPY);
     drop_target.connect_drop(|d, v, x, y|{
         println!("dropped! d:{:?}, dv:{:?}, v:{:?}, x:{:?}, y:{:?}",
@@ -614,6 +743,27 @@ fn build_ui(app: &Application) {
     //list_view.set_enable_rubberband(true);
     list_view.set_show_separators(true);
 
+    // a source file is changed: the rows of the mats which refer to it
+    let history_for_sources  = Rc::downgrade(&history);
+    let selection_for_sources= selection_model.clone();
+    let sources_weak         = Rc::downgrade(&sources);
+    sources.connect_changed(move |path| {
+        let (Some(h), Some(sources)) = (history_for_sources.upgrade(), sources_weak.upgrade()) else { return };
+        let tlm     = h.tree_list_model();
+        let selected= selected_node(&selection_for_sources);
+        let changed: Vec<Vec<usize>>= scenario_query::pre_order_forest( first_root(&tlm) )
+            .filter(|n| match &*n.value.borrow() {
+                Item::Mat(m) | Item::Pmat(m) => m.src.as_ref().is_some_and(|s| sources.refers_to(s, path)),
+                _                            => false,
+            })
+            .map(|n| n.index_path())
+            .collect();
+        for p in changed.iter() {
+            refresh_row(&tlm, p); }
+        if let Some(row) = selected.and_then(|n| row_at_path(&tlm, &n.index_path())) {
+            selection_for_sources.set_selected( row.position() ); }
+    });
+
     let scrolled_window = ScrolledWindow::builder()
         .hscrollbar_policy(PolicyType::Never) // Disable horizontal scrolling
         .min_content_width(320)
@@ -621,36 +771,58 @@ fn build_ui(app: &Application) {
         .child(&list_view)
         .build();
 
+    // split view //////////////////////////////////////////
+    // a second ListView of the same TreeListModel and factory: the rows are shared, so an edit in one pane
+    // is shown in the other at once and a row is dragged from one pane to the other.
+    // it has its own selection and scroll, the buttons work on the selection of the first pane
+    let split_view = ListView::new(Some(SingleSelection::new(selection_model.model())), Some(factory.clone()));
+    split_view.set_show_separators(true);
+    let split_window = ScrolledWindow::builder()
+        .hscrollbar_policy(PolicyType::Never)
+        .min_content_width(320)
+        .min_content_height(160)
+        .child(&split_view)
+        .visible(false)
+        .build();
+    let split_button = ToggleButton::with_label("split");
+    let split_window_for_button = split_window.clone();
+    split_button.connect_toggled(move |b| split_window_for_button.set_visible(b.is_active()) );
+
     // remove //////////////////////////////////////////////
     let remove_button = Isv2Button::with_label_selection_history("rm",
                                                                  selection_model.clone(),
                                                                  history.clone());
     remove_button.connect_clicked(move |a| {
-        if let Ok(hdl) = isv2button_to_dest_member4(a){
-            let h= OperationHistoryItem::new_from_handle(Operation::Remove, hdl);
-            a.get_history().push(h.clone());
-            remove_node(h.src.store.unwrap().as_ref(), h.src.sno.unwrap().as_ref());
-        } else {
-            println!("empty!");
-        }
+        let result= isv2button_to_dest_member4(a).and_then(|hdl| {
+            let (src_sno, src_store)= (hdl.get_sno()?, hdl.get_store()?);
+            check_seq(src_sno, src_store)?;
+            let src_parent= src_sno.get_node().get_parent_node();
+            let h= OperationHistoryItem::new_remove(&src_sno.get_node());
+            remove_node(src_store, src_sno)?;
+            refresh_emptied(&a.get_history().tree_list_model(), src_store, src_parent);
+            a.get_history().push(h);
+            Ok(())
+        });
+        if let Err(e) = result {
+            a.get_history().report(&e); }
     });
     // add_neighbor ////////////////////////////////////////
     let add_neighbor_button = Isv2Button::with_label_selection_history("add(n)",
                                                                        selection_model.clone(),
                                                                        history.clone());
     add_neighbor_button.connect_clicked(move |a| {
-        let new_node = ScenarioNodeObject::new_with_seq_id(0, get_seq());
-
-        if let Ok(hdl) = isv2button_to_dest_member4(a){
-            add_neighbor( hdl.sno.as_ref().unwrap().as_ref(), &new_node, hdl.store.as_ref().unwrap().as_ref() );
-            let mut h= OperationHistoryItem::new_from_handle(Operation::AddNeighbor, hdl);
-            h.new_sno= Some( Rc::new(new_node.clone()) );
-            a.get_history().push(h);
-        } else {
-            let root_store= a.get_store();
-            let h= OperationHistoryItem::new_with_root_store(Operation::AddRoot, &root_store, &new_node);
-            a.get_history().push(h);
-            add_node_to_empty_store(a.clone(), &new_node);
+        let new_node = ScenarioNodeObject::new_with_seq_id(0, a.get_history().document().alloc_id());
+
+        let result= isv2button_to_dest_member4(a).and_then(|hdl| {
+            add_neighbor( hdl.get_sno()?, &new_node, hdl.get_store()? )?;
+            Ok(Operation::AddNeighbor)
+        }).or_else(|e| match e {
+            TreeError::NotSelected => add_node_to_root(a.clone(), &new_node).map(|_| Operation::AddRoot),
+            e                      => Err(e),
+        });
+        match result {
+            Ok(ope) => a.get_history().push( OperationHistoryItem::new_add(ope, &new_node.get_node()) ),
+            Err(e)  => a.get_history().report(&e),
         }
     });
     // add_child ///////////////////////////////////////////
@@ -658,20 +830,152 @@ fn build_ui(app: &Application) {
                                                                     selection_model.clone(),
                                                                     history.clone());
     add_child_button.connect_clicked(move |a| {
-        let new_node = ScenarioNodeObject::new_with_seq_id(0, get_seq());
-        if let Ok(hdl) = isv2button_to_dest_member4(a){
-            add_child( hdl.sno.as_ref().unwrap().as_ref(),
-                       &new_node,
-                       hdl.row.as_ref().unwrap().as_ref(),
-                       hdl.store.as_ref().unwrap().as_ref() );
-            let mut h= OperationHistoryItem::new_from_handle(Operation::AddChild, hdl);
-            h.new_sno= Some( Rc::new(new_node.clone()) );
-            a.get_history().push(h);
-        } else {
-            let root_store= a.get_store();
-            let h= OperationHistoryItem::new_with_root_store(Operation::AddRoot, &root_store, &new_node);
-            a.get_history().push(h);
-            add_node_to_empty_store(a.clone(), &new_node);
+        let new_node = ScenarioNodeObject::new_with_seq_id(0, a.get_history().document().alloc_id());
+        let result= isv2button_to_dest_member4(a).and_then(|hdl| {
+            let (dest_sno, dest_row, dest_store)= (hdl.get_sno()?, hdl.get_row()?, hdl.get_store()?);
+            add_child( dest_sno, &new_node, dest_row, dest_store )?;
+            Ok(Operation::AddChild)
+        }).or_else(|e| match e {
+            TreeError::NotSelected => add_node_to_root(a.clone(), &new_node).map(|_| Operation::AddRoot),
+            e                      => Err(e),
+        });
+        match result {
+            Ok(ope) => a.get_history().push( OperationHistoryItem::new_add(ope, &new_node.get_node()) ),
+            Err(e)  => a.get_history().report(&e),
+        }
+    });
+    // convert /////////////////////////////////////////////
+    let kind_names: Vec<&str>= ItemKind::ALL.iter().map(|k| k.name()).collect();
+    let kind_dropdown = DropDown::from_strings(&kind_names);
+    let kind_dropdown_for_convert = kind_dropdown.clone();
+    let convert_button = Isv2Button::with_label_selection_history("convert",
+                                                                  selection_model.clone(),
+                                                                  history.clone());
+    convert_button.connect_clicked(move |a| {
+        let to= ItemKind::ALL[ kind_dropdown_for_convert.selected() as usize ];
+        match isv2button_to_dest_member4(a).and_then(|hdl| Ok(hdl.get_sno()?.get_node())) {
+            Ok(node) => {
+                if let Err(e) = item_convert::convert(&a.get_history(), &node.index_path(), to) {
+                    let targets: Vec<&str>= item_convert::conversion_targets(&node).iter().map(|k| k.name()).collect();
+                    let possible= if targets.is_empty() { String::from("none") } else { targets.join(", ") };
+                    a.get_history().report( &TreeError::Refused(format!("convert failed: {} (possible: {})", e, possible)) );
+                }
+            },
+            Err(e) => a.get_history().report(&e),
+        }
+    });
+    // normalize ///////////////////////////////////////////
+    let reverse_check = CheckButton::with_label("reverse");
+    let reverse_check_for_normalize = reverse_check.clone();
+    let normalize_button = Isv2Button::with_label_selection_history("normalize",
+                                                                    selection_model.clone(),
+                                                                    history.clone());
+    normalize_button.connect_clicked(move |a| {
+        let report= normalize::normalize(&a.get_history(), reverse_check_for_normalize.is_active());
+        AlertDialog::builder()
+            .message( format!("normalized {} nodes", report.changes.len()) )
+            .detail( report.to_string() )
+            .build()
+            .show( a.root().and_downcast_ref::<gtk::Window>() );
+    });
+    // open / save / save as / recent ///////////////////////
+    let file = DocumentFile::new(&history);
+    let open_button = Button::with_label("open");
+    let app_for_open = app.clone();
+    open_button.connect_clicked(move |b| {
+        let Some(w) = b.root().and_downcast::<gtk::Window>() else { return };
+        let app= app_for_open.clone();
+        document_file::choose_file(&w, move |p| { build_ui(&app, Some(p)); });
+    });
+    let save_button = Button::with_label("save");
+    let file_for_save = file.clone();
+    save_button.connect_clicked(move |b| {
+        if let Some(w) = b.root().and_downcast::<gtk::Window>() {
+            file_for_save.save(&w, || ()); }
+    });
+    let save_as_button = Button::with_label("save as");
+    let file_for_save_as = file.clone();
+    save_as_button.connect_clicked(move |b| {
+        if let Some(w) = b.root().and_downcast::<gtk::Window>() {
+            file_for_save_as.save_as(&w, || ()); }
+    });
+    let recent_box = Box::new(Orientation::Vertical, 0);
+    let recent_popover = Popover::builder().child(&recent_box).build();
+    let recent_button = MenuButton::builder().label("recent").popover(&recent_popover).build();
+    let app_for_recent = app.clone();
+    recent_popover.connect_show(move |p| { // the list is read when shown, other windows may have changed it
+        while let Some(c) = recent_box.first_child() {
+            recent_box.remove(&c); }
+        for f in document_file::recent_files() {
+            let b= Button::with_label( &f.display().to_string() );
+            b.add_css_class("flat");
+            let (app, popover)= (app_for_recent.clone(), p.clone());
+            b.connect_clicked(move |_| {
+                popover.popdown();
+                build_ui(&app, Some(f.clone()));
+            });
+            recent_box.append(&b);
+        }
+        if recent_box.first_child().is_none() {
+            recent_box.append( &Label::new(Some("no recent files")) ); }
+    });
+    // export //////////////////////////////////////////////
+    let export_button = Isv2Button::with_label_selection_history("export",
+                                                                 selection_model.clone(),
+                                                                 history.clone());
+    let sources_for_export = sources.clone();
+    let asset_root_for_export = assets::asset_root(&scenario_path);
+    let canvas = Rc::new(Cell::new(Canvas::DEFAULT)); // the resolution of the preview and the export
+    let canvas_for_export = canvas.clone();
+    export_button.connect_clicked(move |a| {
+        match isv2button_to_dest_member4(a).and_then(|hdl| Ok(hdl.get_sno()?.get_node())) {
+            Ok(node) => {
+                if let Err(e) = render::export_png(&node, &sources_for_export, &asset_root_for_export,
+                                                   canvas_for_export.get(), Path::new(EXPORT_PATH)) {
+                    println!("export failed: {}", e);
+                }
+            },
+            Err(e) => a.get_history().report(&e),
+        }
+    });
+    // play ////////////////////////////////////////////////
+    let play_button = Button::with_label("play");
+    let history_for_play   = history.clone();
+    let selection_for_play = selection_model.clone();
+    let sources_for_play   = sources.clone();
+    let canvas_for_play    = canvas.clone();
+    let asset_root_for_play= assets::asset_root(&scenario_path);
+    play_button.connect_clicked(move |b| {
+        player::open_player(b.root().and_downcast::<gtk::Window>().as_ref(),
+                            history_for_play.clone(),
+                            selection_for_play.clone(),
+                            sources_for_play.clone(),
+                            asset_root_for_play.clone(),
+                            canvas_for_play.clone(),
+                            selected_node(&selection_for_play));
+    });
+    // import //////////////////////////////////////////////
+    let import_button = Button::with_label("import");
+    let history_for_import   = history.clone();
+    let selection_for_import = selection_model.clone();
+    import_button.connect_clicked(move |b| {
+        open_import_dialog(b.root().and_downcast::<gtk::Window>().as_ref(),
+                           history_for_import.clone(),
+                           selection_for_import.clone(),
+                           marks.clone());
+    });
+    // move to /////////////////////////////////////////////
+    let move_button = Isv2Button::with_label_selection_history("move to…",
+                                                               selection_model.clone(),
+                                                               history.clone());
+    move_button.connect_clicked(move |a| {
+        match selected_node(&a.get_selection()) {
+            Some(n) => open_move_dialog(a.root().and_downcast::<gtk::Window>().as_ref(),
+                                        a.get_history(),
+                                        a.get_selection().as_ref().clone(),
+                                        n.id.get(),
+                                        move_to),
+            None    => a.get_history().report(&TreeError::NotSelected),
         }
     });
     // undo ////////////////////////////////////////////////
@@ -690,56 +994,83 @@ fn build_ui(app: &Application) {
     });
     // update //////////////////////////////////////////////
     let update_button = Button::with_label("update");
-    update_button.connect_clicked( |button| {
-        let list_view= button.parent().unwrap()
-            .prev_sibling().unwrap()
-            .first_child().unwrap()
-            .downcast::<ListView>().expect("ListView");
-        let list_model= list_view.model().unwrap() // SelectionModel
-            .downcast::<SingleSelection>().expect("SingleSelection")
-            .model().unwrap() // TreeListModel
-            .downcast::<TreeListModel>().expect("TreeListModel")
-            .model();          // ListModel
-        for i in 0..list_model.n_items() {
-            list_model.items_changed(i, 1, 1);
-        }
-
-    });
+    let history_for_update = history.clone();
+    update_button.connect_clicked(move |_| history_for_update.redraw_all() );
     // dump ////////////////////////////////////////////////
-    let dump_button = Button::with_label("dump"); // just for debug
+    let diagnostics = DiagnosticsPanel::new(&history, selection_model.clone());
+    let side_notebook = Notebook::new();
+    let dump_button = Button::with_label("dump"); // the diagnostics tab
+    let diagnostics_for_dump = diagnostics.clone();
+    let notebook_for_dump = side_notebook.clone();
     dump_button.connect_clicked( move |_| {
-        let obj= list_view.model().unwrap() // SelectionModel
-            .downcast::<SingleSelection>().expect("SingleSelection")
-            .model().unwrap() // TreeListModel
-            .downcast::<TreeListModel>().expect("TreeListModel")
-            .model()          // ListModel
-            .item(0);         // Object<ScenarioNodeObject>
-        let sno= obj.unwrap().downcast_ref::<ScenarioNodeObject>().expect("sno").get_node();
-        println!("--------------------");
-        sno.dump(0);
-        list_view.set_model( Some( &list_view.model().unwrap() ) );
-        list_view.queue_draw();
-
-        // 再描画のサンプル，性能はわからないがとりあえず期待通り動作する
-        // TODO list_model を move せずに， connect_clicked の引数から生成する
-
-        let list_model= list_view.model().unwrap() // SelectionModel
-            .downcast::<SingleSelection>().expect("SingleSelection")
-            .model().unwrap() // TreeListModel
-            .downcast::<TreeListModel>().expect("TreeListModel")
-            .model();          // ListModel
-        for i in 0..list_model.n_items() {
-            list_model.items_changed(i, 1, 1);
-        }
+        notebook_for_dump.set_current_page( notebook_for_dump.page_num(diagnostics_for_dump.widget()) );
+        diagnostics_for_dump.dump();
+    });
 
+    // query ///////////////////////////////////////////////
+    let query_entry = Entry::builder()
+        .placeholder_text("scene[lbl=intro]/page/mat, 2/0 or @intro/m1")
+        .build();
+    let query_label = Label::new(None);
+    let query_label_for_entry = query_label.clone();
+    let selection_model_for_query = selection_model.clone();
+    query_entry.connect_activate(move |e| {
+        let tlm= selection_model_for_query.model().unwrap()
+            .downcast::<TreeListModel>().expect("TreeListModel");
+        let root= first_root(&tlm);
+        let text= e.text();
+        let found= if let Some(labels) = text.strip_prefix('@') {
+            let labels: Vec<&str>= labels.split('/').collect();
+            Ok( scenario_query::nodes_at_label_path(root, &labels) )
+        } else if let Some(path) = scenario_query::parse_index_path(&text) {
+            Ok( scenario_query::node_at_index_path(root, &path).into_iter().collect() )
+        } else {
+            scenario_query::query(root, &text)
+        };
+        match found {
+            Ok(nodes) => {
+                let mut msg= format!("{} found", nodes.len());
+                if let Some(n) = nodes.first() {
+                    let labels= scenario_query::label_path(n);
+                    if !labels.is_empty() {
+                        msg+= &format!(", @{}", labels.join("/")); }
+                    if let Some(row) = row_at_path(&tlm, &n.index_path()) {
+                        selection_model_for_query.set_selected( row.position() ); }
+                }
+                query_label_for_entry.set_label(&msg);
+            },
+            Err(err) => query_label_for_entry.set_label( &err.to_string() ),
+        }
     });
+    let query_box = Box::builder()
+        .orientation(Orientation::Horizontal)
+        .build();
+    query_entry.set_hexpand(true);
+    query_box.append(&query_entry);
+    query_box.append(&query_label);
 
     ////////////////////////////////////////////////////////
 
     let gtk_box = Box::builder()
         .orientation(Orientation::Vertical)
         .build();
-    gtk_box.append(&scrolled_window);
+    gtk_box.append(&query_box);
+    gtk_box.append(&build_breadcrumb(history.clone(), selection_model.clone()));
+    let tree_paned = Paned::builder()
+        .orientation(Orientation::Vertical)
+        .start_child(&scrolled_window)
+        .end_child(&split_window)
+        .build();
+    let (tree_overlay, toast) = Toast::new(&tree_paned); // a failed drop, undo, ...
+    history.connect_error(move |e| toast.show(&e.to_string()));
+    tree_overlay.set_hexpand(true);
+    let tree_box = Box::builder()
+        .orientation(Orientation::Horizontal)
+        .vexpand(true)
+        .build();
+    tree_box.append(&tree_overlay);
+    tree_box.append(&build_minimap(history.clone(), selection_model.clone()));
+    gtk_box.append(&tree_box);
 
     let button_box = Box::builder()
         .orientation(Orientation::Horizontal)
@@ -748,20 +1079,79 @@ fn build_ui(app: &Application) {
     button_box.append(&redo_button);
     button_box.append(&dump_button);
     button_box.append(&update_button);
+    button_box.append(&split_button);
     button_box.append(&add_neighbor_button);
     button_box.append(&add_child_button);
     button_box.append(&remove_button);
+    button_box.append(&move_button);
+    button_box.append(&open_button);
+    button_box.append(&recent_button);
+    button_box.append(&save_button);
+    button_box.append(&save_as_button);
+    button_box.append(&export_button);
+    button_box.append(&play_button);
+    button_box.append(&import_button);
     gtk_box.append(&button_box);
 
+    let convert_box = Box::builder()
+        .orientation(Orientation::Horizontal)
+        .build();
+    convert_box.append(&kind_dropdown);
+    convert_box.append(&convert_button);
+    convert_box.append(&normalize_button);
+    convert_box.append(&reverse_check);
+    gtk_box.append(&convert_box);
+
+    // preview / mat editor / history, assets and crop ////
+    let preview_box = Box::builder()
+        .orientation(Orientation::Vertical)
+        .build();
+    let asset_root = assets::asset_root(&scenario_path);
+    preview_box.append(&build_preview(history.clone(), selection_model.clone(), sources.clone(), asset_root.clone(),
+                                      canvas.clone()));
+    preview_box.append(&build_mat_editor(history.clone(), selection_model.clone(), sources.clone()));
+    side_notebook.append_page(&build_history_panel(history.clone()), Some(&Label::new(Some("history"))));
+    side_notebook.append_page(&build_asset_panel(history.clone(), asset_root.clone()),
+                              Some(&Label::new(Some("assets"))));
+    side_notebook.append_page(&build_crop_editor(history.clone(), selection_model.clone(), asset_root),
+                              Some(&Label::new(Some("crop"))));
+    side_notebook.append_page(diagnostics.widget(), Some(&Label::new(Some("diagnostics"))));
+    let side_paned = Paned::builder()
+        .orientation(Orientation::Vertical)
+        .start_child(&preview_box)
+        .end_child(&side_notebook)
+        .build();
+    let paned = Paned::builder()
+        .orientation(Orientation::Horizontal)
+        .start_child(&gtk_box)
+        .end_child(&side_paned)
+        .resize_end_child(false)
+        .build();
+
     // Create a window
     let window = ApplicationWindow::builder()
         .application(app)
-        .title( String::from("isv2") )
-        .default_width(320)
+        .default_width(880)
         .default_height(480)
-        .child(&gtk_box)
+        .child(&paned)
         .build();
 
+    // the title follows the file and the history
+    let window_for_title = window.downgrade();
+    let file_for_title = Rc::downgrade(&file);
+    history.connect_changed(move || {
+        if let (Some(w), Some(f)) = (window_for_title.upgrade(), file_for_title.upgrade()) {
+            w.set_title( Some(&f.title()) ); }
+    });
+    file.connect_close(window.upcast_ref());
+    recovery::start_autosave(window.upcast_ref(), &history, &file);
+
     // Present window
     window.present();
+    if let Some(p) = path {
+        if let Err(e) = file.open(&p) {
+            document_file::show_error(window.upcast_ref(), &format!("{} is not opened", p.display()), &e); }
+    }
+    window.set_title( Some(&file.title()) );
+    file
 }
@@ -0,0 +1,188 @@
+//! inline markup of the text of a mat
+//!
+//!   [b]bold[/b]  [i]italic[/i]  [color=#f00]red[/color]  (#rgb or #rrggbb)
+//!   [ruby=かんじ]漢字[/ruby]   ruby (furigana) over the base text, the base is plain text
+//!   [wait 500]                 pause in ms, nothing is drawn
+//!   [[                         a literal '['
+//!
+//! parse() gives a tree of Span, or the first error with its byte offset.
+
+use std::fmt;
+
+use crate::scenario_node::{Item, Color};
+
+// Span ////////////////////////////////////////////////////
+#[derive(Debug, Clone)]
+pub enum Span {
+    Text(String),
+    Bold(Vec<Span>),
+    Italic(Vec<Span>),
+    Color(Color, Vec<Span>),
+    Ruby{ base: String, reading: String },
+    Wait(u32), // ms
+}
+// MarkupError /////////////////////////////////////////////
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarkupError {
+    pub pos: usize, // byte offset in the text
+    pub msg: String,
+}
+impl fmt::Display for MarkupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {}", self.msg, self.pos)
+    }
+}
+// Tag /////////////////////////////////////////////////////
+enum Tag {
+    Bold,
+    Italic,
+    Color(Color),
+    Ruby(String),
+}
+impl Tag {
+    fn name(&self) -> &'static str {
+        match self {
+            Tag::Bold     => "b",
+            Tag::Italic   => "i",
+            Tag::Color(_) => "color",
+            Tag::Ruby(_)  => "ruby",
+        }
+    }
+}
+/// the inside of [...] except the closing tags
+enum Token {
+    Open(Tag),
+    Wait(u32),
+}
+/// an open tag and the spans in it
+struct Frame {
+    tag  : Tag,
+    pos  : usize,
+    spans: Vec<Span>,
+}
+// parse_tag ///////////////////////////////////////////////
+/// Err(msg) if unknown or malformed
+fn parse_tag(s: &str) -> Result<Token, String> {
+    let (name, arg)= match s.find(['=', ' ']) {
+        Some(i) => (&s[..i], Some(s[i + 1..].trim())),
+        None    => (s, None),
+    };
+    match (name, arg) {
+        ("b", None)        => Ok(Token::Open(Tag::Bold)),
+        ("i", None)        => Ok(Token::Open(Tag::Italic)),
+        ("color", Some(c)) => Color::parse(c).ok().map(|c| Token::Open(Tag::Color(c)))
+            .ok_or(format!("bad color: {}", c)),
+        ("ruby", Some(r))  => if r.is_empty() { Err(String::from("empty ruby")) }
+                              else { Ok(Token::Open(Tag::Ruby(r.to_string()))) },
+        ("wait", Some(ms)) => ms.parse::<u32>().map(Token::Wait)
+            .map_err(|_| format!("wait needs ms: {}", ms)),
+        ("b" | "i", Some(_))              => Err(format!("[{}] takes no value", name)),
+        ("color" | "ruby" | "wait", None) => Err(format!("[{}] needs a value", name)),
+        _ => Err(format!("unknown tag: {}", name)),
+    }
+}
+// close ///////////////////////////////////////////////////
+fn close(frame: Frame) -> Result<Span, MarkupError> {
+    Ok( match frame.tag {
+        Tag::Bold     => Span::Bold(frame.spans),
+        Tag::Italic   => Span::Italic(frame.spans),
+        Tag::Color(c) => Span::Color(c, frame.spans),
+        Tag::Ruby(reading) => {
+            let mut base= String::new();
+            for s in frame.spans {
+                match s {
+                    Span::Text(t) => base+= &t,
+                    _ => return Err(MarkupError{ pos: frame.pos, msg: String::from("only text is allowed in [ruby]") }),
+                }
+            }
+            if base.is_empty() {
+                return Err(MarkupError{ pos: frame.pos, msg: String::from("no base text of [ruby]") }); }
+            Span::Ruby{ base, reading }
+        },
+    } )
+}
+// parse ///////////////////////////////////////////////////
+pub fn parse(s: &str) -> Result<Vec<Span>, MarkupError> {
+    let mut top  : Vec<Span>  = Vec::new();
+    let mut stack: Vec<Frame> = Vec::new();
+    let mut text = String::new();
+    let mut i= 0;
+
+    // the spans of the innermost open tag
+    fn spans<'a>(top: &'a mut Vec<Span>, stack: &'a mut [Frame]) -> &'a mut Vec<Span> {
+        match stack.last_mut() {
+            Some(f) => &mut f.spans,
+            None    => top,
+        }
+    }
+    while i < s.len() {
+        let rest= &s[i..];
+        if rest.starts_with("[[") {
+            text.push('[');
+            i+= 2;
+            continue;
+        }
+        if !rest.starts_with('[') {
+            let c= rest.chars().next().unwrap();
+            text.push(c);
+            i+= c.len_utf8();
+            continue;
+        }
+        let end= rest.find(']')
+            .ok_or(MarkupError{ pos: i, msg: String::from("']' is missing") })?;
+        let inner= rest[1..end].trim();
+        if !text.is_empty() {
+            spans(&mut top, &mut stack).push( Span::Text(std::mem::take(&mut text)) ); }
+
+        if let Some(name) = inner.strip_prefix('/') {
+            let frame= stack.pop()
+                .ok_or(MarkupError{ pos: i, msg: format!("[/{}] without [{}]", name, name) })?;
+            if frame.tag.name() != name.trim() {
+                return Err(MarkupError{ pos: i, msg: format!("[/{}] does not close [{}]", name, frame.tag.name()) }); }
+            let span= close(frame)?;
+            spans(&mut top, &mut stack).push(span);
+        } else {
+            match parse_tag(inner).map_err(|msg| MarkupError{ pos: i, msg })? {
+                Token::Open(tag) => stack.push( Frame{ tag, pos: i, spans: Vec::new() } ),
+                Token::Wait(ms)  => spans(&mut top, &mut stack).push( Span::Wait(ms) ),
+            }
+        }
+        i+= end + 1;
+    }
+    if let Some(f) = stack.last() {
+        return Err(MarkupError{ pos: f.pos, msg: format!("[{}] is not closed", f.tag.name()) }); }
+    if !text.is_empty() {
+        top.push( Span::Text(text) ); }
+    Ok(top)
+}
+// plain_text //////////////////////////////////////////////
+/// the text without the markup, the base text of ruby is kept
+pub fn plain_text(spans: &[Span]) -> String {
+    let mut s= String::new();
+    for span in spans {
+        match span {
+            Span::Text(t)       => s+= t,
+            Span::Bold(c) | Span::Italic(c) | Span::Color(_, c) => s+= &plain_text(c),
+            Span::Ruby{ base, .. } => s+= base,
+            Span::Wait(_)       => (),
+        }
+    }
+    s
+}
+// total_wait //////////////////////////////////////////////
+/// the sum of the waits in ms
+pub fn total_wait(spans: &[Span]) -> u32 {
+    spans.iter().map(|span| match span {
+        Span::Bold(c) | Span::Italic(c) | Span::Color(_, c) => total_wait(c),
+        Span::Wait(ms) => *ms,
+        _              => 0,
+    }).sum()
+}
+// validate ////////////////////////////////////////////////
+/// the markup error of the text of a mat / pmat
+pub fn validate(v: &Item) -> Option<MarkupError> {
+    match v {
+        Item::Mat(m) | Item::Pmat(m) => parse(&m.txt).err(),
+        _                            => None,
+    }
+}
@@ -0,0 +1,237 @@
+//! Mat editor: the color (col), the text body (txt), the vertical writing flag (v)
+//! and the source (src) of the selected mat / pmat.
+//! each edit is pushed to OperationHistory as a SetValue,
+//! consecutive edits of the same field are coalesced into one undo step.
+//! the markup error of the text and the state of src are shown below the text and on the row.
+//! src refers to an external text (text_source.rs), "sync" copies it to txt.
+
+use std::cell::Cell;
+use std::rc::Rc;
+use gtk::prelude::*;
+use gtk::{Box, Button, CheckButton, ColorDialog, ColorDialogButton, Entry, Label, Orientation,
+          PolicyType, ScrolledWindow, SingleSelection, TextView, WrapMode};
+
+use crate::scenario_node::{Item, Mat, Color};
+use crate::operation_history::{OperationHistory, OperationHistoryItem};
+use crate::scenario_node_object::{selected_node, refresh_row, row_at_path};
+use crate::markup;
+use crate::text_source::{SourceCache, SourceState};
+
+// row_notes ///////////////////////////////////////////////
+/// the notes shown on the row of a node: (text, css class)
+pub fn row_notes(v: &Item, sources: &SourceCache) -> Vec<(String, &'static str)> {
+    let mut notes= Vec::new();
+    if let Some(e) = markup::validate(v) {
+        notes.push( (e.to_string(), "markup_error") ); }
+    if let Item::Mat(m) | Item::Pmat(m) = v {
+        match sources.state(m) {
+            SourceState::Stale      => notes.push( (String::from("src stale"), "src_stale") ),
+            SourceState::Missing(e) => notes.push( (format!("src missing: {}", e), "src_missing") ),
+            _                       => (),
+        }
+    }
+    notes
+}
+// selected_mat ////////////////////////////////////////////
+fn selected_mat(selection: &SingleSelection) -> Option<Mat> {
+    match &*selected_node(selection)?.value.borrow() {
+        Item::Mat(m) | Item::Pmat(m) => Some(m.clone()),
+        _                            => None,
+    }
+}
+// edit ////////////////////////////////////////////////////
+/// apply `f` to the selected mat and push the edit of `field`
+fn edit<F: FnOnce(&mut Mat)>(history: &OperationHistory, selection: &SingleSelection,
+                             sources: &SourceCache, field: &str, f: F) {
+    let Some(node) = selected_node(selection) else { return };
+    let old= node.value.borrow().clone();
+    let mut new= old.clone();
+    match &mut new {
+        Item::Mat(m) | Item::Pmat(m) => f(m),
+        _                            => return,
+    }
+    let relabel= row_notes(&old, sources) != row_notes(&new, sources);
+    node.set_value(new.clone());
+    history.push( OperationHistoryItem::new_set_value(&node, field, old, new) );
+
+    // the row shows the notes, re-create it and keep the selection
+    if relabel {
+        let tlm = history.tree_list_model();
+        let path= node.index_path();
+        refresh_row(&tlm, &path);
+        if let Some(row) = row_at_path(&tlm, &path) {
+            selection.set_selected( row.position() ); }
+    }
+}
+// Widgets /////////////////////////////////////////////////
+struct Widgets {
+    text_view : TextView,
+    vertical  : CheckButton,
+    col       : ColorDialogButton,
+    status    : Label, // length of the text or the markup error
+    src       : Entry,
+    src_status: Label,
+}
+// load ////////////////////////////////////////////////////
+/// show the values of the selected mat, the widgets are insensitive for other items
+fn load(w: &Widgets, selection: &SingleSelection, sources: &SourceCache) {
+    let m= selected_mat(selection);
+    w.text_view.set_sensitive(m.is_some());
+    w.vertical .set_sensitive(m.is_some());
+    w.col      .set_sensitive(m.is_some());
+    w.src      .set_sensitive(m.is_some());
+
+    match m.as_ref().map(|m| markup::parse(&m.txt)) {
+        Some(Ok(spans)) => {
+            w.status.set_label( &format!("{} chars, wait {} ms",
+                                         markup::plain_text(&spans).chars().count(),
+                                         markup::total_wait(&spans)) );
+            w.status.remove_css_class("markup_error");
+        },
+        Some(Err(e)) => {
+            w.status.set_label( &e.to_string() );
+            w.status.add_css_class("markup_error");
+        },
+        None => w.status.set_label(""),
+    }
+    w.src_status.set_label( &match m.as_ref().map(|m| sources.state(m)) {
+        Some(SourceState::Ok)         => String::from("src: up to date"),
+        Some(SourceState::Stale)      => String::from("src: changed, sync to copy it to txt"),
+        Some(SourceState::Missing(e)) => format!("src: {}", e),
+        _                             => String::new(),
+    } );
+
+    let (txt, v, src)= m.map_or((String::new(), false, String::new()),
+                                |m| (m.txt, m.v, m.src.unwrap_or_default()));
+    let buffer= w.text_view.buffer();
+    if buffer.text(&buffer.start_iter(), &buffer.end_iter(), false) != txt {
+        buffer.set_text(&txt); }
+    if w.vertical.is_active() != v {
+        w.vertical.set_active(v); }
+    if let Some(c) = selected_mat(selection).map(|m| m.col) {
+        if Color::from_rgba(&w.col.rgba()) != c {
+            w.col.set_rgba(&c.to_rgba()); }
+    }
+    if w.src.text() != src {
+        w.src.set_text(&src); }
+}
+// build_mat_editor ////////////////////////////////////////
+pub fn build_mat_editor(history: Rc<OperationHistory>, selection: SingleSelection,
+                        sources: Rc<SourceCache>) -> Box {
+    let w= Rc::new( Widgets{
+        text_view : TextView::builder().wrap_mode(WrapMode::WordChar).build(),
+        vertical  : CheckButton::with_label("縦書き (v)"),
+        col       : ColorDialogButton::new(Some(ColorDialog::builder().with_alpha(true).build())),
+        status    : Label::builder().xalign(0.0).build(),
+        src       : Entry::builder().placeholder_text("src: story.txt#L10-L14 or story.txt#anchor").build(),
+        src_status: Label::builder().xalign(0.0).build(),
+    } );
+    let sync    = Button::with_label("sync");
+    let updating= Rc::new(Cell::new(false)); // true while the widgets are set from the node
+
+    // edits ///////////////////////////////////////////////
+    let history_for_text  = Rc::downgrade(&history);
+    let selection_for_text= selection.clone();
+    let sources_for_text  = sources.clone();
+    let updating_for_text = updating.clone();
+    w.text_view.buffer().connect_changed(move |b| {
+        if updating_for_text.get() {
+            return; }
+        let Some(h) = history_for_text.upgrade() else { return };
+        let txt= b.text(&b.start_iter(), &b.end_iter(), false).to_string();
+        edit(&h, &selection_for_text, &sources_for_text, "txt", |m| m.txt= txt);
+    });
+
+    let history_for_v  = Rc::downgrade(&history);
+    let selection_for_v= selection.clone();
+    let sources_for_v  = sources.clone();
+    let updating_for_v = updating.clone();
+    w.vertical.connect_toggled(move |c| {
+        if updating_for_v.get() {
+            return; }
+        let Some(h) = history_for_v.upgrade() else { return };
+        let v= c.is_active();
+        edit(&h, &selection_for_v, &sources_for_v, "v", |m| m.v= v);
+    });
+
+    let history_for_col  = Rc::downgrade(&history);
+    let selection_for_col= selection.clone();
+    let sources_for_col  = sources.clone();
+    let updating_for_col = updating.clone();
+    w.col.connect_rgba_notify(move |b| {
+        if updating_for_col.get() {
+            return; }
+        let Some(h) = history_for_col.upgrade() else { return };
+        let c= Color::from_rgba(&b.rgba());
+        edit(&h, &selection_for_col, &sources_for_col, "col", |m| m.col= c);
+    });
+
+    let history_for_src  = Rc::downgrade(&history);
+    let selection_for_src= selection.clone();
+    let sources_for_src  = sources.clone();
+    w.src.connect_activate(move |e| {
+        let Some(h) = history_for_src.upgrade() else { return };
+        let s= e.text().trim().to_string();
+        edit(&h, &selection_for_src, &sources_for_src, "src",
+             |m| m.src= if s.is_empty() { None } else { Some(s) });
+    });
+
+    let history_for_sync  = Rc::downgrade(&history);
+    let selection_for_sync= selection.clone();
+    let sources_for_sync  = sources.clone();
+    sync.connect_clicked(move |_| {
+        let Some(h) = history_for_sync.upgrade() else { return };
+        let Some(src) = selected_mat(&selection_for_sync).and_then(|m| m.src) else { return };
+        match sources_for_sync.text(&src) {
+            Ok(t)  => edit(&h, &selection_for_sync, &sources_for_sync, "txt", |m| m.txt= t),
+            Err(e) => println!("sync failed: {}", e),
+        }
+    });
+
+    // reload on selection change / undo / redo / source change
+    let reload= {
+        let w        = w.clone();
+        let selection= selection.clone();
+        let sources  = Rc::downgrade(&sources);
+        let updating = updating.clone();
+        move || {
+            if let Some(s) = sources.upgrade() {
+                updating.set(true);
+                load(&w, &selection, &s);
+                updating.set(false);
+            }
+        }
+    };
+    let reload= Rc::new(reload);
+    let reload_for_selection= reload.clone();
+    selection.connect_selection_changed(move |_, _, _| reload_for_selection());
+    let reload_for_history= reload.clone();
+    history.connect_changed(move || reload_for_history());
+    let reload_for_sources= reload.clone();
+    sources.connect_changed(move |_| reload_for_sources());
+    reload();
+
+    let src_box= Box::builder()
+        .orientation(Orientation::Horizontal)
+        .build();
+    w.src.set_hexpand(true);
+    src_box.append(&w.src);
+    src_box.append(&sync);
+
+    let editor= Box::builder()
+        .orientation(Orientation::Vertical)
+        .build();
+    let flags= Box::new(Orientation::Horizontal, 4);
+    flags.append(&w.vertical);
+    flags.append(&w.col);
+    editor.append(&flags);
+    editor.append(&src_box);
+    editor.append(&w.src_status);
+    editor.append(&ScrolledWindow::builder()
+                  .hscrollbar_policy(PolicyType::Never)
+                  .min_content_height(80)
+                  .child(&w.text_view)
+                  .build());
+    editor.append(&w.status);
+    editor
+}
@@ -0,0 +1,202 @@
+//! "move to" dialog: moves the selected node without dragging.
+//!
+//! the destinations are the nodes where the node may be placed by the hierarchy rules (hierarchy.rs),
+//! filtered by the search text. the move itself is done by the caller (`Mover`) in the way of a drop,
+//! so that it is recorded as an Mv* operation.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use gtk::prelude::*;
+use gtk::glib;
+use gtk::{Box, Button, DropDown, Label, ListBox, Orientation, PolicyType, ScrolledWindow, SearchEntry,
+          SelectionMode, SingleSelection, Window};
+
+use crate::hierarchy;
+use crate::operation_history::OperationHistory;
+use crate::outline::crumb_label;
+use crate::scenario_node::ScenarioNode;
+use crate::scenario_node_object::{first_root, select_node};
+use crate::scenario_query;
+use crate::tree_error::TreeResult;
+
+/// the destinations listed at most
+const MAX_LINES: usize = 500;
+
+// Placement ///////////////////////////////////////////////
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Placement { Before, After, FirstChild, LastChild, }
+impl Placement {
+    pub const ALL: [Placement; 4] = [Placement::Before, Placement::After, Placement::FirstChild, Placement::LastChild];
+    pub fn name(&self) -> &'static str {
+        match self {
+            Placement::Before     => "before",
+            Placement::After      => "after",
+            Placement::FirstChild => "first child",
+            Placement::LastChild  => "last child",
+        }
+    }
+}
+/// move the node of the first id to the second one
+pub type Mover = fn(&OperationHistory, i32, i32, Placement) -> TreeResult<()>;
+
+// stays //////////////////////////////////////////////////
+/// `src` already is at `dest` by `p`, moving it there is a no-op
+pub fn stays(src: &ScenarioNode, dest: &ScenarioNode, p: Placement) -> bool {
+    let id  = src.id.get();
+    let same= |n: Option<Rc<ScenarioNode>>, id: i32| n.is_some_and(|n| n.id.get() == id);
+    match p {
+        Placement::Before     => same(src.get_neighbor(), dest.id.get()),
+        Placement::After      => same(dest.get_neighbor(), id),
+        Placement::FirstChild => same(dest.get_child(), id),
+        Placement::LastChild  => src.get_neighbor().is_none() && same(src.get_parent_node(), dest.id.get()),
+    }
+}
+// allowed /////////////////////////////////////////////////
+/// `src` may be placed at `dest`: not into itself, not where it already is (stays),
+/// and its kind is allowed under the new parent
+pub fn allowed(src: &ScenarioNode, dest: &ScenarioNode, p: Placement) -> bool {
+    let id= src.id.get();
+    if dest.id.get() == id || scenario_query::ancestors(dest).any(|a| a.id.get() == id) {
+        return false; }
+    if stays(src, dest, p) {
+        return false; }
+    let parent= match p {
+        Placement::Before     | Placement::After     => dest.get_parent_node().map(|n| n.value.borrow().kind()),
+        Placement::FirstChild | Placement::LastChild => Some(dest.value.borrow().kind()),
+    };
+    hierarchy::allows(parent, src.value.borrow().kind())
+}
+
+// MoveDialog //////////////////////////////////////////////
+struct MoveDialog {
+    history  : Rc<OperationHistory>,
+    selection: SingleSelection,
+    mover    : Mover,
+    src_id   : i32,
+    search   : SearchEntry,
+    placement: DropDown,
+    list_box : ListBox,
+    status   : Label,
+    ids      : RefCell<Vec<i32>>, // the node of each line
+}
+impl MoveDialog {
+    fn get_placement(&self) -> Placement {
+        Placement::ALL[ (self.placement.selected() as usize).min(Placement::ALL.len() - 1) ]
+    }
+    // fill ////////////////////////////////////////////////
+    fn fill(&self) {
+        self.list_box.remove_all();
+        self.ids.borrow_mut().clear();
+        let Some(src) = self.history.lookup(self.src_id) else {
+            self.status.set_label( &format!("id:{} is gone", self.src_id) );
+            return;
+        };
+        let (p, text)= (self.get_placement(), self.search.text().to_lowercase());
+        let mut found= 0;
+        for n in scenario_query::pre_order_forest( first_root(&self.history.tree_list_model()) ) {
+            if !allowed(&src, &n, p) {
+                continue; }
+            let line= format!("{:?} {}", n.index_path(), crumb_label(&n));
+            if !line.to_lowercase().contains(&text) {
+                continue; }
+            found+= 1;
+            if found <= MAX_LINES {
+                self.list_box.append( &Label::builder().label(&line).xalign(0.0).build() );
+                self.ids.borrow_mut().push(n.id.get());
+            }
+        }
+        self.status.set_label( &match found {
+            n if n > MAX_LINES => format!("{} destinations, the first {} are listed", n, MAX_LINES),
+            n                  => format!("{} destinations", n),
+        } );
+    }
+    // move_to /////////////////////////////////////////////
+    fn move_to(&self) {
+        let Some(dest) = self.list_box.selected_row().and_then(|r| self.ids.borrow().get(r.index() as usize).copied())
+            else {
+                self.status.set_label("choose a destination");
+                return;
+            };
+        match (self.mover)(&self.history, self.src_id, dest, self.get_placement()) {
+            Ok(()) => {
+                if let Some(n) = self.history.lookup(self.src_id) {
+                    select_node(&self.history.tree_list_model(), &self.selection, &n); }
+                if let Some(w) = self.list_box.root().and_downcast::<Window>() {
+                    w.close(); }
+            },
+            Err(e) => {
+                self.history.report(&e);
+                self.status.set_label( &e.to_string() );
+            },
+        }
+    }
+}
+// open_move_dialog ////////////////////////////////////////
+/// move the node of `src_id`
+pub fn open_move_dialog(parent: Option<&Window>, history: Rc<OperationHistory>, selection: SingleSelection,
+                        src_id: i32, mover: Mover) {
+    let names: Vec<&str>= Placement::ALL.iter().map(|p| p.name()).collect();
+    let dialog= Rc::new( MoveDialog{
+        history,
+        selection,
+        mover,
+        src_id,
+        search   : SearchEntry::builder().placeholder_text("path, kind, label or id").hexpand(true).build(),
+        placement: DropDown::from_strings(&names),
+        list_box : ListBox::new(),
+        status   : Label::builder().xalign(0.0).build(),
+        ids      : RefCell::new(Vec::new()),
+    } );
+    dialog.list_box.set_selection_mode(SelectionMode::Single);
+
+    let move_button= Button::with_label("move");
+    let d= Rc::downgrade(&dialog);
+    move_button.connect_clicked(move |_| {
+        if let Some(d) = d.upgrade() { d.move_to(); }
+    });
+    let d= Rc::downgrade(&dialog);
+    dialog.list_box.connect_row_activated(move |_, _| { // a double click or enter
+        if let Some(d) = d.upgrade() { d.move_to(); }
+    });
+    let d= Rc::downgrade(&dialog);
+    dialog.search.connect_search_changed(move |_| {
+        if let Some(d) = d.upgrade() { d.fill(); }
+    });
+    let d= Rc::downgrade(&dialog);
+    dialog.placement.connect_selected_notify(move |_| {
+        if let Some(d) = d.upgrade() { d.fill(); }
+    });
+
+    let bar= Box::new(Orientation::Horizontal, 4);
+    bar.append(&dialog.search);
+    bar.append(&dialog.placement);
+    bar.append(&move_button);
+    let vbox= Box::new(Orientation::Vertical, 2);
+    vbox.append(&bar);
+    vbox.append(&ScrolledWindow::builder()
+                .hscrollbar_policy(PolicyType::Automatic)
+                .min_content_height(240)
+                .min_content_width(480)
+                .vexpand(true)
+                .child(&dialog.list_box)
+                .build());
+    vbox.append(&dialog.status);
+
+    let title= match dialog.history.lookup(src_id) {
+        Some(n) => format!("move {} to", crumb_label(&n)),
+        None    => String::from("move to"),
+    };
+    let window= Window::builder()
+        .title(title)
+        .child(&vbox)
+        .build();
+    window.set_transient_for(parent);
+    dialog.fill();
+
+    // the dialog lives as long as the window
+    window.connect_close_request(move |_| {
+        let _= &dialog;
+        glib::Propagation::Proceed
+    });
+    window.present();
+}
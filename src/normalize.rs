@@ -0,0 +1,94 @@
+//! document wide normalization of page / pmat
+//!
+//! a pmat is a page with only one mat.
+//! normalize() folds every page whose only child is a mat into a pmat,
+//! with `reverse` every pmat is expanded to a page with one mat.
+//! a node whose new kind is not allowed under its parent (hierarchy.rs) is left as it is.
+//! the conversions are done by item_convert and recorded as one transaction.
+
+use std::fmt;
+use std::rc::Rc;
+
+use crate::scenario_node::{ScenarioNode, Item, ItemKind};
+use crate::scenario_node_object::first_root;
+use crate::scenario_query::pre_order_forest;
+use crate::operation_history::OperationHistory;
+use crate::item_convert;
+
+// NormalizeChange /////////////////////////////////////////
+#[derive(Debug, Clone)]
+pub struct NormalizeChange {
+    pub id      : i32,
+    pub path    : Vec<usize>,
+    pub from    : ItemKind,
+    pub to      : ItemKind,
+    pub lbl     : Option<String>,
+    pub child_id: Option<i32>, // the merged mat (page -> pmat) or the added mat (pmat -> page)
+}
+// NormalizeReport /////////////////////////////////////////
+#[derive(Debug, Clone, Default)]
+pub struct NormalizeReport {
+    pub changes: Vec<NormalizeChange>,
+    pub errors : Vec<String>,
+}
+impl fmt::Display for NormalizeReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.changes.is_empty() {
+            writeln!(f, "no change")?; }
+        for c in self.changes.iter() {
+            write!(f, "{:?} id:{} {} -> {}", c.path, c.id, c.from.name(), c.to.name())?;
+            if let Some(l) = &c.lbl {
+                write!(f, " [{}]", l)?; }
+            match (c.to, c.child_id) {
+                (ItemKind::Pmat, Some(i)) => write!(f, ", mat id:{} merged", i)?,
+                (ItemKind::Page, Some(i)) => write!(f, ", mat id:{} added", i)?,
+                _ => (),
+            }
+            writeln!(f)?;
+        }
+        for e in self.errors.iter() {
+            writeln!(f, "error: {}", e)?;
+        }
+        Ok(())
+    }
+}
+// is_foldable /////////////////////////////////////////////
+/// a page whose only child is a mat (no ovimg, no other mat)
+fn is_foldable(node: &ScenarioNode) -> bool {
+    if !matches!(*node.value.borrow(), Item::Page) {
+        return false; }
+    match node.get_children().as_slice() {
+        [c] => matches!(*c.value.borrow(), Item::Mat(_)),
+        _   => false,
+    }
+}
+// normalize ///////////////////////////////////////////////
+/// page -> pmat (or pmat -> page with `reverse`) for the whole document
+pub fn normalize(history: &OperationHistory, reverse: bool) -> NormalizeReport {
+    let (from, to)= if reverse { (ItemKind::Pmat, ItemKind::Page) } else { (ItemKind::Page, ItemKind::Pmat) };
+    let targets: Vec<Rc<ScenarioNode>>= pre_order_forest( first_root(&history.tree_list_model()) )
+        .filter(|n| if reverse { n.value.borrow().kind() == ItemKind::Pmat } else { is_foldable(n) })
+        .filter(|n| item_convert::can_convert(n, to)) // e.g. a page under a group is not folded
+        .collect();
+
+    let mut report= NormalizeReport::default();
+    history.begin( &format!("normalize {} to {}", from.name(), to.name()) );
+    for n in targets {
+        let path    = n.index_path();
+        let lbl     = n.value.borrow().lbl();
+        let child_id= n.get_child().map(|c| c.id.get());
+        match item_convert::convert(history, &path, to) {
+            Ok(()) => report.changes.push( NormalizeChange{
+                id      : n.id.get(),
+                path,
+                from,
+                to,
+                lbl,
+                child_id: if reverse { n.get_child().map(|c| c.id.get()) } else { child_id },
+            } ),
+            Err(e) => report.errors.push( format!("{:?} id:{}: {}", path, n.id.get(), e) ),
+        }
+    }
+    history.commit();
+    report
+}
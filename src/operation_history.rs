@@ -1,22 +1,31 @@
 use std::rc::Rc;
 use std::cell::{RefCell,Cell};
+use std::fs;
+use std::io;
+use std::path::Path;
+use serde::{Serialize, Deserialize};
 use gtk::gio;
 use gtk::ListView;
 use gtk::TreeListRow;
 use gtk::TreeListModel;
 use gtk::SingleSelection;
 use crate::scenario_node_object::ScenarioNodeObject;
-use crate::scenario_node_object::adj_seq;
-use crate::scenario_node_object::add_neighbor;
-use crate::scenario_node_object::add_child;
-use crate::scenario_node_object::remove_node;
+use crate::scenario_node_object::insert_node_at;
+use crate::scenario_node_object::remove_node_at;
+use crate::scenario_node_object::move_node_at;
+use crate::scenario_node_object::set_value_at;
+use crate::scenario_node_object::first_root;
+use crate::scenario_node_object::{Touched, refresh_rows};
+use crate::scenario_document::ScenarioDocument;
 use crate::scenario_node::ScenarioNode;
-use crate::scenario_node::BranchType;
+use crate::scenario_node::Item;
+use crate::scenario_file::{NodeRecord, Fingerprint, to_record, from_record, fingerprint};
+use crate::tree_error::{TreeError, TreeResult};
 use gtk::gdk::prelude::ListModelExt;
 use gtk::gdk::prelude::Cast;
 
 // operation_history
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum Operation{
     Remove,
     AddNeighbor,
@@ -27,326 +36,354 @@ pub enum Operation{
     MvToParentNeighbor,
     MvToDestNeighbor,
     MvToParent,
+    SetValue,    // edit of a field of the node value
+    Transaction, // named group of operations, undone/redone as one
     Nop,
 }
 
-#[derive(Clone)]
+/// default number of items kept in OperationHistory
+pub const DEFAULT_HISTORY_LIMIT: usize = 1000;
+
+pub const HISTORY_FILE_VERSION: u32 = 2;
+
+// TreeManipulationHandle is a snapshot of the rows/stores around a node
+// which is used while an operation is performed.
+// it must not be kept in the history, the rows are re-created by TreeListModel.
+#[derive(Clone, Default)]
 pub struct TreeManipulationHandle{
-    pub bt           : Cell<BranchType>,
     pub row          : Option<Rc<TreeListRow>>,
     pub sno          : Option<Rc<ScenarioNodeObject>>,
     pub store        : Option<Rc<gio::ListStore>>,
-    pub depth        : Cell<u32>,
-    pub size         : Cell<u32>,
-    pub parent_row   : Option<Rc<TreeListRow>>,
     pub parent_sno   : Option<Rc<ScenarioNodeObject>>,
-    pub parent_store : Option<Rc<gio::ListStore>>,
 }
-impl Default for TreeManipulationHandle{
-    fn default() -> Self{
-        TreeManipulationHandle{
-            bt           : Cell::new(BranchType::Child),
-            row          : None,
-            sno          : None,
-            store        : None,
-            depth        : Cell::new(0),
-            size         : Cell::new(0),
-            parent_row   : None,
-            parent_sno   : None,
-            parent_store : None
-        }
+impl TreeManipulationHandle{
+    pub fn get_row(&self) -> TreeResult<&TreeListRow>{
+        self.row.as_deref().ok_or(TreeError::NoRow)
+    }
+    pub fn get_sno(&self) -> TreeResult<&ScenarioNodeObject>{
+        self.sno.as_deref().ok_or(TreeError::NotNode)
+    }
+    pub fn get_store(&self) -> TreeResult<&gio::ListStore>{
+        self.store.as_deref().ok_or(TreeError::NoStore)
+    }
+    pub fn get_parent_sno(&self) -> TreeResult<&ScenarioNodeObject>{
+        self.parent_sno.as_deref().ok_or(TreeError::NotNode)
     }
 }
 
-#[derive(Clone)]
+// OperationHistoryItem is expressed in stable terms (node id and index paths),
+// so that it can be saved and applied to the tree loaded from a file.
+#[derive(Clone, Serialize, Deserialize)]
 pub struct OperationHistoryItem{
-    pub ope     : Cell<Operation>,
-    pub src     : TreeManipulationHandle,
-    pub dest    : TreeManipulationHandle,
-    pub new_sno : Option<Rc<ScenarioNodeObject>>,
+    pub ope       : Cell<Operation>,
+    pub id        : i32,                       // id of the node operated on
+    #[serde(default)]
+    pub src       : Vec<usize>,                // index path before the operation
+    #[serde(default)]
+    pub dest      : Vec<usize>,                // index path after the operation
+    #[serde(default)]
+    pub snapshot  : Option<NodeRecord>,        // Remove/Add*: the subtree to restore
+    #[serde(default)]
+    pub field     : String,                    // SetValue: edited field, key of coalescing
+    #[serde(default)]
+    pub old_value : Option<Item>,              // SetValue
+    #[serde(default)]
+    pub new_value : Option<Item>,              // SetValue
+    #[serde(default)]
+    pub name      : String,                    // Transaction
+    #[serde(default)]
+    pub children  : Vec<OperationHistoryItem>, // Transaction
 }
 impl OperationHistoryItem{
     pub fn default() -> Self{
         OperationHistoryItem{
             ope            : Cell::new(Operation::Nop),
-            src            : TreeManipulationHandle::default(),
-            dest           : TreeManipulationHandle::default(),
-            new_sno        : None,
+            id             : 0,
+            src            : Vec::new(),
+            dest           : Vec::new(),
+            snapshot       : None,
+            field          : String::new(),
+            old_value      : None,
+            new_value      : None,
+            name           : String::new(),
+            children       : Vec::new(),
         }
     }
-    pub fn new_with_root_store(ope  : Operation,
-                               store: &gio::ListStore,
-                               sno  : &ScenarioNodeObject ) -> OperationHistoryItem{
-
-        let mut src_hdl = TreeManipulationHandle::default();
-        src_hdl.bt    = Cell::new(BranchType::Child);
-        src_hdl.sno   = Some(sno.clone().into());
-        src_hdl.store = Some(store.clone().into());
-
-        OperationHistoryItem{
-            ope            : Cell::new(ope),
-            src            : src_hdl,
-            dest           : TreeManipulationHandle::default(),
-            new_sno        : None,
-        }
+    /// `node` is going to be removed
+    pub fn new_remove(node: &ScenarioNode) -> OperationHistoryItem{
+        let mut h= OperationHistoryItem::default();
+        h.ope      = Cell::new(Operation::Remove);
+        h.id       = node.id.get();
+        h.src      = node.index_path();
+        h.snapshot = Some(to_record(node));
+        h
     }
-    pub fn new_from_handle(
-        ope: Operation,
-        hdl: TreeManipulationHandle) -> OperationHistoryItem{
-        OperationHistoryItem{
-            ope            : Cell::new(ope),
-            src            : hdl,
-            dest           : TreeManipulationHandle::default(),
-            new_sno        : None,
-        }
+    /// `node` has been added by `ope` (AddNeighbor, AddChild or AddRoot)
+    pub fn new_add(ope: Operation, node: &ScenarioNode) -> OperationHistoryItem{
+        let mut h= OperationHistoryItem::default();
+        h.ope      = Cell::new(ope);
+        h.id       = node.id.get();
+        h.dest     = node.index_path();
+        h.snapshot = Some(to_record(node));
+        h
+    }
+    /// `node` has been moved from `src` by `ope` (Mv*)
+    pub fn new_move(ope: Operation, src: Vec<usize>, node: &ScenarioNode) -> OperationHistoryItem{
+        let mut h= OperationHistoryItem::default();
+        h.ope  = Cell::new(ope);
+        h.id   = node.id.get();
+        h.src  = src;
+        h.dest = node.index_path();
+        h
+    }
+    /// edit of the value of `node`.
+    /// consecutive edits of the same `field` of the same node are coalesced by push()
+    pub fn new_set_value(node     : &ScenarioNode,
+                         field    : &str,
+                         old_value: Item,
+                         new_value: Item) -> OperationHistoryItem{
+        let mut h= OperationHistoryItem::default();
+        h.ope       = Cell::new(Operation::SetValue);
+        h.id        = node.id.get();
+        h.src       = node.index_path();
+        h.field     = field.to_string();
+        h.old_value = Some(old_value);
+        h.new_value = Some(new_value);
+        h
+    }
+    pub fn new_transaction(name: &str, children: Vec<OperationHistoryItem>) -> OperationHistoryItem{
+        let mut h= OperationHistoryItem::default();
+        h.ope      = Cell::new(Operation::Transaction);
+        h.name     = name.to_string();
+        h.children = children;
+        h
     }
     pub fn set_ope(&self, ope:Operation){
         self.ope.set(ope);
     }
+    // description /////////////////////////////////////////
+    /// one line summary for the history panel
+    pub fn description(&self) -> String{
+        match self.ope.get() {
+            Operation::Remove             => format!("remove id:{}", self.id),
+            Operation::AddNeighbor        => format!("add neighbor id:{}", self.id),
+            Operation::AddChild           => format!("add child id:{}", self.id),
+            Operation::AddRoot            => format!("add root id:{}", self.id),
+            Operation::MvToParentChild    => format!("move id:{} (parent child)", self.id),
+            Operation::MvToDestChild      => format!("move id:{} (dest child)", self.id),
+            Operation::MvToParentNeighbor => format!("move id:{} (parent neighbor)", self.id),
+            Operation::MvToDestNeighbor   => format!("move id:{} (dest neighbor)", self.id),
+            Operation::MvToParent         => format!("move id:{} (parent)", self.id),
+            Operation::SetValue           => format!("edit {} of id:{}", self.field, self.id),
+            Operation::Transaction        => format!("{} ({} ops)", self.name, self.children.len()),
+            Operation::Nop                => String::from("nop"),
+        }
+    }
+    // is_coalescable_with /////////////////////////////////
+    /// true if `next` is an edit of the same field of the same node as self
+    fn is_coalescable_with(&self, next: &OperationHistoryItem) -> bool{
+        (self.ope.get() == Operation::SetValue) && (next.ope.get() == Operation::SetValue) &&
+            (self.id == next.id) && (self.src == next.src) && (self.field == next.field)
+    }
+}
+
+// HistoryFile /////////////////////////////////////////////
+#[derive(Serialize, Deserialize)]
+struct HistoryFile{
+    version: u32,
+    #[serde(default)]
+    tree   : Option<Fingerprint>, // the tree saved with the items, None before version 2
+    index  : i32,
+    items  : Vec<OperationHistoryItem>,
 }
 
+/// called with a failed manipulation, see connect_error()
+type ErrorListener = Box<dyn Fn(&TreeError)>;
+
 pub struct OperationHistory{
-    history: RefCell<Vec<OperationHistoryItem>>,
-    index  : Cell<i32>,
-    lv     : RefCell<Option<Rc<ListView>>>,
-    size   : Cell<i32>,
+    history  : RefCell<Vec<OperationHistoryItem>>,
+    index    : Cell<i32>,
+    lv       : RefCell<Option<Rc<ListView>>>,
+    size     : Cell<i32>,
+    limit    : Cell<usize>,
+    pending  : RefCell<Vec<(String, Vec<OperationHistoryItem>)>>, // open transactions, innermost last
+    sealed   : Cell<bool>, // true: the next SetValue is not coalesced
+    saved    : Cell<Option<i32>>, // the index of the saved state, None when it cannot be reached
+    listeners: RefCell<Vec<Box<dyn Fn()>>>,
+    error_listeners: RefCell<Vec<ErrorListener>>,
+    doc      : Rc<ScenarioDocument>,
 }
 
 impl Default for OperationHistory{
     fn default() -> Self{
         OperationHistory{
-            history: RefCell::new(Vec::new()),
-            index  : Cell::new(0),
-            lv     : RefCell::new(None),
-            size   : Cell::new(0),
+            history  : RefCell::new(Vec::new()),
+            index    : Cell::new(0),
+            lv       : RefCell::new(None),
+            size     : Cell::new(0),
+            limit    : Cell::new(DEFAULT_HISTORY_LIMIT),
+            pending  : RefCell::new(Vec::new()),
+            sealed   : Cell::new(true),
+            saved    : Cell::new(Some(0)),
+            listeners: RefCell::new(Vec::new()),
+            error_listeners: RefCell::new(Vec::new()),
+            doc      : Rc::new(ScenarioDocument::new()),
         }
     }
 }
-// undo_remove /////////////////////////////////////////////
-fn undo_remove(h: &OperationHistoryItem){
-    let src_store        = h.src.store.as_ref().unwrap();
-    let src_sno          = h.src.sno.as_ref().unwrap();
-    let src_parent_sno   = h.src.parent_sno.as_ref().unwrap();
-    let src_parent_row   = h.src.parent_row.as_ref().unwrap();
-    let src_parent_store = h.src.parent_store.as_ref().unwrap();
-
-    if h.src.bt.get() == BranchType::Neighbor { // like add_neighbor_button
-        add_neighbor(&src_parent_sno, &src_sno, &src_store);
-    } else if (h.src.bt.get() == BranchType::Child) && (h.src.depth.get() > 0) {
-        add_child( &src_parent_sno, &src_sno, &src_parent_row, &src_parent_store);
-    } else { // root-root
-        if h.src.size.get() <= 1 { // last one
-            // do nothing
-        } else {
-            adj_seq( &src_store, 0, 1 );
-            let dest_sn= src_store.item(0).unwrap().downcast_ref::<ScenarioNodeObject>().expect("sno").get_node();
-            ScenarioNode::mv_to_parent(dest_sn, src_sno.get_node());
-        }
-        src_store.insert( 0, src_sno.as_ref() );
+// resolve_path ////////////////////////////////////////////
+/// `path` if the node of `id` is there, otherwise the current path of that node
+fn resolve_path(tlm: &TreeListModel, doc: &ScenarioDocument, path: &[usize], id: i32) -> Vec<usize>{
+    let root= first_root(tlm);
+    match root.clone().and_then(|r| ScenarioNode::node_at_path(r, path)) {
+        Some(n) if n.id.get() == id => path.to_vec(),
+        _ => doc.lookup(root, id).map_or(path.to_vec(), |n| n.index_path()),
     }
 }
-// undo_add_neighbor ///////////////////////////////////////
-fn undo_add_neighbor(h: &OperationHistoryItem){
-    let new_sno   = h.new_sno.as_ref().unwrap();
-    let src_store = h.src.store.as_ref().unwrap();
-
-    remove_node(src_store, new_sno);
+// undo_remove /////////////////////////////////////////////
+// the items are applied by the path based helpers, the touched rows are collected into `t`
+fn undo_remove(h: &OperationHistoryItem, tlm: &TreeListModel, t: &mut Touched) -> TreeResult<()>{
+    let r= h.snapshot.as_ref().ok_or(TreeError::NoSnapshot(h.id))?;
+    t.extend( insert_node_at(tlm, &h.src, from_record(r))? );
+    Ok(())
 }
-// undo_add_child ///////////////////////////////////////
-fn undo_add_child(h: &OperationHistoryItem){
-    let new_sno = h.new_sno.as_ref().unwrap();
-
-    if h.src.row.as_ref().unwrap().children().is_none() { // 追加で子なし→ありになった場合
-        let src_store = h.src.store.as_ref().unwrap();
-        let src_sno   = h.src.sno.as_ref().unwrap();
-        new_sno.get_node().remove();
-        src_store.upcast_ref::<gio::ListModel>().items_changed(src_sno.get_seq() as u32, 1, 1);
-    } else { // 既に子がいるところに追加した場合
-        let src_store = h.src.row.as_ref().unwrap().children().unwrap().downcast::<gio::ListStore>().expect("ListStore");
-        remove_node(&src_store, new_sno);
-    }
+// undo_add ////////////////////////////////////////////////
+fn undo_add(h: &OperationHistoryItem, tlm: &TreeListModel, doc: &ScenarioDocument, t: &mut Touched) -> TreeResult<()>{
+    t.extend( remove_node_at(tlm, &resolve_path(tlm, doc, &h.dest, h.id))?.1 );
+    Ok(())
 }
-// undo_add_root ///////////////////////////////////////////
-fn undo_add_root(h: &OperationHistoryItem){
-    let src_store   = h.src.store.as_ref().unwrap();
-    let src_sno     = h.src.sno.as_ref().unwrap();
-
-    remove_node(&src_store, src_sno);
+// undo_move ///////////////////////////////////////////////
+fn undo_move(h: &OperationHistoryItem, tlm: &TreeListModel, doc: &ScenarioDocument, t: &mut Touched) -> TreeResult<()>{
+    t.extend( move_node_at(tlm, &resolve_path(tlm, doc, &h.dest, h.id), &h.src)? );
+    Ok(())
 }
-// undo_moved_source ///////////////////////////////////////
-fn undo_moved_source(h: &OperationHistoryItem){
-    let src_bt           = h.src.bt.get();
-    let src_parent_sno   = h.src.parent_sno.as_ref().unwrap();
-    let new_sno          = h.new_sno.as_ref().unwrap();
-    let src_store        = h.src.store.as_ref().unwrap();
-    let src_depth        = h.src.depth.get();
-    let src_parent_store = h.src.parent_store.as_ref().unwrap();
-    let src_parent_row   = h.src.parent_row.as_ref().unwrap();
-
-    if src_bt == BranchType::Neighbor {
-        add_neighbor(&src_parent_sno, &new_sno, &src_store);
-    } else {
-        if  src_depth > 0 {
-            add_child(&src_parent_sno,
-                      &new_sno,
-                      &src_parent_row,
-                      &src_parent_store);
-        } else { // from root
-            adj_seq( &src_store, 0, 1 );
-            let dest_sn= src_store.item(0).unwrap()
-                .downcast_ref::<ScenarioNodeObject>().expect("sno").get_node();
-            ScenarioNode::mv_to_parent(dest_sn, new_sno.get_node());
-            new_sno.set_seq(0);
-            src_store.insert( 0, new_sno.as_ref() );
+// undo_set_value //////////////////////////////////////////
+fn undo_set_value(h: &OperationHistoryItem, tlm: &TreeListModel, doc: &ScenarioDocument, t: &mut Touched) -> TreeResult<()>{
+    let v= h.old_value.as_ref().ok_or(TreeError::NoSnapshot(h.id))?;
+    t.extend( set_value_at(tlm, &resolve_path(tlm, doc, &h.src, h.id), v.clone())? );
+    Ok(())
+}
+// undo_transaction ////////////////////////////////////////
+/// when an item fails, the items undone before it are redone
+fn undo_transaction(h: &OperationHistoryItem, tlm: &TreeListModel, doc: &ScenarioDocument, t: &mut Touched) -> TreeResult<()>{
+    for (i, c) in h.children.iter().enumerate().rev() {
+        if let Err(e) = undo_item(c, tlm, doc, t) {
+            for d in h.children[i + 1..].iter() {
+                let _= redo_item(d, tlm, doc, t); }
+            return Err(e);
         }
     }
-
-}
-// undo_mv_to_parent_neighbor //////////////////////////////
-fn undo_mv_to_parent_neighbor(h: &OperationHistoryItem){
-    let new_sno    = h.new_sno.as_ref().unwrap();
-    let dest_store = h.dest.store.as_ref().unwrap();
-
-    remove_node(&dest_store, &new_sno);
-
-    undo_moved_source(h);
-}
-// undo_mv_to_dest_neighbor ////////////////////////////////
-fn undo_mv_to_dest_neighbor(h: &OperationHistoryItem){
-    let new_sno    = h.new_sno.as_ref().unwrap();
-    let dest_store = h.dest.store.as_ref().unwrap();
-
-    remove_node(&dest_store, &new_sno);
-
-    undo_moved_source(h);
-}
-// undo_mv_to_parent_child /////////////////////////////////
-fn undo_mv_to_parent_child(h: &OperationHistoryItem){
-    let new_sno    = h.new_sno.as_ref().unwrap();
-    let dest_store = h.dest.store.as_ref().unwrap();
-
-    remove_node(&dest_store, &new_sno);
-
-    undo_moved_source(h);
+    Ok(())
 }
-// undo_mv_to_dest_child ///////////////////////////////////
-fn undo_mv_to_dest_child(h: &OperationHistoryItem){
-    let new_sno  = h.new_sno.as_ref().unwrap();
-    let dest_row = h.dest.row.as_ref().unwrap();
-
-    if dest_row.children().is_some() {
-        let dest_children = &dest_row.children().unwrap().downcast::<gio::ListStore>().expect("ListStore");
-        remove_node(&dest_children, &new_sno);
+// undo_item ///////////////////////////////////////////////
+fn undo_item(h: &OperationHistoryItem, tlm: &TreeListModel, doc: &ScenarioDocument, t: &mut Touched) -> TreeResult<()>{
+    match h.ope.get() {
+        Operation::Remove             => undo_remove(h, tlm, t),
+        Operation::AddNeighbor        => undo_add(h, tlm, doc, t),
+        Operation::AddChild           => undo_add(h, tlm, doc, t),
+        Operation::AddRoot            => undo_add(h, tlm, doc, t),
+        Operation::MvToParentNeighbor => undo_move(h, tlm, doc, t),
+        Operation::MvToDestNeighbor   => undo_move(h, tlm, doc, t),
+        Operation::MvToParentChild    => undo_move(h, tlm, doc, t),
+        Operation::MvToDestChild      => undo_move(h, tlm, doc, t),
+        Operation::MvToParent         => undo_move(h, tlm, doc, t),
+        Operation::SetValue           => undo_set_value(h, tlm, doc, t),
+        Operation::Transaction        => undo_transaction(h, tlm, doc, t),
+        _ => Ok(())
     }
-
-    undo_moved_source(h);
-}
-// undo_mv_to_parent ///////////////////////////////////////
-fn undo_mv_to_parent(h: &OperationHistoryItem){
-    let new_sno    = h.new_sno.as_ref().unwrap();
-    let dest_store = h.dest.store.as_ref().unwrap();
-
-    remove_node(&dest_store, &new_sno);
-
-    undo_moved_source(h);
 }
 // redo_remove /////////////////////////////////////////////
-fn redo_remove(h: &OperationHistoryItem){
-    let src_store   = h.src.store.as_ref().unwrap();
-    let src_sno     = h.src.sno.as_ref().unwrap();
-
-    remove_node(&src_store, src_sno);
-}
-// redo_add_neighbor ///////////////////////////////////////
-fn redo_add_neighbor(h: &OperationHistoryItem){
-    let src_store = h.src.store.as_ref().unwrap();
-    let new_sno   = h.new_sno.as_ref().unwrap();
-    let src_sno   = h.src.sno.as_ref().unwrap();
-    add_neighbor(&src_sno, &new_sno, &src_store);
-}
-// redo_add_child //////////////////////////////////////////
-fn redo_add_child(h: &OperationHistoryItem){
-    let src_store = h.src.store.as_ref().unwrap();
-    let new_sno   = h.new_sno.as_ref().unwrap();
-    let src_sno   = h.src.sno.as_ref().unwrap();
-    let src_row   = h.src.row.as_ref().unwrap();
-    add_child( &src_sno, &new_sno, &src_row, &src_store);
+fn redo_remove(h: &OperationHistoryItem, tlm: &TreeListModel, doc: &ScenarioDocument, t: &mut Touched) -> TreeResult<()>{
+    t.extend( remove_node_at(tlm, &resolve_path(tlm, doc, &h.src, h.id))?.1 );
+    Ok(())
 }
-// redo_add_root ///////////////////////////////////////////
-fn redo_add_root(h: &OperationHistoryItem){
-    let src_store = h.src.store.as_ref().unwrap();
-    let src_sno   = h.src.sno.as_ref().unwrap();
-    src_store.insert( 0, src_sno.as_ref() );
+// redo_add ////////////////////////////////////////////////
+fn redo_add(h: &OperationHistoryItem, tlm: &TreeListModel, t: &mut Touched) -> TreeResult<()>{
+    let r= h.snapshot.as_ref().ok_or(TreeError::NoSnapshot(h.id))?;
+    t.extend( insert_node_at(tlm, &h.dest, from_record(r))? );
+    Ok(())
 }
-// redo_mv_to_neighbor /////////////////////////////////////
-fn redo_mv_to_parent_neighbor(h: &OperationHistoryItem){
-    let dest_store      = h.dest.store.as_ref().unwrap();
-    let dest_parent_sno = h.dest.parent_sno.as_ref().unwrap();
-    let src_store       = h.src.store.as_ref().unwrap();
-    let src_sno         = h.src.sno.as_ref().unwrap();
-    let new_sno         = h.new_sno.as_ref().unwrap();
-
-    remove_node(&src_store, &src_sno);
-    add_neighbor(&dest_parent_sno, &new_sno, &dest_store);
-}
-fn redo_mv_to_dest_neighbor(h: &OperationHistoryItem){
-    let dest_store      = h.dest.store.as_ref().unwrap();
-    let dest_sno        = h.dest.sno.as_ref().unwrap();
-    let src_store       = h.src.store.as_ref().unwrap();
-    let src_sno         = h.src.sno.as_ref().unwrap();
-    let new_sno         = h.new_sno.as_ref().unwrap();
-
-    remove_node(&src_store, &src_sno);
-    add_neighbor(&dest_sno, &new_sno, &dest_store);
+// redo_move ///////////////////////////////////////////////
+fn redo_move(h: &OperationHistoryItem, tlm: &TreeListModel, doc: &ScenarioDocument, t: &mut Touched) -> TreeResult<()>{
+    t.extend( move_node_at(tlm, &resolve_path(tlm, doc, &h.src, h.id), &h.dest)? );
+    Ok(())
 }
-fn redo_mv_to_parent_child(h: &OperationHistoryItem){
-    let dest_parent_sno   = h.dest.parent_sno.as_ref().unwrap();
-    let dest_parent_store = h.dest.parent_store.as_ref().unwrap();
-    let dest_parent_row   = h.dest.parent_row.as_ref().unwrap();
-    let src_store         = h.src.store.as_ref().unwrap();
-    let src_sno           = h.src.sno.as_ref().unwrap();
-    let new_sno           = h.new_sno.as_ref().unwrap();
-
-    remove_node(&src_store, &src_sno);
-    add_child(&dest_parent_sno, &new_sno, &dest_parent_row, &dest_parent_store);
+// redo_set_value //////////////////////////////////////////
+fn redo_set_value(h: &OperationHistoryItem, tlm: &TreeListModel, doc: &ScenarioDocument, t: &mut Touched) -> TreeResult<()>{
+    let v= h.new_value.as_ref().ok_or(TreeError::NoSnapshot(h.id))?;
+    t.extend( set_value_at(tlm, &resolve_path(tlm, doc, &h.src, h.id), v.clone())? );
+    Ok(())
 }
-fn redo_mv_to_dest_child(h: &OperationHistoryItem){
-    let dest_sno   = h.dest.sno.as_ref().unwrap();
-    let dest_store = h.dest.store.as_ref().unwrap();
-    let dest_row   = h.dest.row.as_ref().unwrap();
-    let src_store  = h.src.store.as_ref().unwrap();
-    let src_sno    = h.src.sno.as_ref().unwrap();
-    let new_sno    = h.new_sno.as_ref().unwrap();
-
-    remove_node(&src_store, &src_sno);
-    add_child(&dest_sno, &new_sno, &dest_row, &dest_store);
+// redo_transaction ////////////////////////////////////////
+/// when an item fails, the items redone before it are undone
+fn redo_transaction(h: &OperationHistoryItem, tlm: &TreeListModel, doc: &ScenarioDocument, t: &mut Touched) -> TreeResult<()>{
+    for (i, c) in h.children.iter().enumerate() {
+        if let Err(e) = redo_item(c, tlm, doc, t) {
+            for d in h.children[..i].iter().rev() {
+                let _= undo_item(d, tlm, doc, t); }
+            return Err(e);
+        }
+    }
+    Ok(())
 }
-fn redo_mv_to_parent(h: &OperationHistoryItem){
-    let src_store  = h.src.store.as_ref().unwrap();
-    let dest_store = h.dest.store.as_ref().unwrap();
-    let dest_sno   = h.dest.sno.as_ref().unwrap();
-    let new_sno    = h.new_sno.as_ref().unwrap();
-
-    remove_node(&src_store, &new_sno);
-    adj_seq( &dest_store, 0, 1 );
-    ScenarioNode::mv_to_parent(dest_sno.get_node(), new_sno.get_node());
-    new_sno.set_seq(0);
-    dest_store.insert( 0, new_sno.as_ref() );
+// redo_item ///////////////////////////////////////////////
+fn redo_item(h: &OperationHistoryItem, tlm: &TreeListModel, doc: &ScenarioDocument, t: &mut Touched) -> TreeResult<()>{
+    match h.ope.get() {
+        Operation::Remove             => redo_remove(h, tlm, doc, t),
+        Operation::AddNeighbor        => redo_add(h, tlm, t),
+        Operation::AddChild           => redo_add(h, tlm, t),
+        Operation::AddRoot            => redo_add(h, tlm, t),
+        Operation::MvToParentNeighbor => redo_move(h, tlm, doc, t),
+        Operation::MvToDestNeighbor   => redo_move(h, tlm, doc, t),
+        Operation::MvToParentChild    => redo_move(h, tlm, doc, t),
+        Operation::MvToDestChild      => redo_move(h, tlm, doc, t),
+        Operation::MvToParent         => redo_move(h, tlm, doc, t),
+        Operation::SetValue           => redo_set_value(h, tlm, doc, t),
+        Operation::Transaction        => redo_transaction(h, tlm, doc, t),
+        _ => Ok(())
+    }
 }
 // OperationHistory ////////////////////////////////////////
 impl OperationHistory{
     // new /////////////////////////////////////////////////
-    pub fn new(lv: ListView) -> OperationHistory{
+    pub fn new(lv: ListView, doc: Rc<ScenarioDocument>) -> OperationHistory{
         let oh=  OperationHistory {
-            history: RefCell::new(Vec::new()),
-            index  : Cell::new(0),
-            size   : Cell::new(0),
             lv     : RefCell::new(Some(lv.clone().into())),
+            doc,
+            ..Default::default()
         };
         oh
     }
     // push ////////////////////////////////////////////////
     pub fn push(&self, oh: OperationHistoryItem) {
+        // inside of a transaction
+        if let Some((_, items)) = self.pending.borrow_mut().last_mut() {
+            if let Some(last) = items.last_mut() {
+                if last.is_coalescable_with(&oh) {
+                    last.new_value= oh.new_value;
+                    return;
+                }
+            }
+            items.push(oh);
+            return;
+        }
+
+        if !self.sealed.get() && (self.index.get() == self.size.get()) && (self.index.get() > 0) {
+            let mut history= self.history.borrow_mut();
+            let last= &mut history[(self.index.get() - 1) as usize];
+            if last.is_coalescable_with(&oh) {
+                last.new_value= oh.new_value;
+                drop(history);
+                if self.saved.get() == Some(self.index.get()) { // the saved state is changed
+                    self.saved.set(None); }
+                self.notify_changed();
+                return;
+            }
+        }
+        self.sealed.set( oh.ope.get() != Operation::SetValue );
+        if self.saved.get().is_some_and(|s| s > self.index.get()) { // the saved state is going to be dropped
+            self.saved.set(None); }
+
         if self.history.borrow().len() > (self.index.get() as usize){
             self.history.borrow_mut().resize( self.index.get() as usize,
                                               OperationHistoryItem::default() );
@@ -354,63 +391,248 @@ impl OperationHistory{
         self.history.borrow_mut().push(oh);
         self.index.set( self.index.get() + 1 ); // index indicates lates empty slot
         self.size.set( self.index.get() );
+        self.apply_limit();
+
+        self.notify_changed();
     }
-    // redraw_all //////////////////////////////////////////
-    pub fn redraw_all(&self){
-        let list_model= self.lv.borrow().as_ref().unwrap().as_ref().model().unwrap() // SelectionModel
+    // seal ////////////////////////////////////////////////
+    /// stop coalescing, the next edit becomes a new item
+    pub fn seal(&self){
+        self.sealed.set(true);
+    }
+    // set_limit ///////////////////////////////////////////
+    /// keep at most `limit` items (the History panel), the oldest ones are dropped now
+    pub fn set_limit(&self, limit: usize){
+        self.limit.set(limit);
+        self.apply_limit();
+        self.notify_changed();
+    }
+    pub fn get_limit(&self) -> usize{
+        self.limit.get()
+    }
+    // apply_limit /////////////////////////////////////////
+    /// drop the oldest items which exceed the limit
+    fn apply_limit(&self){
+        let len= self.history.borrow().len();
+        let limit= self.limit.get().max(1);
+        if len <= limit {
+            return; }
+        let n= (len - limit).min(self.index.get() as usize); // keep the items which can be redone
+        self.history.borrow_mut().drain(0..n);
+        self.index.set( (self.index.get() - n as i32).max(0) );
+        self.size.set( (self.size.get() - n as i32).max(0) );
+        self.saved.set( self.saved.get().and_then(|s| (s >= n as i32).then_some(s - n as i32)) );
+    }
+    // begin ///////////////////////////////////////////////
+    /// open a named transaction, pushed items are grouped until commit()
+    pub fn begin(&self, name: &str){
+        self.pending.borrow_mut().push( (name.to_string(), Vec::new()) );
+    }
+    // commit //////////////////////////////////////////////
+    /// close the innermost transaction, empty one is discarded
+    pub fn commit(&self){
+        let pending= self.pending.borrow_mut().pop();
+        if let Some((name, items)) = pending {
+            if !items.is_empty() {
+                self.sealed.set(true);
+                self.push( OperationHistoryItem::new_transaction(&name, items) );
+            }
+        }
+    }
+    // abort ///////////////////////////////////////////////
+    /// close the innermost transaction undoing its items, e.g. when one of them failed
+    pub fn abort(&self) -> TreeResult<()>{
+        let Some((name, items)) = self.pending.borrow_mut().pop() else { return Ok(()) };
+        let (tlm, mut touched)= (self.tree_list_model(), Touched::new());
+        let result= undo_item( &OperationHistoryItem::new_transaction(&name, items), &tlm, &self.doc, &mut touched );
+        refresh_rows(&tlm, &touched);
+        result
+    }
+    // in_transaction //////////////////////////////////////
+    pub fn in_transaction(&self) -> bool{
+        !self.pending.borrow().is_empty()
+    }
+    // connect_changed /////////////////////////////////////
+    /// `f` is called when items or the index are changed
+    pub fn connect_changed<F: Fn() + 'static>(&self, f: F){
+        self.listeners.borrow_mut().push(Box::new(f));
+    }
+    fn notify_changed(&self){
+        for f in self.listeners.borrow().iter() {
+            f();
+        }
+    }
+    // connect_error ///////////////////////////////////////
+    /// `f` is called with a failed manipulation, e.g. to show a toast
+    pub fn connect_error<F: Fn(&TreeError) + 'static>(&self, f: F){
+        self.error_listeners.borrow_mut().push(Box::new(f));
+    }
+    pub fn report(&self, e: &TreeError){
+        for f in self.error_listeners.borrow().iter() {
+            f(e);
+        }
+    }
+    // mark_saved //////////////////////////////////////////
+    /// the current state is saved
+    pub fn mark_saved(&self){
+        self.saved.set( Some(self.index.get()) );
+        self.notify_changed();
+    }
+    /// true unless the tree is in the saved state
+    pub fn is_modified(&self) -> bool{
+        self.saved.get() != Some(self.index.get())
+    }
+    /// the index of the saved state, for a recovery file (recovery.rs)
+    pub fn get_saved(&self) -> Option<i32>{
+        self.saved.get()
+    }
+    pub fn set_saved(&self, saved: Option<i32>){
+        self.saved.set(saved);
+        self.notify_changed();
+    }
+    // accessors for the history panel /////////////////////
+    pub fn get_index(&self) -> i32{
+        self.index.get()
+    }
+    pub fn get_size(&self) -> i32{
+        self.size.get()
+    }
+    pub fn descriptions(&self) -> Vec<String>{
+        self.history.borrow()[0..(self.size.get() as usize)]
+            .iter().map(|h| h.description()).collect()
+    }
+    // document ////////////////////////////////////////////
+    pub fn document(&self) -> Rc<ScenarioDocument>{
+        self.doc.clone()
+    }
+    // lookup //////////////////////////////////////////////
+    /// the node of `id` in the current tree
+    pub fn lookup(&self, id: i32) -> Option<Rc<ScenarioNode>>{
+        self.doc.lookup( first_root(&self.tree_list_model()), id )
+    }
+    // tree_list_model /////////////////////////////////////
+    pub fn tree_list_model(&self) -> TreeListModel{
+        self.lv.borrow().as_ref().unwrap().as_ref().model().unwrap() // SelectionModel
             .downcast::<SingleSelection>().expect("SingleSelection")
             .model().unwrap() // TreeListModel
             .downcast::<TreeListModel>().expect("TreeListModel")
-            .model();          // ListModel
+    }
+    // redraw_all //////////////////////////////////////////
+    /// re-create every root row, undo / redo re-create the touched rows only (refresh_rows)
+    pub fn redraw_all(&self){
+        let list_model= self.tree_list_model().model(); // ListModel
         for i in 0..list_model.n_items() {
             list_model.items_changed(i, 1, 1);
         }
     }
 
     // undo ////////////////////////////////////////////////
+    /// false when there is nothing to undo or it failed, a failure is reported and the tree is kept
     pub fn undo(&self) -> bool{
-        if self.index.get() <= 0 {
-            return false; }
-        self.index.set( self.index.get() - 1); // decrement before get
-        let h= &self.history.borrow()[self.index.get() as usize];
-        match h.ope.get() {
-            Operation::Remove             => undo_remove(&h),
-            Operation::AddNeighbor        => undo_add_neighbor(&h),
-            Operation::AddChild           => undo_add_child(&h),
-            Operation::AddRoot            => undo_add_root(&h),
-            Operation::MvToParentNeighbor => undo_mv_to_parent_neighbor(&h),
-            Operation::MvToDestNeighbor   => undo_mv_to_dest_neighbor(&h),
-            Operation::MvToParentChild    => undo_mv_to_parent_child(&h),
-            Operation::MvToDestChild      => undo_mv_to_dest_child(&h),
-            Operation::MvToParent         => undo_mv_to_parent(&h),
-            _ => ()
+        let mut touched= Touched::new();
+        let result= self.undo_one(&mut touched);
+        refresh_rows(&self.tree_list_model(), &touched); // the rolled back ones too
+        match result {
+            Ok(true) => {
+                self.notify_changed();
+                true
+            },
+            Ok(false) => false,
+            Err(e)    => { self.report(&e); false },
         }
-
-        self.redraw_all();
-
-        true
+    }
+    fn undo_one(&self, t: &mut Touched) -> TreeResult<bool>{
+        if (self.index.get() <= 0) || self.in_transaction() {
+            return Ok(false); }
+        self.sealed.set(true);
+        let index= self.index.get() - 1;
+        undo_item( &self.history.borrow()[index as usize], &self.tree_list_model(), &self.doc, t )?;
+        self.index.set(index); // the index is kept when failed
+        Ok(true)
     }
     // redo ////////////////////////////////////////////////
     pub fn redo(&self) -> bool{
-        if self.index.get() >= self.size.get() {
-            return false; }
-        let h= &self.history.borrow()[self.index.get() as usize];
-        match h.ope.get() {
-            Operation::Remove             => redo_remove(&h),
-            Operation::AddNeighbor        => redo_add_neighbor(&h),
-            Operation::AddChild           => redo_add_child(&h),
-            Operation::AddRoot            => redo_add_root(&h),
-            Operation::MvToParentNeighbor => redo_mv_to_parent_neighbor(&h),
-            Operation::MvToDestNeighbor   => redo_mv_to_dest_neighbor(&h),
-            Operation::MvToParentChild    => redo_mv_to_parent_child(&h),
-            Operation::MvToDestChild      => redo_mv_to_dest_child(&h),
-            Operation::MvToParent         => redo_mv_to_parent(&h),
-            _ => ()
+        let mut touched= Touched::new();
+        let result= self.redo_one(&mut touched);
+        refresh_rows(&self.tree_list_model(), &touched);
+        match result {
+            Ok(true) => {
+                self.notify_changed();
+                true
+            },
+            Ok(false) => false,
+            Err(e)    => { self.report(&e); false },
         }
+    }
+    fn redo_one(&self, t: &mut Touched) -> TreeResult<bool>{
+        if (self.index.get() >= self.size.get()) || self.in_transaction() {
+            return Ok(false); }
+        self.sealed.set(true);
+        redo_item( &self.history.borrow()[self.index.get() as usize], &self.tree_list_model(), &self.doc, t )?;
         self.index.set( self.index.get() + 1 ); // increment after operation
-
-        self.redraw_all();
-
-        true
+        Ok(true)
+    }
+    // jump_to /////////////////////////////////////////////
+    /// undo/redo until the index becomes `index`, stops at a failure.
+    /// a row touched by several items is re-created once at the end
+    pub fn jump_to(&self, index: i32){
+        let (mut moved, mut touched)= (false, Touched::new());
+        let result= self.step_to(index, &mut moved, &mut touched);
+        refresh_rows(&self.tree_list_model(), &touched);
+        if moved {
+            self.notify_changed(); }
+        if let Err(e) = result {
+            self.report(&e); }
+    }
+    fn step_to(&self, index: i32, moved: &mut bool, t: &mut Touched) -> TreeResult<()>{
+        while self.index.get() > index && self.undo_one(t)? { *moved= true; }
+        while self.index.get() < index && self.redo_one(t)? { *moved= true; }
+        Ok(())
+    }
+    // clear ///////////////////////////////////////////////
+    pub fn clear(&self){
+        self.history.borrow_mut().clear();
+        self.pending.borrow_mut().clear();
+        self.index.set(0);
+        self.size.set(0);
+        self.sealed.set(true);
+        self.saved.set(Some(0));
+        self.notify_changed();
+    }
+    // save_to /////////////////////////////////////////////
+    /// save the items and the index with the fingerprint of the current tree,
+    /// the tree must be saved in the same state
+    pub fn save_to(&self, path: &Path) -> io::Result<()>{
+        let file= HistoryFile{
+            version: HISTORY_FILE_VERSION,
+            tree   : Some( self.fingerprint() ),
+            index  : self.index.get(),
+            items  : self.history.borrow()[0..(self.size.get() as usize)].to_vec(),
+        };
+        let json= serde_json::to_string(&file).map_err(io::Error::from)?;
+        fs::write(path, json)
+    }
+    fn fingerprint(&self) -> Fingerprint{
+        fingerprint( first_root(&self.tree_list_model()), self.doc.get_next_id() )
+    }
+    // load_from ///////////////////////////////////////////
+    /// replace the items by the saved ones, the loaded state is the saved state.
+    /// a file saved with another tree (e.g. the scenario is changed by the CLI, a merge or git)
+    /// is rejected and the items are kept
+    pub fn load_from(&self, path: &Path) -> io::Result<()>{
+        let json= fs::read_to_string(path)?;
+        let file: HistoryFile= serde_json::from_str(&json).map_err(io::Error::from)?;
+        if file.tree != Some( self.fingerprint() ) {
+            return Err( io::Error::new(io::ErrorKind::InvalidData, "it is saved with another tree") ); }
+        let size= file.items.len() as i32;
+        *self.history.borrow_mut()= file.items;
+        self.pending.borrow_mut().clear();
+        self.size.set(size);
+        self.index.set(file.index.clamp(0, size));
+        self.sealed.set(true);
+        self.saved.set( Some(self.index.get()) );
+        self.apply_limit();
+        self.notify_changed();
+        Ok(())
     }
 }
@@ -0,0 +1,180 @@
+//! navigation of a large tree: a breadcrumb bar and a minimap
+//!
+//! the breadcrumb shows the ancestors of the selected node and the node, clicking one selects it.
+//! the minimap draws every node (collapsed ones too) in pre-order as a bar indented by the depth,
+//! coloured by the kind, with the selected node marked. clicking it selects the node at that height.
+
+use std::cell::RefCell;
+use std::rc::{Rc, Weak};
+use gtk::prelude::*;
+use gtk::cairo;
+use gtk::{Box, Button, DrawingArea, GestureClick, Label, Orientation, PolicyType, ScrolledWindow, SingleSelection};
+
+use crate::color::Color;
+use crate::operation_history::OperationHistory;
+use crate::scenario_node::{ScenarioNode, ItemKind};
+use crate::scenario_node_object::{first_root, selected_node, select_node};
+use crate::scenario_query;
+
+const MINIMAP_WIDTH : i32 = 48;
+const MINIMAP_INDENT: f64 = 4.0;
+
+// kind_color //////////////////////////////////////////////
+pub fn kind_color(k: ItemKind) -> Color {
+    match k {
+        ItemKind::Group => Color::rgb(128, 128, 128),
+        ItemKind::Scene => Color::rgb( 64, 115, 217),
+        ItemKind::Page  => Color::rgb( 77, 179, 102),
+        ItemKind::Mat   => Color::rgb(242, 166,  51),
+        ItemKind::Ovimg => Color::rgb(179, 102, 204),
+        ItemKind::Pmat  => Color::rgb(230,  89,  89),
+    }
+}
+/// "scene intro" with the label, otherwise "page id:3"
+pub fn crumb_label(n: &ScenarioNode) -> String {
+    let v= n.value.borrow();
+    match v.lbl() {
+        Some(l) => format!("{} {}", v.kind().name(), l),
+        None    => format!("{} id:{}", v.kind().name(), n.id.get()),
+    }
+}
+/// select `id` in the current tree
+fn select_id(history: &OperationHistory, selection: &SingleSelection, id: i32) {
+    if let Some(n) = history.lookup(id) {
+        select_node(&history.tree_list_model(), selection, &n); }
+}
+
+// Breadcrumb //////////////////////////////////////////////
+struct Breadcrumb {
+    history  : Weak<OperationHistory>,
+    selection: SingleSelection,
+    bar      : Box,
+}
+impl Breadcrumb {
+    fn refresh(self: &Rc<Self>) {
+        while let Some(c) = self.bar.first_child() {
+            self.bar.remove(&c); }
+        let Some(node) = selected_node(&self.selection) else {
+            self.bar.append( &Label::new(Some("(nothing selected)")) );
+            return;
+        };
+        let mut chain: Vec<Rc<ScenarioNode>>= scenario_query::ancestors(&node).collect();
+        chain.reverse();
+        chain.push(node);
+        for (i, n) in chain.iter().enumerate() {
+            if i > 0 {
+                self.bar.append( &Label::new(Some("›")) ); }
+            let b= Button::with_label( &crumb_label(n) );
+            b.add_css_class("flat");
+            let (crumb, id)= (Rc::downgrade(self), n.id.get());
+            b.connect_clicked(move |_| {
+                let Some(c) = crumb.upgrade() else { return };
+                if let Some(h) = c.history.upgrade() {
+                    select_id(&h, &c.selection, id); }
+            });
+            self.bar.append(&b);
+        }
+    }
+}
+// build_breadcrumb ////////////////////////////////////////
+pub fn build_breadcrumb(history: Rc<OperationHistory>, selection: SingleSelection) -> ScrolledWindow {
+    let crumb= Rc::new( Breadcrumb{
+        history  : Rc::downgrade(&history),
+        selection: selection.clone(),
+        bar      : Box::new(Orientation::Horizontal, 0),
+    } );
+    let c= Rc::downgrade(&crumb);
+    selection.connect_selection_changed(move |_, _, _| {
+        if let Some(c) = c.upgrade() { c.refresh(); }
+    });
+    let crumb_for_history= crumb.clone(); // the breadcrumb lives as long as the history
+    history.connect_changed(move || crumb_for_history.refresh()); // a label or an ancestor may be changed
+    crumb.refresh();
+
+    ScrolledWindow::builder()
+        .hscrollbar_policy(PolicyType::Automatic)
+        .vscrollbar_policy(PolicyType::Never)
+        .child(&crumb.bar)
+        .build()
+}
+
+// Minimap /////////////////////////////////////////////////
+struct Minimap {
+    history  : Weak<OperationHistory>,
+    selection: SingleSelection,
+    area     : DrawingArea,
+    nodes    : RefCell<Vec<(usize, ItemKind, i32)>>, // depth, kind and id in pre-order
+}
+impl Minimap {
+    fn refresh(&self) {
+        let Some(h) = self.history.upgrade() else { return };
+        *self.nodes.borrow_mut()= scenario_query::pre_order_forest_depth( first_root(&h.tree_list_model()) )
+            .map(|(n, depth)| (depth, n.value.borrow().kind(), n.id.get()))
+            .collect();
+        self.area.queue_draw();
+    }
+    fn draw(&self, cr: &cairo::Context, w: f64, h: f64) {
+        cr.set_source_rgb(0.96, 0.96, 0.96);
+        cr.paint().ok();
+        let nodes= self.nodes.borrow();
+        if nodes.is_empty() {
+            return; }
+        let step= h / nodes.len() as f64;
+        let selected= selected_node(&self.selection).map(|n| n.id.get());
+        for (i, (depth, kind, id)) in nodes.iter().enumerate() {
+            let (r, g, b)= kind_color(*kind).to_f64();
+            let x= *depth as f64 * MINIMAP_INDENT;
+            cr.set_source_rgb(r, g, b);
+            cr.rectangle(x, i as f64 * step, (w - x).max(1.0), step.max(1.0));
+            cr.fill().ok();
+            if Some(*id) == selected {
+                cr.set_source_rgb(0.0, 0.0, 0.0);
+                cr.set_line_width(2.0);
+                cr.rectangle(1.0, i as f64 * step, w - 2.0, step.max(2.0));
+                cr.stroke().ok();
+            }
+        }
+    }
+    fn click(&self, y: f64) {
+        let h= self.area.height() as f64;
+        let id= {
+            let nodes= self.nodes.borrow();
+            if nodes.is_empty() || h <= 0.0 {
+                return; }
+            let i= ((y / h) * nodes.len() as f64) as usize;
+            nodes[i.min(nodes.len() - 1)].2
+        };
+        if let Some(hist) = self.history.upgrade() {
+            select_id(&hist, &self.selection, id); }
+    }
+}
+// build_minimap ///////////////////////////////////////////
+pub fn build_minimap(history: Rc<OperationHistory>, selection: SingleSelection) -> DrawingArea {
+    let map= Rc::new( Minimap{
+        history  : Rc::downgrade(&history),
+        selection: selection.clone(),
+        area     : DrawingArea::builder().content_width(MINIMAP_WIDTH).vexpand(true).build(),
+        nodes    : RefCell::new(Vec::new()),
+    } );
+    map.area.set_tooltip_text(Some("the whole tree, click to jump"));
+    let m= Rc::downgrade(&map);
+    map.area.set_draw_func(move |_, cr, w, h| {
+        if let Some(m) = m.upgrade() { m.draw(cr, w as f64, h as f64); }
+    });
+    let click= GestureClick::new();
+    let m= Rc::downgrade(&map);
+    click.connect_pressed(move |_, _, _, y| {
+        if let Some(m) = m.upgrade() { m.click(y); }
+    });
+    map.area.add_controller(click);
+
+    let area_for_selection= map.area.downgrade();
+    selection.connect_selection_changed(move |_, _, _| {
+        if let Some(a) = area_for_selection.upgrade() { a.queue_draw(); }
+    });
+    let map_for_history= map.clone(); // the minimap lives as long as the history
+    history.connect_changed(move || map_for_history.refresh());
+    map.refresh();
+
+    map.area.clone()
+}
@@ -0,0 +1,260 @@
+//! Play mode: steps through the click units (page / pmat) in the reading order,
+//! the pre-order of the tree.
+//!
+//! a click state is drawn with the background of its scene and the mats and ovimgs
+//! of the units of the scene up to the current one, the later over the earlier.
+//! next: → space enter or a click, prev: ← backspace, first / last: home / end.
+//! a label (lbl of a scene / mat / pmat) jumps to its unit, or to the first unit after it.
+//! the current unit is selected in the ListView, the window follows the edits.
+
+use std::cell::{Cell, RefCell};
+use std::path::PathBuf;
+use std::rc::{Rc, Weak};
+use gtk::prelude::*;
+use gtk::{gdk, glib};
+use gtk::{Box, Button, DrawingArea, DropDown, EventControllerKey, GestureClick, Label, Orientation,
+          SingleSelection, StringList, Window};
+
+use crate::scenario_node::{ScenarioNode, Item};
+use crate::scenario_query::{ancestors, pre_order_forest};
+use crate::operation_history::OperationHistory;
+use crate::scenario_node_object::{first_root, select_node};
+use crate::render::{self, Canvas};
+use crate::text_source::SourceCache;
+
+// click_units /////////////////////////////////////////////
+fn is_unit(n: &Rc<ScenarioNode>) -> bool {
+    matches!(*n.value.borrow(), Item::Page | Item::Pmat(_))
+}
+/// the pages and pmats in the reading order
+pub fn click_units(first_root: Option<Rc<ScenarioNode>>) -> Vec<Rc<ScenarioNode>> {
+    pre_order_forest(first_root).filter(is_unit).collect()
+}
+/// the units drawn for the click state of units[i]: the units of the same scene up to i
+pub fn click_state(units: &[Rc<ScenarioNode>], i: usize) -> &[Rc<ScenarioNode>] {
+    let scene= render::scene_node_of(&units[i]);
+    let same= |u: &Rc<ScenarioNode>| match (&scene, render::scene_node_of(u)) {
+        (Some(a), Some(b)) => Rc::ptr_eq(a, &b),
+        (None,    None)    => true,
+        _                  => false,
+    };
+    let start= units[..i].iter().rposition(|u| !same(u)).map_or(0, |j| j + 1);
+    &units[start..=i]
+}
+/// the index of the unit shown for `node`: its unit, or the first unit after it
+pub fn unit_index(units: &[Rc<ScenarioNode>], first_root: Option<Rc<ScenarioNode>>,
+                  node: &Rc<ScenarioNode>) -> Option<usize> {
+    let position= |n: &Rc<ScenarioNode>| units.iter().position(|u| Rc::ptr_eq(u, n));
+    if let Some(i) = std::iter::once(node.clone()).chain(ancestors(node)).find_map(|n| position(&n)) {
+        return Some(i); }
+    pre_order_forest(first_root)
+        .skip_while(|n| !Rc::ptr_eq(n, node))
+        .find_map(|n| position(&n))
+}
+/// the labelled nodes in the reading order
+pub fn labels(first_root: Option<Rc<ScenarioNode>>) -> Vec<(String, Rc<ScenarioNode>)> {
+    pre_order_forest(first_root)
+        .filter_map(|n| { let l= n.value.borrow().lbl()?; Some( (l, n.clone()) ) })
+        .collect()
+}
+// Player //////////////////////////////////////////////////
+struct Player {
+    root     : PathBuf, // asset root
+    history  : Weak<OperationHistory>,
+    selection: SingleSelection,
+    sources  : Rc<SourceCache>,
+    canvas   : Rc<Cell<Canvas>>,
+    area     : DrawingArea,
+    position : Label,
+    labels   : StringList,
+    label_box: DropDown,
+    units    : RefCell<Vec<Rc<ScenarioNode>>>,
+    targets  : RefCell<Vec<Rc<ScenarioNode>>>, // the labelled nodes, in the order of `labels`
+    index    : Cell<usize>,
+}
+impl Player {
+    // refresh /////////////////////////////////////////////
+    /// collect the units and the labels again, the current unit is kept if it still exists
+    fn refresh(&self) {
+        let Some(h) = self.history.upgrade() else { return };
+        let tlm    = h.tree_list_model();
+        let current= self.units.borrow().get(self.index.get()).cloned();
+        let units  = click_units(first_root(&tlm));
+        let index  = current.and_then(|c| units.iter().position(|u| Rc::ptr_eq(u, &c)))
+            .unwrap_or( self.index.get().min(units.len().saturating_sub(1)) );
+        *self.units.borrow_mut()= units;
+        self.index.set(index);
+
+        let (names, targets): (Vec<String>, Vec<_>)= labels(first_root(&tlm)).into_iter().unzip();
+        let selected= self.label_box.selected();
+        self.labels.splice(0, self.labels.n_items(), &names.iter().map(|n| n.as_str()).collect::<Vec<_>>());
+        self.label_box.set_selected(selected.min(names.len().saturating_sub(1) as u32));
+        *self.targets.borrow_mut()= targets;
+        self.update();
+    }
+    /// show the current state
+    fn update(&self) {
+        let units= self.units.borrow();
+        self.position.set_label( &match units.get(self.index.get()) {
+            Some(u) => format!("{} / {}  (id:{})", self.index.get() + 1, units.len(), u.id.get()),
+            None    => String::from("no page"),
+        } );
+        self.area.queue_draw();
+    }
+    // move ////////////////////////////////////////////////
+    /// go to units[i] and select it in the ListView
+    fn show(&self, i: usize) {
+        let unit= self.units.borrow().get(i).cloned();
+        let (Some(u), Some(h)) = (unit, self.history.upgrade()) else { return };
+        self.index.set(i);
+        select_node(&h.tree_list_model(), &self.selection, &u);
+        self.update();
+    }
+    fn next(&self) {
+        self.show( self.index.get() + 1 );
+    }
+    fn prev(&self) {
+        if let Some(i) = self.index.get().checked_sub(1) {
+            self.show(i); }
+    }
+    fn last(&self) {
+        let n= self.units.borrow().len();
+        if n > 0 {
+            self.show(n - 1); }
+    }
+    /// go to the unit of the selected label
+    fn jump(&self) {
+        let target= self.targets.borrow().get(self.label_box.selected() as usize).cloned();
+        let (Some(t), Some(h)) = (target, self.history.upgrade()) else { return };
+        let i= unit_index(&self.units.borrow(), first_root(&h.tree_list_model()), &t);
+        match i {
+            Some(i) => self.show(i),
+            None    => self.position.set_label("no page after the label"),
+        }
+    }
+    // draw ////////////////////////////////////////////////
+    fn draw(&self, cr: &gtk::cairo::Context) {
+        cr.set_source_rgb(0.0, 0.0, 0.0);
+        cr.paint().ok();
+        let units= self.units.borrow();
+        if units.is_empty() {
+            return; }
+        let canvas= self.canvas.get();
+        let (scale, ox, oy)= render::fit(&self.area, canvas);
+        cr.translate(ox, oy);
+        cr.scale(scale, scale);
+        cr.rectangle(0.0, 0.0, canvas.w as f64, canvas.h as f64);
+        cr.clip();
+        let i= self.index.get().min(units.len() - 1);
+        render::render_units(cr, click_state(&units, i), &self.sources, &self.root, canvas);
+    }
+}
+// open_player /////////////////////////////////////////////
+/// open the play window, starting from the unit of `start` (or the first unit)
+pub fn open_player(parent: Option<&Window>, history: Rc<OperationHistory>, selection: SingleSelection,
+                   sources: Rc<SourceCache>, root: PathBuf, canvas: Rc<Cell<Canvas>>,
+                   start: Option<Rc<ScenarioNode>>) {
+    let labels= StringList::new(&[]);
+    let (w, h)= canvas.get().size();
+    let player= Rc::new( Player{
+        root,
+        history  : Rc::downgrade(&history),
+        selection,
+        sources  : sources.clone(),
+        canvas,
+        area     : DrawingArea::builder()
+            .content_width ((w / 2.0) as i32)
+            .content_height((h / 2.0) as i32)
+            .hexpand(true)
+            .vexpand(true)
+            .build(),
+        position : Label::builder().xalign(0.0).hexpand(true).build(),
+        label_box: DropDown::new(Some(labels.clone()), None::<gtk::Expression>),
+        labels,
+        units    : RefCell::new(Vec::new()),
+        targets  : RefCell::new(Vec::new()),
+        index    : Cell::new(0),
+    } );
+    player.refresh();
+    let tlm= history.tree_list_model();
+    if let Some(i) = start.and_then(|s| unit_index(&player.units.borrow(), first_root(&tlm), &s)) {
+        player.index.set(i); }
+    player.show( player.index.get() );
+
+    let p= Rc::downgrade(&player);
+    player.area.set_draw_func(move |_, cr, _, _| {
+        if let Some(p) = p.upgrade() { p.draw(cr); }
+    });
+    let click= GestureClick::new();
+    let p= Rc::downgrade(&player);
+    click.connect_released(move |_, _, _, _| {
+        if let Some(p) = p.upgrade() { p.next(); }
+    });
+    player.area.add_controller(click);
+
+    let prev= Button::with_label("◀ prev");
+    let next= Button::with_label("next ▶");
+    let jump= Button::with_label("jump");
+    let p= Rc::downgrade(&player);
+    prev.connect_clicked(move |_| {
+        if let Some(p) = p.upgrade() { p.prev(); }
+    });
+    let p= Rc::downgrade(&player);
+    next.connect_clicked(move |_| {
+        if let Some(p) = p.upgrade() { p.next(); }
+    });
+    let p= Rc::downgrade(&player);
+    jump.connect_clicked(move |_| {
+        if let Some(p) = p.upgrade() { p.jump(); }
+    });
+
+    // the edits are shown at once
+    let p= Rc::downgrade(&player);
+    history.connect_changed(move || {
+        if let Some(p) = p.upgrade() { p.refresh(); }
+    });
+    let p= Rc::downgrade(&player);
+    sources.connect_changed(move |_| {
+        if let Some(p) = p.upgrade() { p.update(); }
+    });
+
+    let bar= Box::new(Orientation::Horizontal, 4);
+    bar.append(&prev);
+    bar.append(&next);
+    bar.append(&player.position);
+    bar.append(&player.label_box);
+    bar.append(&jump);
+    let vbox= Box::new(Orientation::Vertical, 2);
+    vbox.append(&player.area);
+    vbox.append(&bar);
+
+    let window= Window::builder()
+        .title("play")
+        .child(&vbox)
+        .build();
+    window.set_transient_for(parent);
+
+    let keys= EventControllerKey::new();
+    let p= Rc::downgrade(&player);
+    let window_for_keys= window.downgrade();
+    keys.connect_key_pressed(move |_, key, _, _| {
+        let Some(p) = p.upgrade() else { return glib::Propagation::Proceed };
+        match key {
+            gdk::Key::Right | gdk::Key::space | gdk::Key::Return | gdk::Key::Page_Down => p.next(),
+            gdk::Key::Left  | gdk::Key::BackSpace | gdk::Key::Page_Up                 => p.prev(),
+            gdk::Key::Home   => p.show(0),
+            gdk::Key::End    => p.last(),
+            gdk::Key::Escape => if let Some(w) = window_for_keys.upgrade() { w.close(); },
+            _ => return glib::Propagation::Proceed,
+        }
+        glib::Propagation::Stop
+    });
+    window.add_controller(keys);
+
+    // the player lives as long as the window
+    window.connect_close_request(move |_| {
+        let _= &player;
+        glib::Propagation::Proceed
+    });
+    window.present();
+}
@@ -0,0 +1,381 @@
+//! Preview: draws the page of the selected node with render.rs,
+//! scaled to fit the area. redrawn when the selection, the history or a source file is changed.
+//!
+//! the mats and ovimgs on the canvas are edited directly:
+//! click an element to select its row, drag it to move,
+//! drag a corner handle to resize and the round handle to change the corner radius (mat only).
+//! with "snap" the edges are snapped to the grid, the canvas and the edges of the other elements.
+//! a drag is one undoable step.
+//! the canvas is one of render::RESOLUTIONS, "%" switches the geometry of the selected element
+//! between pixels and percent of the canvas (geometry.rs), a drag keeps the mode.
+
+use std::cell::{Cell, RefCell};
+use std::path::{Path, PathBuf};
+use std::rc::{Rc, Weak};
+use gtk::prelude::*;
+use gtk::cairo;
+use gtk::{Box, CheckButton, DrawingArea, DropDown, GestureDrag, Orientation, SingleSelection};
+
+use crate::render::{self, Canvas, RESOLUTIONS, CANVAS_W, CANVAS_H};
+use crate::scenario_node::{ScenarioNode, Item, Length};
+use crate::operation_history::{OperationHistory, OperationHistoryItem};
+use crate::scenario_node_object::{selected_node, select_node};
+use crate::text_source::SourceCache;
+
+const HANDLE       : f64 = 8.0;  // size of a handle in the widget coordinates
+const GRID         : f64 = 16.0; // in the canvas coordinates
+const SNAP_DISTANCE: f64 = 8.0;  // in the widget coordinates
+const RADIUS_MIN   : f64 = 12.0; // the offset of the radius handle of a small radius
+
+// Bounds //////////////////////////////////////////////////
+/// a rectangle in the canvas coordinates
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Bounds {
+    x: f64,
+    y: f64,
+    w: f64,
+    h: f64,
+}
+impl Bounds {
+    fn of(v: &Item, asset_root: &Path, canvas: Canvas) -> Option<Bounds> {
+        render::bounds(v, asset_root, canvas).map(|(x, y, w, h)| Bounds{ x, y, w, h })
+    }
+    fn contains(&self, x: f64, y: f64) -> bool {
+        (self.x..=self.x + self.w).contains(&x) && (self.y..=self.y + self.h).contains(&y)
+    }
+    fn corners(&self) -> [(f64, f64); 4] {
+        [(self.x, self.y), (self.x + self.w, self.y), (self.x, self.y + self.h), (self.x + self.w, self.y + self.h)]
+    }
+}
+/// the center of the radius handle of a mat
+fn radius_handle(b: &Bounds, r: f64) -> (f64, f64) {
+    let d= r.max(RADIUS_MIN).min(b.w / 2.0).min(b.h / 2.0);
+    (b.x + d, b.y + d)
+}
+// snap_offset /////////////////////////////////////////////
+/// the offset which moves one of `vs` onto the nearest line within `distance`, 0 without such a line.
+/// the lines are `lines` and the grid
+fn snap_offset(vs: &[f64], lines: &[f64], distance: f64) -> f64 {
+    let mut best: Option<f64>= None;
+    for v in vs {
+        let grid= (v / GRID).round() * GRID;
+        for l in lines.iter().chain(std::iter::once(&grid)) {
+            let d= l - v;
+            if d.abs() <= distance && best.is_none_or(|b| d.abs() < b.abs()) {
+                best= Some(d); }
+        }
+    }
+    best.unwrap_or(0.0)
+}
+// Grip ////////////////////////////////////////////////////
+#[derive(Debug, Clone, Copy)]
+enum Grip {
+    Move,
+    Resize{ ax: f64, ay: f64, px: f64, py: f64 }, // fixed corner, grabbed corner
+    Radius,
+}
+impl Grip {
+    /// the edited field for the history
+    fn field(&self) -> &'static str {
+        match self {
+            Grip::Move         => "pos",
+            Grip::Resize{ .. } => "dim",
+            Grip::Radius       => "r",
+        }
+    }
+}
+struct Drag {
+    node : Rc<ScenarioNode>,
+    grip : Grip,
+    start: Bounds,
+    r    : f64,
+    old  : Item,                 // the value before the drag
+    lines: (Vec<f64>, Vec<f64>), // x and y of the edges to snap to
+}
+// Preview /////////////////////////////////////////////////
+struct Preview {
+    root     : PathBuf, // asset root
+    history  : Weak<OperationHistory>,
+    selection: SingleSelection,
+    sources  : Rc<SourceCache>,
+    canvas   : Rc<Cell<Canvas>>,
+    area     : DrawingArea,
+    snap     : CheckButton,
+    relative : CheckButton,
+    drag     : RefCell<Option<Drag>>,
+    updating : Cell<bool>, // true while `relative` is set from the node
+}
+impl Preview {
+    /// (scale, offset x, offset y) from the canvas to the widget
+    fn transform(&self) -> (f64, f64, f64) {
+        render::fit(&self.area, self.canvas.get())
+    }
+    fn bounds(&self, v: &Item) -> Option<Bounds> {
+        Bounds::of(v, &self.root, self.canvas.get())
+    }
+    /// the selected node if it is drawn on the canvas
+    fn selected_element(&self) -> Option<Rc<ScenarioNode>> {
+        selected_node(&self.selection)
+            .filter(|n| matches!(*n.value.borrow(), Item::Mat(_) | Item::Pmat(_) | Item::Ovimg(_)))
+    }
+    // draw ////////////////////////////////////////////////
+    fn draw(&self, cr: &cairo::Context) {
+        cr.set_source_rgb(0.5, 0.5, 0.5);
+        cr.paint().ok();
+        let Some(node) = selected_node(&self.selection) else { return };
+
+        let (scale, ox, oy)= self.transform();
+        cr.save().ok();
+        cr.translate(ox, oy);
+        cr.scale(scale, scale);
+        let canvas= self.canvas.get();
+        cr.rectangle(0.0, 0.0, canvas.w as f64, canvas.h as f64);
+        cr.clip();
+        render::render(cr, &node, &self.sources, &self.root, canvas);
+        cr.restore().ok();
+
+        // the selected element and its handles, in the widget coordinates
+        let Some(e) = self.selected_element() else { return };
+        let v= e.value.borrow().clone();
+        let Some(b) = self.bounds(&v) else { return };
+        let (x, y, w, h)= (ox + b.x * scale, oy + b.y * scale, b.w * scale, b.h * scale);
+        cr.set_source_rgb(1.0, 0.6, 0.0);
+        cr.set_line_width(1.0);
+        cr.rectangle(x, y, w, h);
+        cr.stroke().ok();
+        if let Item::Mat(m) | Item::Pmat(m) = &v {
+            for (hx, hy) in b.corners() {
+                cr.rectangle(ox + hx * scale - HANDLE / 2.0, oy + hy * scale - HANDLE / 2.0, HANDLE, HANDLE); }
+            cr.fill().ok();
+            let (rx, ry)= radius_handle(&b, m.r as f64);
+            cr.arc(ox + rx * scale, oy + ry * scale, HANDLE / 2.0, 0.0, 2.0 * std::f64::consts::PI);
+            cr.fill().ok();
+        }
+    }
+    // drag ////////////////////////////////////////////////
+    /// the grip of the selected element at the canvas point (px, py)
+    fn grip_at(&self, px: f64, py: f64, scale: f64) -> Option<(Rc<ScenarioNode>, Grip)> {
+        let e= self.selected_element()?;
+        let v= e.value.borrow().clone();
+        let b= self.bounds(&v)?;
+        let near= |(x, y): (f64, f64)| (x - px).abs() * scale <= HANDLE && (y - py).abs() * scale <= HANDLE;
+        if let Item::Mat(m) | Item::Pmat(m) = &v {
+            if near(radius_handle(&b, m.r as f64)) {
+                return Some( (e, Grip::Radius) ); }
+            if let Some((cx, cy)) = b.corners().into_iter().find(|c| near(*c)) {
+                let grip= Grip::Resize{ ax: 2.0 * b.x + b.w - cx, ay: 2.0 * b.y + b.h - cy, px: cx, py: cy };
+                return Some( (e, grip) );
+            }
+        }
+        b.contains(px, py).then_some( (e, Grip::Move) )
+    }
+    fn drag_begin(&self, wx: f64, wy: f64) {
+        let Some(node) = selected_node(&self.selection) else { return };
+        let Some(unit) = render::display_unit(&node) else { return };
+        let (scale, ox, oy)= self.transform();
+        let (px, py)= ((wx - ox) / scale, (wy - oy) / scale);
+
+        // the handles of the selected element first, then the topmost element under the point
+        let elements= render::elements(&unit);
+        let hit= self.grip_at(px, py, scale).or_else(|| {
+            elements.iter().rev()
+                .find(|e| self.bounds(&e.value.borrow()).is_some_and(|b| b.contains(px, py)))
+                .map(|e| (e.clone(), Grip::Move))
+        });
+        let Some((e, grip)) = hit else { return };
+        if let (false, Some(h)) = (Rc::ptr_eq(&e, &node), self.history.upgrade()) {
+            select_node(&h.tree_list_model(), &self.selection, &e); }
+
+        let v= e.value.borrow().clone();
+        let Some(start) = self.bounds(&v) else { return };
+        let r= match &v {
+            Item::Mat(m) | Item::Pmat(m) => m.r as f64,
+            _                            => 0.0,
+        };
+        let (cw, ch)= self.canvas.get().size();
+        let mut lines= (vec![0.0, cw / 2.0, cw], vec![0.0, ch / 2.0, ch]);
+        for o in elements.iter().filter(|o| !Rc::ptr_eq(o, &e)) {
+            if let Some(b) = self.bounds(&o.value.borrow()) {
+                lines.0.extend([b.x, b.x + b.w]);
+                lines.1.extend([b.y, b.y + b.h]);
+            }
+        }
+        *self.drag.borrow_mut()= Some( Drag{ node: e, grip, start, r, old: v, lines } );
+    }
+    fn drag_update(&self, dx: f64, dy: f64) {
+        let drag= self.drag.borrow();
+        let Some(d) = drag.as_ref() else { return };
+        let (scale, _, _)= self.transform();
+        let (dx, dy)= (dx / scale, dy / scale);
+        let distance= if self.snap.is_active() { SNAP_DISTANCE / scale } else { -1.0 };
+        let s= d.start;
+        let (cw, ch)= self.canvas.get().size();
+        let x_of= |l: Length, v: f64| l.with_resolved(v, cw).rounded();
+        let y_of= |l: Length, v: f64| l.with_resolved(v, ch).rounded();
+
+        let mut v= d.node.value.borrow().clone();
+        let (pos, dim, r)= match &mut v {
+            Item::Mat(m) | Item::Pmat(m) => (&mut m.pos, Some(&mut m.dim), Some(&mut m.r)),
+            Item::Ovimg(o)               => (&mut o.pos, None, None),
+            _                            => return,
+        };
+        match d.grip {
+            Grip::Move => {
+                let (x, y)= (s.x + dx, s.y + dy);
+                let x= x + snap_offset(&[x, x + s.w], &d.lines.0, distance);
+                let y= y + snap_offset(&[y, y + s.h], &d.lines.1, distance);
+                (pos.x, pos.y)= (x_of(pos.x, x), y_of(pos.y, y));
+            },
+            Grip::Resize{ ax, ay, px, py } => {
+                let (x, y)= (px + dx, py + dy);
+                let x= x + snap_offset(&[x], &d.lines.0, distance);
+                let y= y + snap_offset(&[y], &d.lines.1, distance);
+                (pos.x, pos.y)= (x_of(pos.x, ax.min(x)), y_of(pos.y, ay.min(y)));
+                if let Some(dim) = dim {
+                    (dim.w, dim.h)= (x_of(dim.w, (x - ax).abs().max(1.0)), y_of(dim.h, (y - ay).abs().max(1.0))); }
+            },
+            Grip::Radius => {
+                if let Some(r) = r {
+                    *r= (d.r + dx).clamp(0.0, s.w.min(s.h) / 2.0).round() as usize; }
+            },
+        }
+        d.node.set_value(v);
+        self.area.queue_draw();
+    }
+    /// push the drag as one step, not coalesced with the previous drag
+    fn drag_end(&self) {
+        let Some(d) = self.drag.borrow_mut().take() else { return };
+        let new= d.node.value.borrow().clone();
+        let changed= match (&d.old, &new) {
+            (Item::Mat(a), Item::Mat(b)) | (Item::Pmat(a), Item::Pmat(b)) =>
+                (&a.pos, &a.dim, a.r) != (&b.pos, &b.dim, b.r),
+            (Item::Ovimg(a), Item::Ovimg(b)) => a.pos != b.pos,
+            _                                => false,
+        };
+        if let (true, Some(h)) = (changed, self.history.upgrade()) {
+            h.seal();
+            h.push( OperationHistoryItem::new_set_value(&d.node, d.grip.field(), d.old, new) );
+        }
+    }
+    // relative ////////////////////////////////////////////
+    /// show the mode of the selected element
+    fn load(&self) {
+        let e= self.selected_element();
+        let percent= e.as_ref().is_some_and(|e| match &*e.value.borrow() {
+            Item::Mat(m) | Item::Pmat(m) => m.pos.x.is_percent(),
+            Item::Ovimg(o)               => o.pos.x.is_percent(),
+            _                            => false,
+        });
+        self.updating.set(true);
+        self.relative.set_sensitive(e.is_some());
+        self.relative.set_active(percent);
+        self.updating.set(false);
+        self.area.queue_draw();
+    }
+    /// convert the geometry of the selected element to percent or to pixels,
+    /// the element stays at the same place on the current canvas
+    fn relative_toggled(&self) {
+        if self.updating.get() {
+            return; }
+        let (Some(e), Some(h)) = (self.selected_element(), self.history.upgrade()) else { return };
+        let (cw, ch)= self.canvas.get().size();
+        let percent= self.relative.is_active();
+        let conv= |l: &mut Length, size: f64| {
+            let v= l.resolve(size);
+            *l= if percent { Length::Percent(0.0).with_resolved(v, size).rounded() } else { Length::Px(v) };
+        };
+        let old= e.value.borrow().clone();
+        let mut new= old.clone();
+        match &mut new {
+            Item::Mat(m) | Item::Pmat(m) => {
+                conv(&mut m.pos.x, cw); conv(&mut m.pos.y, ch);
+                conv(&mut m.dim.w, cw); conv(&mut m.dim.h, ch);
+            },
+            Item::Ovimg(o) => { conv(&mut o.pos.x, cw); conv(&mut o.pos.y, ch); },
+            _              => return,
+        }
+        e.set_value(new.clone());
+        h.seal();
+        h.push( OperationHistoryItem::new_set_value(&e, "pos/dim", old, new) );
+    }
+}
+// build_preview ///////////////////////////////////////////
+pub fn build_preview(history: Rc<OperationHistory>, selection: SingleSelection,
+                     sources: Rc<SourceCache>, root: PathBuf, canvas: Rc<Cell<Canvas>>) -> Box {
+    let preview= Rc::new( Preview{
+        root,
+        history  : Rc::downgrade(&history),
+        selection: selection.clone(),
+        sources  : sources.clone(),
+        canvas,
+        area     : DrawingArea::builder()
+            .content_width (CANVAS_W / 4)
+            .content_height(CANVAS_H / 4)
+            .hexpand(true)
+            .vexpand(true)
+            .build(),
+        snap     : CheckButton::builder().label("snap").active(true).build(),
+        relative : CheckButton::with_label("%"),
+        drag     : RefCell::new(None),
+        updating : Cell::new(false),
+    } );
+    let names: Vec<String>= RESOLUTIONS.iter().map(|c| format!("{}x{}", c.w, c.h)).collect();
+    let resolution= DropDown::from_strings(&names.iter().map(|n| n.as_str()).collect::<Vec<_>>());
+    if let Some(i) = RESOLUTIONS.iter().position(|c| *c == preview.canvas.get()) {
+        resolution.set_selected(i as u32); }
+
+    let p= Rc::downgrade(&preview);
+    preview.area.set_draw_func(move |_, cr, _, _| {
+        if let Some(p) = p.upgrade() { p.draw(cr); }
+    });
+    let gesture= GestureDrag::new();
+    let p= Rc::downgrade(&preview);
+    gesture.connect_drag_begin(move |_, x, y| {
+        if let Some(p) = p.upgrade() { p.drag_begin(x, y); }
+    });
+    let p= Rc::downgrade(&preview);
+    gesture.connect_drag_update(move |_, dx, dy| {
+        if let Some(p) = p.upgrade() { p.drag_update(dx, dy); }
+    });
+    let p= Rc::downgrade(&preview);
+    gesture.connect_drag_end(move |_, _, _| {
+        if let Some(p) = p.upgrade() { p.drag_end(); }
+    });
+    preview.area.add_controller(gesture);
+
+    let p= Rc::downgrade(&preview);
+    resolution.connect_selected_notify(move |d| {
+        let (Some(p), Some(c)) = (p.upgrade(), RESOLUTIONS.get(d.selected() as usize)) else { return };
+        p.canvas.set(*c);
+        p.area.queue_draw();
+    });
+    let p= Rc::downgrade(&preview);
+    preview.relative.connect_toggled(move |_| {
+        if let Some(p) = p.upgrade() { p.relative_toggled(); }
+    });
+
+    let p= Rc::downgrade(&preview);
+    selection.connect_selection_changed(move |_, _, _| {
+        if let Some(p) = p.upgrade() { p.load(); }
+    });
+    let preview_for_history= preview.clone(); // the preview lives as long as the history
+    history.connect_changed(move || preview_for_history.load());
+    let area_for_sources= preview.area.downgrade();
+    sources.connect_changed(move |_| {
+        if let Some(a) = area_for_sources.upgrade() {
+            a.queue_draw();
+        }
+    });
+
+    preview.load();
+
+    let tools= Box::new(Orientation::Horizontal, 4);
+    tools.append(&preview.snap);
+    tools.append(&preview.relative);
+    tools.append(&resolution);
+
+    let vbox= Box::new(Orientation::Vertical, 2);
+    vbox.append(&preview.area);
+    vbox.append(&tools);
+    vbox
+}
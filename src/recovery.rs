@@ -0,0 +1,153 @@
+//! autosave and crash recovery
+//!
+//! every AUTOSAVE_SECONDS a modified document is written with its history to a slot
+//! in <user cache dir>/isv2/recovery:
+//!
+//!   <pid>-<n>.json           the tree (scenario_file.rs)
+//!   <pid>-<n>.json.history   the undo history
+//!   <pid>-<n>.info.json      RecoveryInfo: the file of the document and the time, written last
+//!
+//! the slot is removed when the document is saved (or back to the saved state) and when the window is closed,
+//! so the slots of another pid found at the start are left by a run which did not end normally.
+//! a restored document keeps its slot until it is saved or closed.
+
+use std::cell::Cell;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::{Rc, Weak};
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+use gtk::glib;
+use gtk::prelude::*;
+
+use crate::scenario_file;
+use crate::scenario_node_object::first_root;
+use crate::operation_history::OperationHistory;
+use crate::document_file::{self, APP_NAME, DocumentFile};
+
+pub const AUTOSAVE_SECONDS: u32 = 30;
+const RECOVERY_FILE_VERSION: u32 = 1;
+const INFO_SUFFIX: &str = ".info.json";
+
+// RecoveryInfo ////////////////////////////////////////////
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecoveryInfo {
+    pub version: u32,
+    pub path   : Option<PathBuf>, // the file of the document, None: untitled
+    pub saved  : Option<i32>,     // the saved index of the history
+    pub time   : u64,             // seconds since the epoch
+}
+// Recovery ////////////////////////////////////////////////
+/// a slot left by a previous run
+#[derive(Debug, Clone)]
+pub struct Recovery {
+    pub slot: PathBuf,
+    pub info: RecoveryInfo,
+}
+impl Recovery {
+    pub fn name(&self) -> String {
+        self.info.path.as_ref().map_or(String::from("untitled"), |p| p.display().to_string())
+    }
+}
+// slot ////////////////////////////////////////////////////
+fn recovery_dir() -> PathBuf {
+    glib::user_cache_dir().join(APP_NAME).join("recovery")
+}
+thread_local! {
+    static NEXT_SLOT: Cell<u32> = const { Cell::new(0) };
+}
+/// a new slot of this process
+pub fn new_slot() -> PathBuf {
+    let n= NEXT_SLOT.with(|c| { let n= c.get(); c.set(n + 1); n });
+    recovery_dir().join( format!("{}-{}", std::process::id(), n) )
+}
+/// the scenario file of `slot`
+pub fn scenario_path(slot: &Path) -> PathBuf {
+    slot.with_extension("json")
+}
+fn info_path(slot: &Path) -> PathBuf {
+    let mut s= slot.as_os_str().to_owned();
+    s.push(INFO_SUFFIX);
+    PathBuf::from(s)
+}
+// write / remove //////////////////////////////////////////
+pub fn write(slot: &Path, history: &OperationHistory, path: Option<&Path>) -> io::Result<()> {
+    fs::create_dir_all(recovery_dir())?;
+    let scenario= scenario_path(slot);
+    scenario_file::save(&scenario, first_root(&history.tree_list_model()), history.document().get_next_id())?;
+    history.save_to( &scenario_file::history_path(&scenario) )?;
+    let info= RecoveryInfo{
+        version: RECOVERY_FILE_VERSION,
+        path   : path.map(Path::to_path_buf),
+        saved  : history.get_saved(),
+        time   : SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+    };
+    fs::write( info_path(slot), serde_json::to_string_pretty(&info).map_err(io::Error::from)? )
+}
+/// the files of `slot` which exist, an error is the first file not removed
+pub fn remove(slot: &Path) -> io::Result<()> {
+    let scenario= scenario_path(slot);
+    let mut result= Ok(());
+    for p in [info_path(slot), scenario_file::history_path(&scenario), scenario] {
+        if p.exists() {
+            if let Err(e) = fs::remove_file(&p) {
+                result= result.and( Err(io::Error::new(e.kind(), format!("{}: {}", p.display(), e))) ); }
+        }
+    }
+    result
+}
+// pending /////////////////////////////////////////////////
+/// the slots of the other processes, the newest first
+pub fn pending() -> Vec<Recovery> {
+    let Ok(entries) = fs::read_dir(recovery_dir()) else { return Vec::new() };
+    let pid= std::process::id().to_string();
+    let mut found: Vec<Recovery>= entries.flatten()
+        .filter_map(|e| {
+            let name= e.file_name().to_string_lossy().to_string();
+            let stem= name.strip_suffix(INFO_SUFFIX)?;
+            if stem.split('-').next() == Some(pid.as_str()) {
+                return None; }
+            let info= serde_json::from_str( &fs::read_to_string(e.path()).ok()? ).ok()?;
+            Some( Recovery{ slot: recovery_dir().join(stem), info } )
+        })
+        .collect();
+    found.sort_by_key(|r| std::cmp::Reverse(r.info.time));
+    found
+}
+// start_autosave //////////////////////////////////////////
+/// write the slot of `file` every AUTOSAVE_SECONDS while the document is modified,
+/// the slot is removed when `window` is destroyed.
+/// a failure is shown on `window` once, until the autosave succeeds again
+pub fn start_autosave(window: &gtk::Window, history: &Rc<OperationHistory>, file: &Rc<DocumentFile>) {
+    let changed= Rc::new(Cell::new(true));
+    let changed_for_history= changed.clone();
+    history.connect_changed(move || changed_for_history.set(true));
+
+    let (h, f): (Weak<OperationHistory>, Weak<DocumentFile>)= (Rc::downgrade(history), Rc::downgrade(file));
+    let w= window.downgrade();
+    let mut failing= false;
+    glib::timeout_add_seconds_local(AUTOSAVE_SECONDS, move || {
+        let (Some(w), Some(h), Some(f)) = (w.upgrade(), h.upgrade(), f.upgrade()) else { return glib::ControlFlow::Break };
+        if changed.replace(false) {
+            let result= if h.is_modified() {
+                write(&f.recovery_slot(), &h, f.path().as_deref())
+            } else {
+                remove(&f.recovery_slot())
+            };
+            match &result {
+                Err(e) if !failing => document_file::show_error(&w, "autosave failed", e),
+                _                  => (),
+            }
+            failing= result.is_err();
+        }
+        glib::ControlFlow::Continue
+    });
+    let f= file.clone(); // the widgets holding the file may be disposed first
+    window.connect_destroy(move |w| {
+        if let Err(e) = remove(&f.recovery_slot()) { // shown on another window of the app, if any
+            if let Some(other) = w.application().and_then(|a| a.active_window()) {
+                document_file::show_error(&other, "the autosave is not removed", &e); }
+        }
+    });
+}
@@ -0,0 +1,372 @@
+//! rendering of a page with cairo / pango, used by the preview and the export
+//!
+//! the canvas is CANVAS_W x CANVAS_H by default (see RESOLUTIONS),
+//! the lengths in percent (geometry.rs) are resolved by its size.
+//! the scene gives the background color,
+//! a page is drawn with its mats, a pmat is drawn as a page with one mat.
+//! the text of a mat with `v` is written vertically (縦書き):
+//! the layout is rotated by 90 degrees and pango lays it out with the east gravity,
+//! the glyphs stay upright and the "vert" feature of the font replaces
+//! the punctuation (、。「」ー…) with the vertical forms.
+//! the markup of the text (markup.rs) becomes pango attributes,
+//! ruby is drawn by a second layout over (or right of, when vertical) the base text.
+//! a mat with src shows the text of the source (text_source.rs).
+//! the images (bgimg with its crop, ovimg) are relative to the asset root,
+//! they are loaded once and kept until clear_images().
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use gtk::cairo;
+use gtk::pango;
+use gtk::gdk_pixbuf::Pixbuf;
+use gtk::prelude::GdkCairoContextExt;
+
+use crate::scenario_node::{ScenarioNode, Item, Mat, Ovimg, Scene, Color};
+use crate::scenario_query::{ancestors, pre_order};
+use crate::markup::{self, Span};
+use crate::text_source::SourceCache;
+
+pub const CANVAS_W: i32 = 1280;
+pub const CANVAS_H: i32 = 720;
+const TEXT_FONT   : &str = "Sans 28";
+const TEXT_PADDING: f64  = 16.0;
+const RUBY_SCALE  : f64  = 0.5;
+const NO_IMAGE_SIZE: f64 = 128.0; // the size of an ovimg whose image is not found
+
+// Canvas //////////////////////////////////////////////////
+/// the output resolution
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Canvas {
+    pub w: i32,
+    pub h: i32,
+}
+impl Canvas {
+    pub const DEFAULT: Canvas = Canvas{ w: CANVAS_W, h: CANVAS_H };
+    pub fn size(self) -> (f64, f64) {
+        (self.w as f64, self.h as f64)
+    }
+}
+/// (scale, offset x, offset y) from the canvas to `area`, the canvas is fitted and centered
+pub fn fit(area: &gtk::DrawingArea, canvas: Canvas) -> (f64, f64, f64) {
+    use gtk::prelude::WidgetExt;
+    let (w, h)  = (area.width() as f64, area.height() as f64);
+    let (cw, ch)= canvas.size();
+    let scale= (w / cw).min(h / ch);
+    (scale, (w - cw * scale) / 2.0, (h - ch * scale) / 2.0)
+}
+pub const RESOLUTIONS: [Canvas; 2] = [Canvas::DEFAULT, Canvas{ w: 1920, h: 1080 }];
+
+thread_local! {
+    static IMAGES: RefCell<HashMap<PathBuf, Option<Pixbuf>>> = RefCell::new(HashMap::new());
+}
+// image ///////////////////////////////////////////////////
+/// the image of `path`, loaded at the first access
+pub fn image(path: &Path) -> Option<Pixbuf> {
+    IMAGES.with(|m| m.borrow_mut()
+                .entry(path.to_path_buf())
+                .or_insert_with(|| Pixbuf::from_file(path).ok())
+                .clone())
+}
+/// forget the loaded images, e.g. after the files are changed
+pub fn clear_images() {
+    IMAGES.with(|m| m.borrow_mut().clear());
+}
+
+// display_unit ////////////////////////////////////////////
+/// the page or pmat which is shown for `node`:
+/// the node itself, its page, or the first page in it (for a scene / group)
+pub fn display_unit(node: &Rc<ScenarioNode>) -> Option<Rc<ScenarioNode>> {
+    let is_unit= |n: &Rc<ScenarioNode>| matches!(*n.value.borrow(), Item::Page | Item::Pmat(_));
+    std::iter::once(node.clone()).chain(ancestors(node)).find(is_unit)
+        .or_else(|| pre_order(node.clone()).find(is_unit))
+}
+// scene_of ////////////////////////////////////////////////
+/// the scene node of `node` (itself or an ancestor)
+pub fn scene_node_of(node: &Rc<ScenarioNode>) -> Option<Rc<ScenarioNode>> {
+    std::iter::once(node.clone()).chain(ancestors(node))
+        .find(|n| matches!(*n.value.borrow(), Item::Scene(_)))
+}
+fn scene_of(node: &Rc<ScenarioNode>) -> Option<Scene> {
+    match &*scene_node_of(node)?.value.borrow() {
+        Item::Scene(s) => Some(s.clone()),
+        _              => None,
+    }
+}
+// elements ////////////////////////////////////////////////
+/// the drawn nodes of the display unit in the drawing order:
+/// the mats and ovimgs of a page, or the pmat itself
+pub fn elements(unit: &Rc<ScenarioNode>) -> Vec<Rc<ScenarioNode>> {
+    match &*unit.value.borrow() {
+        Item::Pmat(_) => vec![unit.clone()],
+        Item::Page    => unit.get_children().into_iter()
+            .filter(|c| matches!(*c.value.borrow(), Item::Mat(_) | Item::Ovimg(_)))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+// bounds //////////////////////////////////////////////////
+/// (x, y, w, h) of a mat / pmat / ovimg on the canvas
+pub fn bounds(v: &Item, asset_root: &Path, canvas: Canvas) -> Option<(f64, f64, f64, f64)> {
+    let (cw, ch)= canvas.size();
+    match v {
+        Item::Mat(m) | Item::Pmat(m) => {
+            let ((x, y), (w, h))= (m.pos.resolve(cw, ch), m.dim.resolve(cw, ch));
+            Some( (x, y, w, h) )
+        },
+        Item::Ovimg(o) => {
+            let (x, y)= o.pos.resolve(cw, ch);
+            let (w, h)= image(&asset_root.join(&o.path))
+                .map_or((NO_IMAGE_SIZE, NO_IMAGE_SIZE), |p| (p.width() as f64, p.height() as f64));
+            Some( (x, y, w, h) )
+        },
+        _ => None,
+    }
+}
+// helpers /////////////////////////////////////////////////
+/// `c` with its alpha multiplied by the `opacity` of the element (see color.rs)
+fn set_color(cr: &cairo::Context, c: &Color, opacity: f64) {
+    let (r, g, b)= c.to_f64();
+    cr.set_source_rgba(r, g, b, c.alpha() * opacity);
+}
+/// black or white, whichever is readable on `c`
+fn text_color(c: &Color) -> Color {
+    if c.luminance() > 128.0 { Color::BLACK } else { Color::WHITE }
+}
+fn rounded_rect(cr: &cairo::Context, x: f64, y: f64, w: f64, h: f64, r: f64) {
+    let r= r.min(w / 2.0).min(h / 2.0).max(0.0);
+    cr.new_sub_path();
+    cr.arc(x + w - r, y + r,     r, -PI / 2.0, 0.0);
+    cr.arc(x + w - r, y + h - r, r, 0.0,       PI / 2.0);
+    cr.arc(x + r,     y + h - r, r, PI / 2.0,  PI);
+    cr.arc(x + r,     y + r,     r, PI,        PI * 1.5);
+    cr.close_path();
+}
+// Styled //////////////////////////////////////////////////
+/// the text of spans with its pango attributes
+#[derive(Default)]
+struct Styled {
+    text : String,
+    attrs: Vec<pango::Attribute>,
+    ruby : Vec<(Range<usize>, String)>, // byte range of the base text, reading
+}
+impl Styled {
+    fn push(&mut self, spans: &[Span]) {
+        for span in spans {
+            let start= self.text.len();
+            match span {
+                Span::Text(t)   => self.text+= t,
+                Span::Wait(_)   => (),
+                Span::Bold(c)   => {
+                    self.push(c);
+                    self.attr(pango::AttrInt::new_weight(pango::Weight::Bold).into(), start);
+                },
+                Span::Italic(c) => {
+                    self.push(c);
+                    self.attr(pango::AttrInt::new_style(pango::Style::Italic).into(), start);
+                },
+                Span::Color(col, c) => {
+                    self.push(c);
+                    let (r, g, b)= (col.r as u16 * 257, col.g as u16 * 257, col.b as u16 * 257);
+                    self.attr(pango::AttrColor::new_foreground(r, g, b).into(), start);
+                    if col.a != 255 {
+                        self.attr(pango::AttrInt::new_foreground_alpha(col.a as u16 * 257).into(), start); }
+                },
+                Span::Ruby{ base, reading } => {
+                    self.text+= base;
+                    self.ruby.push( (start..self.text.len(), reading.clone()) );
+                },
+            }
+        }
+    }
+    fn attr(&mut self, mut a: pango::Attribute, start: usize) {
+        a.set_start_index(start as u32);
+        a.set_end_index(self.text.len() as u32);
+        self.attrs.push(a);
+    }
+}
+// new_layout //////////////////////////////////////////////
+/// a layout on the current matrix of `cr`, with the east gravity if `vertical`
+fn new_layout(cr: &cairo::Context, font: &pango::FontDescription, vertical: bool) -> pango::Layout {
+    let layout= pangocairo::functions::create_layout(cr);
+    let attrs = pango::AttrList::new();
+    if vertical {
+        let context= layout.context();
+        context.set_base_gravity(pango::Gravity::East);
+        context.set_gravity_hint(pango::GravityHint::Natural);
+        layout.context_changed();
+        attrs.insert( pango::AttrFontFeatures::new("vert 1, vrt2 1") );
+    }
+    layout.set_attributes(Some(&attrs));
+    layout.set_font_description(Some(font));
+    layout
+}
+// draw_text ///////////////////////////////////////////////
+/// the markup text `txt` in the box (x, y, w, h), vertical lines go from right to left.
+/// a text with a markup error is drawn as it is
+pub fn draw_text(cr: &cairo::Context, txt: &str, x: f64, y: f64, w: f64, h: f64, vertical: bool) {
+    if txt.is_empty() || w <= 0.0 || h <= 0.0 {
+        return; }
+    let mut styled= Styled::default();
+    match markup::parse(txt) {
+        Ok(spans) => styled.push(&spans),
+        Err(_)    => styled.text= txt.to_string(),
+    }
+    cr.save().ok();
+    if vertical {
+        // x axis of the layout goes down, y axis goes left
+        cr.translate(x + w, y);
+        cr.rotate(PI / 2.0);
+    } else {
+        cr.translate(x, y);
+    }
+    let font  = pango::FontDescription::from_string(TEXT_FONT);
+    let layout= new_layout(cr, &font, vertical);
+    if let Some(attrs) = layout.attributes() {
+        for a in styled.attrs {
+            attrs.insert(a); }
+        layout.set_attributes(Some(&attrs));
+    }
+    let line_len= if vertical { h } else { w };
+    layout.set_width( (line_len * pango::SCALE as f64) as i32 );
+    layout.set_wrap(pango::WrapMode::WordChar);
+    layout.set_text(&styled.text);
+
+    // room for the ruby over the first line and between the lines
+    let mut ruby_font= font.clone();
+    ruby_font.set_size( (font.size() as f64 * RUBY_SCALE) as i32 );
+    let ruby_h= if styled.ruby.is_empty() { 0.0 } else { font.size() as f64 * RUBY_SCALE * 1.5 };
+    layout.set_spacing(ruby_h as i32);
+    let top= ruby_h / pango::SCALE as f64;
+    cr.move_to(0.0, top);
+    pangocairo::functions::show_layout(cr, &layout);
+
+    for (range, reading) in styled.ruby.iter() {
+        let first= layout.index_to_pos(range.start as i32);
+        let last = layout.index_to_pos(prev_char_boundary(&styled.text, range.end) as i32);
+        if first.y() != last.y() { // the base is wrapped, the ruby goes over the first part
+            continue; }
+        let base_x= first.x() as f64;
+        let base_w= (last.x() + last.width() - first.x()) as f64;
+
+        let ruby= new_layout(cr, &ruby_font, vertical);
+        ruby.set_text(reading);
+        let (rw, rh)= ruby.size();
+        let rx= (base_x + (base_w - rw as f64) / 2.0) / pango::SCALE as f64;
+        let ry= (first.y() as f64 - rh as f64) / pango::SCALE as f64 + top;
+        cr.move_to(rx, ry);
+        pangocairo::functions::show_layout(cr, &ruby);
+    }
+    cr.restore().ok();
+}
+/// the start of the char which ends at `end`
+fn prev_char_boundary(s: &str, end: usize) -> usize {
+    s[..end].char_indices().next_back().map_or(0, |(i, _)| i)
+}
+// draw_mat ////////////////////////////////////////////////
+fn draw_mat(cr: &cairo::Context, m: &Mat, sources: &SourceCache, canvas: Canvas) {
+    let (cw, ch)= canvas.size();
+    let (x, y)= m.pos.resolve(cw, ch);
+    let (w, h)= m.dim.resolve(cw, ch);
+    rounded_rect(cr, x, y, w, h, m.r as f64);
+    set_color(cr, &m.col, m.a as f64 / 255.0);
+    cr.fill().ok();
+
+    set_color(cr, &text_color(&m.col), 1.0);
+    let p= TEXT_PADDING;
+    draw_text(cr, &sources.text_of(m), x + p, y + p, w - p * 2.0, h - p * 2.0, m.v);
+}
+// draw_ovimg //////////////////////////////////////////////
+fn draw_ovimg(cr: &cairo::Context, o: &Ovimg, asset_root: &Path, canvas: Canvas) {
+    let (cw, ch)= canvas.size();
+    let (x, y)= o.pos.resolve(cw, ch);
+    match image(&asset_root.join(&o.path)) {
+        Some(p) => {
+            cr.set_source_pixbuf(&p, x, y);
+            cr.paint_with_alpha(o.a as f64 / 255.0).ok();
+        },
+        None => { // a placeholder
+            cr.set_source_rgba(1.0, 0.0, 0.0, 0.8);
+            cr.set_line_width(2.0);
+            cr.rectangle(x, y, NO_IMAGE_SIZE, NO_IMAGE_SIZE);
+            cr.move_to(x, y);
+            cr.line_to(x + NO_IMAGE_SIZE, y + NO_IMAGE_SIZE);
+            cr.stroke().ok();
+        },
+    }
+}
+// draw_background /////////////////////////////////////////
+/// bgcol, and bgimg (its crop) scaled to cover the canvas
+fn draw_background(cr: &cairo::Context, scene: Option<&Scene>, asset_root: &Path, canvas: Canvas) {
+    set_color(cr, scene.map_or(&Color::BLACK, |s| &s.bgcol), 1.0);
+    cr.paint().ok();
+    let Some(s) = scene else { return };
+    let Some(p) = s.bgimg.as_ref().and_then(|b| image(&asset_root.join(b))) else { return };
+    let (iw, ih)= (p.width() as f64, p.height() as f64);
+    let (cx, cy, cw, ch)= match &s.crop {
+        Some(c) => {
+            let ((x, y), (w, h))= (c.pos.resolve(iw, ih), c.dim.resolve(iw, ih));
+            (x, y, w.max(1.0), h.max(1.0))
+        },
+        None => (0.0, 0.0, iw, ih),
+    };
+    let (canvas_w, canvas_h)= canvas.size();
+    let scale= (canvas_w / cw).max(canvas_h / ch);
+    cr.save().ok();
+    cr.rectangle(0.0, 0.0, canvas_w, canvas_h);
+    cr.clip();
+    cr.translate((canvas_w - cw * scale) / 2.0, (canvas_h - ch * scale) / 2.0);
+    cr.scale(scale, scale);
+    cr.set_source_pixbuf(&p, -cx, -cy);
+    cr.paint().ok();
+    cr.restore().ok();
+}
+// render //////////////////////////////////////////////////
+fn draw_elements(cr: &cairo::Context, unit: &Rc<ScenarioNode>, sources: &SourceCache, asset_root: &Path,
+                 canvas: Canvas) {
+    for e in elements(unit) {
+        match &*e.value.borrow() {
+            Item::Mat(m) | Item::Pmat(m) => draw_mat(cr, m, sources, canvas),
+            Item::Ovimg(o)               => draw_ovimg(cr, o, asset_root, canvas),
+            _                            => (),
+        }
+    }
+}
+/// draw the display unit of `node` on the coordinates of `canvas`
+pub fn render(cr: &cairo::Context, node: &Rc<ScenarioNode>, sources: &SourceCache, asset_root: &Path,
+              canvas: Canvas) {
+    draw_background(cr, scene_of(node).as_ref(), asset_root, canvas);
+    if let Some(unit) = display_unit(node) {
+        draw_elements(cr, &unit, sources, asset_root, canvas); }
+}
+/// draw `units` in the order over the background of the scene of the last one
+pub fn render_units(cr: &cairo::Context, units: &[Rc<ScenarioNode>], sources: &SourceCache, asset_root: &Path,
+                    canvas: Canvas) {
+    draw_background(cr, units.last().and_then(scene_of).as_ref(), asset_root, canvas);
+    for u in units {
+        draw_elements(cr, u, sources, asset_root, canvas); }
+}
+// export_png //////////////////////////////////////////////
+fn write_png<F: FnOnce(&cairo::Context)>(canvas: Canvas, path: &Path, draw: F) -> io::Result<()> {
+    let surface= cairo::ImageSurface::create(cairo::Format::ARgb32, canvas.w, canvas.h)
+        .map_err(io::Error::other)?;
+    let cr= cairo::Context::new(&surface).map_err(io::Error::other)?;
+    draw(&cr);
+    drop(cr);
+    surface.write_to_png( &mut File::create(path)? ).map_err(io::Error::other)
+}
+pub fn export_png(node: &Rc<ScenarioNode>, sources: &SourceCache, asset_root: &Path, canvas: Canvas,
+                  path: &Path) -> io::Result<()> {
+    write_png(canvas, path, |cr| render(cr, node, sources, asset_root, canvas))
+}
+/// `units` drawn by render_units()
+pub fn export_units_png(units: &[Rc<ScenarioNode>], sources: &SourceCache, asset_root: &Path, canvas: Canvas,
+                        path: &Path) -> io::Result<()> {
+    write_png(canvas, path, |cr| render_units(cr, units, sources, asset_root, canvas))
+}
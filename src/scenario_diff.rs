@@ -0,0 +1,413 @@
+//! structural diff and three-way merge of scenario trees (NodeRecord, scenario_file.rs)
+//!
+//! the nodes are matched by id, the ids are stable (scenario_document.rs).
+//! between an old and a new tree a node is
+//!   inserted / deleted : its id is only in the new / old tree
+//!   moved              : its parent is changed, or it is reordered among the siblings kept under the parent
+//!   changed            : a field differs, the fields are the XML attributes (scenario_xml.rs), txt and kind
+//!
+//! merge(base, ours, theirs) takes the change of each node from the side which made it.
+//! when both sides changed the same field or moved the same node, or one side deleted
+//! a node the other side changed, ours is taken and a Conflict is reported with the node id.
+//! an id added on both sides is two different nodes, the one of theirs is renumbered.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::scenario_node::{Item, ItemKind};
+use crate::scenario_file::{NodeRecord, ScenarioFile, SCENARIO_FILE_VERSION};
+use crate::scenario_xml;
+
+// fields //////////////////////////////////////////////////
+/// the compared fields of a value: kind, the attributes and txt
+fn fields(v: &Item) -> HashMap<String, String> {
+    let mut f: HashMap<String, String>= scenario_xml::attributes(v).into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect();
+    f.insert(String::from("kind"), v.kind().name().to_string());
+    if let Item::Mat(m) | Item::Pmat(m) = v {
+        if !m.txt.is_empty() {
+            f.insert(String::from("txt"), m.txt.clone()); }
+    }
+    f
+}
+fn from_fields(mut f: HashMap<String, String>) -> Result<Item, String> {
+    let kind= f.remove("kind").and_then(|k| ItemKind::from_name(&k)).ok_or("no kind")?;
+    let txt = f.remove("txt");
+    let mut v= scenario_xml::item_from_attributes(kind, f)?;
+    if let (Item::Mat(m) | Item::Pmat(m), Some(t)) = (&mut v, txt) {
+        m.txt= t; }
+    Ok(v)
+}
+fn field_names<'a>(maps: &[&'a HashMap<String, String>]) -> Vec<&'a String> {
+    let mut names: Vec<&String>= maps.iter().flat_map(|m| m.keys()).collect::<HashSet<_>>().into_iter().collect();
+    names.sort();
+    names
+}
+// Flat ////////////////////////////////////////////////////
+/// a tree indexed by id
+struct FlatNode {
+    value : Item,
+    parent: Option<i32>,
+}
+#[derive(Default)]
+struct Flat {
+    nodes   : HashMap<i32, FlatNode>,
+    children: HashMap<Option<i32>, Vec<i32>>, // None: the root level
+    order   : Vec<i32>,                       // pre-order
+}
+impl Flat {
+    fn new(records: &[NodeRecord]) -> Flat {
+        let mut f= Flat::default();
+        f.add(None, records);
+        f
+    }
+    fn add(&mut self, parent: Option<i32>, records: &[NodeRecord]) {
+        for r in records {
+            self.nodes.insert(r.id, FlatNode{ value: r.value.clone(), parent });
+            self.children.entry(parent).or_default().push(r.id);
+            self.order.push(r.id);
+            self.add(Some(r.id), &r.children);
+        }
+    }
+    fn children(&self, parent: Option<i32>) -> &[i32] {
+        self.children.get(&parent).map_or(&[], |c| c.as_slice())
+    }
+    fn index(&self, id: i32) -> usize {
+        let parent= self.nodes[&id].parent;
+        self.children(parent).iter().position(|c| *c == id).unwrap_or(0)
+    }
+    fn location(&self, id: i32) -> Location {
+        Location{ parent: self.nodes[&id].parent, index: self.index(id) }
+    }
+}
+// lcs /////////////////////////////////////////////////////
+/// the longest common subsequence of `a` and `b`
+fn lcs(a: &[i32], b: &[i32]) -> HashSet<i32> {
+    let mut t= vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            t[i][j]= if a[i] == b[j] { t[i + 1][j + 1] + 1 } else { t[i + 1][j].max(t[i][j + 1]) };
+        }
+    }
+    let (mut i, mut j, mut common)= (0, 0, HashSet::new());
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j]        { common.insert(a[i]); i+= 1; j+= 1; }
+        else if t[i + 1][j] >= t[i][j + 1] { i+= 1; }
+        else                   { j+= 1; }
+    }
+    common
+}
+/// the nodes in both trees which are moved from `old` to `new`
+fn moved(old: &Flat, new: &Flat) -> HashSet<i32> {
+    let mut moved= HashSet::new();
+    let mut parents= HashSet::new();
+    for (id, n) in new.nodes.iter() {
+        let Some(o) = old.nodes.get(id) else { continue };
+        if o.parent != n.parent {
+            moved.insert(*id);
+        } else {
+            parents.insert(n.parent);
+        }
+    }
+    // reordered among the siblings which stay under the same parent
+    let stays= |id: &&i32| old.nodes.get(id).zip(new.nodes.get(id)).is_some_and(|(a, b)| a.parent == b.parent);
+    for p in parents {
+        let a: Vec<i32>= old.children(p).iter().filter(stays).copied().collect();
+        let b: Vec<i32>= new.children(p).iter().filter(stays).copied().collect();
+        let common= lcs(&a, &b);
+        moved.extend( b.into_iter().filter(|id| !common.contains(id)) );
+    }
+    moved
+}
+// Location ////////////////////////////////////////////////
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    pub parent: Option<i32>, // None: the root level
+    pub index : usize,
+}
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.parent {
+            Some(p) => write!(f, "id:{}[{}]", p, self.index),
+            None    => write!(f, "root[{}]", self.index),
+        }
+    }
+}
+// Change //////////////////////////////////////////////////
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Insert{ id: i32, kind: ItemKind, to: Location },
+    Delete{ id: i32, kind: ItemKind, from: Location },
+    Move  { id: i32, from: Location, to: Location },
+    Field { id: i32, field: String, old: Option<String>, new: Option<String> },
+}
+impl Change {
+    pub fn id(&self) -> i32 {
+        match self {
+            Change::Insert{ id, .. } | Change::Delete{ id, .. } |
+            Change::Move{ id, .. }   | Change::Field{ id, .. } => *id,
+        }
+    }
+}
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let opt= |s: &Option<String>| s.as_ref().map_or(String::from("(none)"), |s| format!("{:?}", s));
+        match self {
+            Change::Insert{ id, kind, to }   => write!(f, "+ id:{} {} at {}", id, kind.name(), to),
+            Change::Delete{ id, kind, from } => write!(f, "- id:{} {} at {}", id, kind.name(), from),
+            Change::Move{ id, from, to }     => write!(f, "> id:{} {} -> {}", id, from, to),
+            Change::Field{ id, field, old, new } => write!(f, "~ id:{} {}: {} -> {}", id, field, opt(old), opt(new)),
+        }
+    }
+}
+// diff ////////////////////////////////////////////////////
+fn diff_flat(old: &Flat, new: &Flat) -> Vec<Change> {
+    let moved= moved(old, new);
+    let mut changes= Vec::new();
+    for id in new.order.iter().copied() {
+        let n= &new.nodes[&id];
+        let Some(o) = old.nodes.get(&id) else {
+            changes.push( Change::Insert{ id, kind: n.value.kind(), to: new.location(id) } );
+            continue;
+        };
+        if moved.contains(&id) {
+            changes.push( Change::Move{ id, from: old.location(id), to: new.location(id) } ); }
+        let (fo, fn_)= (fields(&o.value), fields(&n.value));
+        for k in field_names(&[&fo, &fn_]) {
+            if fo.get(k) != fn_.get(k) {
+                changes.push( Change::Field{ id, field: k.clone(), old: fo.get(k).cloned(), new: fn_.get(k).cloned() } ); }
+        }
+    }
+    for id in old.order.iter().copied().filter(|id| !new.nodes.contains_key(id)) {
+        changes.push( Change::Delete{ id, kind: old.nodes[&id].value.kind(), from: old.location(id) } );
+    }
+    changes
+}
+/// the changes from `old` to `new`: inserts, moves and fields in the pre-order of `new`, then deletes
+pub fn diff(old: &[NodeRecord], new: &[NodeRecord]) -> Vec<Change> {
+    diff_flat(&Flat::new(old), &Flat::new(new))
+}
+// Conflict ////////////////////////////////////////////////
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    pub id : i32,
+    pub msg: String,
+}
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "id:{}: {}", self.id, self.msg)
+    }
+}
+// MergeResult /////////////////////////////////////////////
+pub struct MergeResult {
+    pub file      : ScenarioFile,
+    pub conflicts : Vec<Conflict>,
+    pub renumbered: Vec<(i32, i32)>, // (old, new) ids of the nodes added by theirs
+}
+// renumber ////////////////////////////////////////////////
+fn renumber(records: &mut [NodeRecord], map: &HashMap<i32, i32>) {
+    for r in records {
+        if let Some(id) = map.get(&r.id) {
+            r.id= *id; }
+        renumber(&mut r.children, map);
+    }
+}
+// merge ///////////////////////////////////////////////////
+/// three-way merge of `ours` and `theirs` from their common ancestor `base`
+pub fn merge(base: &ScenarioFile, ours: &ScenarioFile, theirs: &ScenarioFile) -> MergeResult {
+    let (b, o, t0)= (Flat::new(&base.nodes), Flat::new(&ours.nodes), Flat::new(&theirs.nodes));
+    let mut next_id= [base.next_id, ours.next_id, theirs.next_id].into_iter()
+        .chain( b.nodes.keys().chain(o.nodes.keys()).chain(t0.nodes.keys()).map(|id| id + 1) )
+        .max().unwrap_or(1);
+
+    // an id added on both sides is given to the node of ours
+    let mut map= HashMap::new();
+    for id in t0.order {
+        if !b.nodes.contains_key(&id) && o.nodes.contains_key(&id) {
+            map.insert(id, next_id);
+            next_id+= 1;
+        }
+    }
+    let mut theirs_nodes= theirs.nodes.clone();
+    renumber(&mut theirs_nodes, &map);
+    let t= Flat::new(&theirs_nodes);
+
+    let (moved_o, moved_t)= (moved(&b, &o), moved(&b, &t));
+    let mut conflicts= Vec::new();
+    let changed= |side: &Flat, moved: &HashSet<i32>, id: i32| {
+        moved.contains(&id) || fields(&side.nodes[&id].value) != fields(&b.nodes[&id].value)
+    };
+
+    // kept nodes //////////////////////////////////////////
+    let mut kept: HashSet<i32>= HashSet::new();
+    for id in o.order.iter().chain(t.order.iter()).copied() {
+        match (b.nodes.contains_key(&id), o.nodes.contains_key(&id), t.nodes.contains_key(&id)) {
+            (true, true, false) if changed(&o, &moved_o, id) => {
+                conflicts.push( Conflict{ id, msg: String::from("deleted by theirs, changed by ours") } );
+                kept.insert(id);
+            }
+            (true, false, true) if changed(&t, &moved_t, id) => {
+                conflicts.push( Conflict{ id, msg: String::from("deleted by ours, changed by theirs") } );
+                kept.insert(id);
+            }
+            (true, true, true) | (false, _, _) => { kept.insert(id); }
+            _ => (),
+        }
+    }
+    // value and parent of a kept node, from_theirs: placed by theirs
+    let mut values : HashMap<i32, Item>= HashMap::new();
+    let mut parents: HashMap<i32, Option<i32>>= HashMap::new();
+    let mut from_theirs: HashSet<i32>= HashSet::new();
+    let mut place= |id: i32, values: &mut HashMap<i32, Item>, parents: &mut HashMap<i32, Option<i32>>,
+                    conflicts: &mut Vec<Conflict>| {
+        let (bn, on, tn)= (b.nodes.get(&id), o.nodes.get(&id), t.nodes.get(&id));
+        let value= match (bn, on, tn) {
+            (Some(bn), Some(on), Some(tn)) => {
+                let (fb, fo, ft)= (fields(&bn.value), fields(&on.value), fields(&tn.value));
+                let mut merged= HashMap::new();
+                for k in field_names(&[&fb, &fo, &ft]) {
+                    let (vb, vo, vt)= (fb.get(k), fo.get(k), ft.get(k));
+                    let v= if vo == vt || vt == vb { vo } else if vo == vb { vt } else {
+                        conflicts.push( Conflict{ id, msg: format!("{} is changed on both sides", k) } );
+                        vo
+                    };
+                    if let Some(v) = v {
+                        merged.insert(k.clone(), v.clone()); }
+                }
+                from_fields(merged).unwrap_or_else(|e| {
+                    conflicts.push( Conflict{ id, msg: format!("the fields are not merged: {}", e) } );
+                    on.value.clone()
+                })
+            }
+            (_, Some(on), _) => on.value.clone(),
+            (_, None, Some(tn)) => tn.value.clone(),
+            (_, None, None) => return,
+        };
+        let parent= match (on, tn) {
+            (Some(on), Some(tn)) => {
+                if moved_t.contains(&id) && !moved_o.contains(&id) {
+                    from_theirs.insert(id);
+                    tn.parent
+                } else {
+                    if moved_t.contains(&id) && on.parent != tn.parent {
+                        conflicts.push( Conflict{ id, msg: String::from("moved on both sides") } ); }
+                    on.parent
+                }
+            }
+            (Some(on), None) => on.parent,
+            (None, Some(tn)) => { from_theirs.insert(id); tn.parent },
+            (None, None)     => None,
+        };
+        values.insert(id, value);
+        parents.insert(id, parent);
+    };
+    let mut queue: Vec<i32>= o.order.iter().chain(t.order.iter()).copied().filter(|id| kept.contains(id)).collect();
+    queue.dedup();
+    while let Some(id) = queue.pop() {
+        if values.contains_key(&id) {
+            continue; }
+        place(id, &mut values, &mut parents, &mut conflicts);
+        // a deleted parent of a kept node is kept too
+        if let Some(Some(p)) = parents.get(&id) {
+            if !kept.contains(p) {
+                kept.insert(*p);
+                conflicts.push( Conflict{ id: *p, msg: String::from("deleted on one side, a child is added on the other") } );
+                queue.push(*p);
+            }
+        }
+    }
+
+    // a node moved under its own descendant goes back to ours, or to the root level
+    // when its parent in ours is not kept (e.g. deleted by theirs)
+    let in_cycle= |parents: &HashMap<i32, Option<i32>>, id: i32| {
+        let mut p= parents.get(&id).copied().flatten();
+        for _ in 0..parents.len() {
+            match p {
+                Some(x) if x == id => return true,
+                Some(x)            => p= parents.get(&x).copied().flatten(),
+                None               => return false,
+            }
+        }
+        false
+    };
+    let mut ids: Vec<i32>= kept.iter().copied().collect();
+    ids.sort();
+    while let Some(id) = ids.iter().copied().find(|id| in_cycle(&parents, *id)) {
+        conflicts.push( Conflict{ id, msg: String::from("the moves make a cycle") } );
+        match o.nodes.get(&id) {
+            Some(on) if from_theirs.remove(&id) => parents.insert(id, on.parent.filter(|p| kept.contains(p))),
+            _                                   => parents.insert(id, None),
+        };
+    }
+
+    // order of the children: ours, the nodes placed by theirs after their preceding sibling in theirs
+    let mut members: HashMap<Option<i32>, Vec<i32>>= HashMap::new();
+    for id in o.order.iter().chain(t.order.iter()).copied().filter(|id| kept.contains(id)) {
+        let m= members.entry(parents[&id]).or_default();
+        if !m.contains(&id) {
+            m.push(id); }
+    }
+    let mut order: HashMap<Option<i32>, Vec<i32>>= HashMap::new();
+    for (p, m) in members.iter() {
+        let mut seq: Vec<i32>= o.children(*p).iter().copied()
+            .filter(|id| m.contains(id) && !from_theirs.contains(id))
+            .collect();
+        let tc= t.children(*p);
+        for (i, id) in tc.iter().enumerate() {
+            if !m.contains(id) || !from_theirs.contains(id) || seq.contains(id) {
+                continue; }
+            let at= tc[..i].iter().rev().find_map(|s| seq.iter().position(|x| x == s)).map_or(0, |j| j + 1);
+            seq.insert(at, *id);
+        }
+        seq.extend( m.iter().filter(|id| !seq.contains(id)).copied().collect::<Vec<_>>() );
+        order.insert(*p, seq);
+    }
+    fn build(p: Option<i32>, order: &HashMap<Option<i32>, Vec<i32>>, values: &mut HashMap<i32, Item>) -> Vec<NodeRecord> {
+        order.get(&p).map_or(Vec::new(), |ids| ids.iter().map(|id| NodeRecord{
+            id      : *id,
+            value   : values.remove(id).unwrap_or(Item::Group),
+            children: build(Some(*id), order, values),
+        }).collect())
+    }
+    let nodes= build(None, &order, &mut values);
+
+    let mut seen= HashSet::new();
+    conflicts.retain(|c| seen.insert((c.id, c.msg.clone())));
+    let mut renumbered: Vec<(i32, i32)>= map.into_iter().collect();
+    renumbered.sort();
+    MergeResult{
+        file: ScenarioFile{ version: SCENARIO_FILE_VERSION, next_id, nodes },
+        conflicts,
+        renumbered,
+    }
+}
+
+// tests ///////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rec(id: i32, value: Item, children: Vec<NodeRecord>) -> NodeRecord {
+        NodeRecord{ id, value, children }
+    }
+    fn file(nodes: Vec<NodeRecord>) -> ScenarioFile {
+        ScenarioFile{ version: SCENARIO_FILE_VERSION, next_id: 10, nodes }
+    }
+    #[test]
+    fn cycle_with_a_deleted_parent_in_ours() {
+        // base: P{X}, Y / ours: Y under X / theirs: P deleted, X under Y
+        let base  = file(vec![ rec(1, Item::Group, vec![ rec(2, Item::Group, vec![]) ]),
+                               rec(3, Item::Group, vec![]) ]);
+        let ours  = file(vec![ rec(1, Item::Group, vec![ rec(2, Item::Group, vec![ rec(3, Item::Group, vec![]) ]) ]) ]);
+        let theirs= file(vec![ rec(3, Item::Group, vec![ rec(2, Item::Group, vec![]) ]) ]);
+        let m= merge(&base, &ours, &theirs);
+
+        // X goes to the root level, not back under the deleted P
+        assert_eq!(m.file.nodes.len(), 1);
+        let x= &m.file.nodes[0];
+        assert_eq!(x.id, 2);
+        assert_eq!(x.children.iter().map(|c| c.id).collect::<Vec<_>>(), vec![3]);
+        assert!(m.conflicts.iter().any(|c| c.id == 2 && c.msg == "the moves make a cycle"));
+    }
+}
@@ -0,0 +1,101 @@
+//! ScenarioDocument: per document state which is not a part of the tree.
+//!
+//! node ids are allocated from a counter of the document, the counter is
+//! saved in the scenario file so that the ids stay unique after save/load.
+//! the lookup from id to node is cached and rebuilt from the tree on a miss.
+
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::rc::{Rc, Weak};
+
+use crate::scenario_node::ScenarioNode;
+use crate::scenario_query::{pre_order, pre_order_forest, ancestors};
+
+/// the first id of an empty document
+pub const FIRST_ID: i32 = 1;
+
+pub struct ScenarioDocument {
+    next_id: Cell<i32>,
+    index  : RefCell<HashMap<i32, Weak<ScenarioNode>>>,
+}
+impl Default for ScenarioDocument {
+    fn default() -> Self {
+        ScenarioDocument {
+            next_id: Cell::new(FIRST_ID),
+            index  : RefCell::new(HashMap::new()),
+        }
+    }
+}
+// for_each_node ///////////////////////////////////////////
+/// pre-order walk of the forest which starts with `first_root`
+pub fn for_each_node<F: FnMut(&Rc<ScenarioNode>)>(first_root: Option<Rc<ScenarioNode>>, f: &mut F) {
+    pre_order_forest(first_root).for_each(|n| f(&n));
+}
+// top_of //////////////////////////////////////////////////
+/// the first root of the forest which contains `node`
+fn top_of(node: &Rc<ScenarioNode>) -> Rc<ScenarioNode> {
+    let top= ancestors(node).last().unwrap_or(node.clone());
+    top.get_first_sibling().unwrap_or(top)
+}
+impl ScenarioDocument {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    // alloc_id ////////////////////////////////////////////
+    pub fn alloc_id(&self) -> i32 {
+        let id= self.next_id.get();
+        self.next_id.set(id + 1);
+        id
+    }
+    pub fn get_next_id(&self) -> i32 {
+        self.next_id.get()
+    }
+    /// `id` smaller than the ids in use is raised by assign_unique_ids()
+    pub fn set_next_id(&self, id: i32) {
+        self.next_id.set(id.max(FIRST_ID));
+    }
+    // assign_unique_ids ///////////////////////////////////
+    /// give a new id to the nodes whose id is invalid (< FIRST_ID) or already used
+    /// by a preceding node, and make the counter greater than every id.
+    /// returns the number of renumbered nodes
+    pub fn assign_unique_ids(&self, first_root: Option<Rc<ScenarioNode>>) -> usize {
+        let mut max= self.next_id.get() - 1;
+        for_each_node(first_root.clone(), &mut |n| max= max.max(n.id.get()) );
+        self.next_id.set(max + 1);
+
+        let mut used = HashSet::new();
+        let mut count= 0;
+        for_each_node(first_root.clone(), &mut |n| {
+            if (n.id.get() < FIRST_ID) || !used.insert(n.id.get()) {
+                n.id.set( self.alloc_id() );
+                used.insert(n.id.get());
+                count+= 1;
+            }
+        });
+        self.reindex(first_root);
+        count
+    }
+    // assign_fresh_ids ////////////////////////////////////
+    /// renumber `node` and its descendants, e.g. for a pasted copy
+    pub fn assign_fresh_ids(&self, node: &Rc<ScenarioNode>) {
+        pre_order(node.clone()).for_each(|n| n.id.set( self.alloc_id() ) );
+    }
+    // reindex /////////////////////////////////////////////
+    pub fn reindex(&self, first_root: Option<Rc<ScenarioNode>>) {
+        let mut index= self.index.borrow_mut();
+        index.clear();
+        for_each_node(first_root, &mut |n| { index.insert(n.id.get(), Rc::downgrade(n)); } );
+    }
+    // lookup //////////////////////////////////////////////
+    /// the node of `id` in the forest which starts with `first_root`
+    pub fn lookup(&self, first_root: Option<Rc<ScenarioNode>>, id: i32) -> Option<Rc<ScenarioNode>> {
+        let root= first_root.clone()?;
+        let cached= self.index.borrow().get(&id).and_then(|w| w.upgrade());
+        if let Some(n) = cached {
+            if (n.id.get() == id) && Rc::ptr_eq(&top_of(&n), &root) {
+                return Some(n); }
+        }
+        self.reindex(first_root);
+        self.index.borrow().get(&id).and_then(|w| w.upgrade())
+    }
+}
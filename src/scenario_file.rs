@@ -0,0 +1,188 @@
+//! serialization of the ScenarioNode tree
+//!
+//! a node is saved with its descendants as NodeRecord,
+//! the order of the children is kept in `children`.
+//! a file is JSON, or XML by its extension.
+//! the undo history is saved in a sidecar file next to the scenario,
+//! see history_path(). the sidecar keeps the fingerprint() of the tree it was saved with.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use serde::{Serialize, Deserialize};
+
+use crate::scenario_node::{ScenarioNode, Item};
+use crate::scenario_query;
+use crate::scenario_xml;
+
+pub const SCENARIO_FILE_VERSION: u32 = 1;
+
+// NodeRecord //////////////////////////////////////////////
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeRecord {
+    pub id      : i32,
+    pub value   : Item,
+    #[serde(default)]
+    pub children: Vec<NodeRecord>,
+}
+impl Drop for NodeRecord {
+    // the descendants are dropped by a loop, not by the recursion
+    fn drop(&mut self) {
+        let mut stack= std::mem::take(&mut self.children);
+        while let Some(mut r) = stack.pop() {
+            stack.append(&mut r.children);
+        }
+    }
+}
+// ScenarioFile ////////////////////////////////////////////
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScenarioFile {
+    pub version: u32,
+    #[serde(default)]
+    pub next_id: i32,             // id counter of ScenarioDocument
+    pub nodes  : Vec<NodeRecord>, // root level nodes
+}
+
+// to_record ///////////////////////////////////////////////
+/// `node` and its descendants, the neighbors of `node` are not included.
+/// walked by a loop, a tree of 1M levels does not overflow the stack
+pub fn to_record(node: &ScenarioNode) -> NodeRecord {
+    // (a record without the children yet, its next child to visit)
+    let open= |n: &ScenarioNode| (NodeRecord{
+        id      : n.id.get(),
+        value   : n.value.borrow().clone(),
+        children: Vec::new(),
+    }, n.get_child());
+    let mut stack= vec![ open(node) ];
+    loop {
+        let (_, next)= stack.last_mut().expect("the record of `node` is closed last");
+        if let Some(c) = next.take() {
+            *next= c.get_neighbor();
+            stack.push( open(&c) );
+            continue;
+        }
+        let (r, _)= stack.pop().expect("the top");
+        match stack.last_mut() {
+            Some((parent, _)) => parent.children.push(r),
+            None              => return r,
+        }
+    }
+}
+// from_record /////////////////////////////////////////////
+/// build a detached subtree from `r`, by a loop as to_record()
+pub fn from_record(r: &NodeRecord) -> Rc<ScenarioNode> {
+    let new_node= |r: &NodeRecord| {
+        let node= ScenarioNode::new();
+        node.id.set(r.id);
+        node.set_value(r.value.clone());
+        node
+    };
+    let root= new_node(r);
+    let mut stack= vec![ (r, root.clone()) ]; // the nodes whose children are not made yet
+    while let Some((r, node)) = stack.pop() {
+        let mut prev: Option<Rc<ScenarioNode>>= None;
+        for c in r.children.iter() {
+            let n= new_node(c);
+            match prev {
+                Some(p) => ScenarioNode::mv_to_neighbor(p, n.clone()),
+                None    => ScenarioNode::mv_to_child(node.clone(), n.clone()),
+            }
+            stack.push( (c, n.clone()) );
+            prev= Some(n);
+        }
+    }
+    root
+}
+// forest_to_records ///////////////////////////////////////
+pub fn forest_to_records(first_root: Option<Rc<ScenarioNode>>) -> Vec<NodeRecord> {
+    let mut records= Vec::new();
+    let mut n= first_root;
+    while let Some(r) = n {
+        records.push( to_record(&r) );
+        n= r.get_neighbor();
+    }
+    records
+}
+// records_to_forest ///////////////////////////////////////
+/// returns the root level nodes, they are linked as neighbors.
+/// the root level nodes are not owned by each other, keep them until they are put in a store
+pub fn records_to_forest(records: &[NodeRecord]) -> Vec<Rc<ScenarioNode>> {
+    let roots: Vec<Rc<ScenarioNode>>= records.iter().map(from_record).collect();
+    for w in roots.windows(2) {
+        ScenarioNode::mv_to_neighbor(w[0].clone(), w[1].clone());
+    }
+    roots
+}
+// read_file / write_file /////////////////////////////////
+/// a file is XML (scenario_xml.rs) when its extension is ".xml", JSON otherwise
+pub fn is_xml(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e.eq_ignore_ascii_case("xml"))
+}
+pub fn read_file(path: &Path) -> io::Result<ScenarioFile> {
+    if is_xml(path) {
+        return scenario_xml::read_file(path); }
+    let json= fs::read_to_string(path)?;
+    serde_json::from_str(&json).map_err(io::Error::from)
+}
+pub fn write_file(path: &Path, file: &ScenarioFile) -> io::Result<()> {
+    if is_xml(path) {
+        return scenario_xml::write_file(path, file); }
+    let json= serde_json::to_string_pretty(file).map_err(io::Error::from)?;
+    fs::write(path, json)
+}
+// save ////////////////////////////////////////////////////
+pub fn save(path: &Path, first_root: Option<Rc<ScenarioNode>>, next_id: i32) -> io::Result<()> {
+    let file= ScenarioFile{
+        version: SCENARIO_FILE_VERSION,
+        next_id,
+        nodes  : forest_to_records(first_root),
+    };
+    write_file(path, &file)
+}
+// load ////////////////////////////////////////////////////
+/// returns the root level nodes and the id counter
+pub fn load(path: &Path) -> io::Result<(Vec<Rc<ScenarioNode>>, i32)> {
+    let file= read_file(path)?;
+    Ok( (records_to_forest(&file.nodes), file.next_id) )
+}
+// fingerprint /////////////////////////////////////////////
+/// identifies a saved tree, a sidecar history is applied to the same tree only
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Fingerprint {
+    pub next_id: i32,
+    pub hash   : u64, // FNV-1a of the depth, id, kind and attributes of each node in pre-order
+}
+/// the attributes are the ones of the XML form (scenario_xml::attributes), so that JSON and XML agree
+pub fn fingerprint(first_root: Option<Rc<ScenarioNode>>, next_id: i32) -> Fingerprint {
+    let mut hash: u64= 0xcbf2_9ce4_8422_2325;
+    let mut feed= |bytes: &[u8]| {
+        for b in bytes {
+            hash^= *b as u64;
+            hash= hash.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    };
+    for (n, depth) in scenario_query::pre_order_forest_depth(first_root) {
+        let v= n.value.borrow();
+        feed( &(depth as u64).to_le_bytes() );
+        feed( &n.id.get().to_le_bytes() );
+        feed( v.kind().name().as_bytes() );
+        for (k, a) in scenario_xml::attributes(&v) {
+            feed( k.as_bytes() );
+            feed( &[0] );
+            feed( a.as_bytes() );
+            feed( &[0] );
+        }
+        if let Item::Mat(m) | Item::Pmat(m) = &*v {
+            feed( m.txt.as_bytes() ); }
+        feed( &[0xff] ); // the end of a node
+    }
+    Fingerprint{ next_id, hash }
+}
+// history_path ////////////////////////////////////////////
+/// sidecar file of the undo history, e.g. "a.json" -> "a.json.history"
+pub fn history_path(scenario_path: &Path) -> PathBuf {
+    let mut s= scenario_path.as_os_str().to_owned();
+    s.push(".history");
+    PathBuf::from(s)
+}
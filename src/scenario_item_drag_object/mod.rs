@@ -29,14 +29,15 @@ impl ScenarioItemDragObject {
         *self.imp().list_item.borrow_mut()= Some(l.into());
     }
 
-    pub fn get_root_store(&self) -> gio::ListStore{
-        self.imp().root_store.borrow().as_ref().unwrap().clone()
+    // None until set by the factory
+    pub fn get_root_store(&self) -> Option<gio::ListStore>{
+        self.imp().root_store.borrow().clone()
     }
-    pub fn get_history(&self) -> Rc<OperationHistory>{
-        self.imp().history.borrow().as_ref().unwrap().clone()
+    pub fn get_history(&self) -> Option<Rc<OperationHistory>>{
+        self.imp().history.borrow().clone()
     }
-    pub fn get_list_item(&self) -> ListItem{
-        self.imp().list_item.borrow().as_ref().unwrap().clone()
+    pub fn get_list_item(&self) -> Option<ListItem>{
+        self.imp().list_item.borrow().clone()
     }
 }
 
@@ -16,27 +16,31 @@
 //!        3.2. pmat(pageの特殊形, matと等価)
 
 use std::cell::{RefCell,Cell};
-use std::rc::{Rc, Weak};
+use std::rc::Rc;
 use std::fmt;
+use std::io;
+use serde::{Serialize, Deserialize};
 
+use crate::scenario_tree::{self, NodeHandle};
+pub use crate::color::Color;
+pub use crate::geometry::Length;
+
+/// the links (parent, ordered children) are kept in scenario_tree,
+/// a node owns its children, see scenario_tree for the root level nodes
 #[derive(Debug)]
 pub struct ScenarioNode {
     pub value   : RefCell<Item>,
-    pub bt      : Cell<BranchType>,
-    pub parent  : RefCell<Weak<ScenarioNode>>, // Cellはコピー/置き換えになっちゃうのでRefCell
-    pub child   : RefCell<Option<Rc<ScenarioNode>>>,
-    pub neighbor: RefCell<Option<Rc<ScenarioNode>>>,
     pub id      : Cell<i32>,
+    handle      : NodeHandle,
 }
-impl Default for ScenarioNode{
-    fn default() -> Self{
-        ScenarioNode{
-            value   : RefCell::new(Item::Page),
-            bt      : Cell::new(BranchType::Child),
-            parent  : RefCell::new(Weak::new()),
-            child   : RefCell::new(None),
-            neighbor: RefCell::new(None),
-            id      : Cell::new(0),
+impl Drop for ScenarioNode{
+    // the descendants are dropped by a loop, not by the recursion
+    fn drop(&mut self){
+        let mut stack= scenario_tree::try_with(|a| a.release(self.handle)).unwrap_or_default();
+        while let Some(n) = stack.pop() {
+            if Rc::strong_count(&n) == 1 { // n is dropped here, take its children first
+                stack.extend( scenario_tree::try_with(|a| a.take_children(n.handle)).unwrap_or_default() );
+            }
         }
     }
 }
@@ -44,10 +48,7 @@ impl Default for ScenarioNode{
 fn dump_mat(m: &Mat) -> String{
     let mut s= String::new();
     s+= &("M(".to_owned() +
-          "c:" +
-          &m.col.r.to_string() + "," +
-          &m.col.g.to_string() + "," +
-          &m.col.b.to_string() + "," +
+          "c:" + &m.col.to_string() + "," +
           " p:" +
           &m.pos.x.to_string() + "," +
           &m.pos.y.to_string() + "," +
@@ -60,6 +61,8 @@ fn dump_mat(m: &Mat) -> String{
     if let Some(a)= &m.src   { s+= &("s".to_owned() + a); }
     if let Some(a)= &m.lbl   { s+= &("l".to_owned() + a); }
     if let Some(a)= &m.lblref{ s+= &("lr".to_owned() + a); }
+    if m.v { s+= "v,"; }
+    if !m.txt.is_empty() { s+= &("t[".to_owned() + &m.txt + "]"); }
     s
 }
 impl fmt::Display for ScenarioNode {
@@ -70,10 +73,7 @@ impl fmt::Display for ScenarioNode {
             Item::Scene(c) => {
                 s+= "S,";
                 if let Some(a)= &c.bgimg{ s+= &("b[".to_owned() + a + "]"); }
-                s+= &("c:".to_owned() +
-                      &c.bgcol.r.to_string() + "," +
-                      &c.bgcol.g.to_string() + "," +
-                      &c.bgcol.b.to_string() + ",");
+                s+= &("c:".to_owned() + &c.bgcol.to_string() + ",");
                 if let Some(i)= &c.crop{
                     s+= "ci:";
                     s+= &(i.pos.x.to_string() + "," + &i.pos.y.to_string());
@@ -90,12 +90,12 @@ impl fmt::Display for ScenarioNode {
                                     &o.pos.y.to_string() + "),"),
             Item::Pmat(m)  => s+= &dump_mat(m),
         }
-        match self.bt.get(){
+        match self.get_bt(){
             BranchType::Child => s+= "b:c,",
             _                 => s+= "b:n,",
         }
         s+= "p:";
-        if let Some(p) = &self.parent.borrow().clone().upgrade(){
+        if let Some(p) = &self.get_parent(){
             match &(*p.value.borrow()){
                 Item::Group    => s+= "G",
                 Item::Scene(_c)=> s+= "S",
@@ -112,151 +112,178 @@ impl ScenarioNode {
     pub fn set_value(&self, v: Item){
         *self.value.borrow_mut()= v;
     }
-    pub fn set_bt(&self, bt: BranchType){
-        self.bt.set(bt);
+    /// a lone root node
+    pub fn new() -> Rc<ScenarioNode>{
+        Rc::new_cyclic(|w| ScenarioNode{
+            value   : RefCell::new(Item::Page),
+            id      : Cell::new(0),
+            handle  : scenario_tree::with(|a| a.alloc_node(w.clone())),
+        })
     }
-    pub fn set_parent(&self, p: Weak<ScenarioNode>){
-        *self.parent.borrow_mut()= p;
+    /// unlink self, self becomes a lone root with its descendants
+    pub fn remove(&self){
+        let owner= scenario_tree::with(|a| a.detach(self.handle));
+        drop(owner); // after the arena is released
     }
-    pub fn set_child(&self, c: Rc<ScenarioNode>){
-        *self.child.borrow_mut()= Some(c);
+    // accessors ///////////////////////////////////////////
+    pub fn get_handle(&self) -> NodeHandle{
+        self.handle
     }
-    pub fn set_neighbor(&self, n: Rc<ScenarioNode>){
-        *self.neighbor.borrow_mut()= Some(n);
+    pub fn from_handle(h: NodeHandle) -> Option<Rc<ScenarioNode>>{
+        scenario_tree::with(|a| a.node(h))
     }
-    pub fn unset_neighbor(&self){
-        *self.neighbor.borrow_mut()= None;
+    /// the first child
+    pub fn get_child(&self) -> Option<Rc<ScenarioNode>>{
+        scenario_tree::with(|a| a.first_child(self.handle).and_then(|c| a.node(c)))
     }
-    pub fn new() -> ScenarioNode{
-        ScenarioNode{
-            value   : RefCell::new(Item::Page),
-            bt      : Cell::new(BranchType::Child),
-            parent  : RefCell::new(Weak::new()),
-            child   : RefCell::new(None),
-            neighbor: RefCell::new(None),
-            id      : Cell::new(0),
+    /// the next sibling
+    pub fn get_neighbor(&self) -> Option<Rc<ScenarioNode>>{
+        scenario_tree::with(|a| a.next(self.handle).and_then(|n| a.node(n)))
+    }
+    /// the previous sibling (bt == Neighbor) or the parent (bt == Child)
+    pub fn get_parent(&self) -> Option<Rc<ScenarioNode>>{
+        scenario_tree::with(|a| a.prev(self.handle).or(a.parent(self.handle)).and_then(|p| a.node(p)))
+    }
+    /// Neighbor if self is not the first sibling
+    pub fn get_bt(&self) -> BranchType{
+        if scenario_tree::with(|a| a.prev(self.handle)).is_some() {
+            BranchType::Neighbor
+        } else {
+            BranchType::Child
         }
     }
-    pub fn remove(&self){
-        let self_p= (*self.parent.borrow_mut()).upgrade();
-
-        if self_p.is_some() { // parentあり -> root以外
-            let self_p= self_p.unwrap().clone();
-            let mut self_p_cn; // child or neighbor
-            if self.bt == BranchType::Child.into() {
-                self_p_cn= self_p.child.borrow_mut();
-            } else {
-                self_p_cn= self_p.neighbor.borrow_mut();
+    /// the parent in the hierarchy (not the previous neighbor), None at the root level
+    pub fn get_parent_node(&self) -> Option<Rc<ScenarioNode>>{
+        scenario_tree::with(|a| a.parent(self.handle).and_then(|p| a.node(p)))
+    }
+    pub fn get_children(&self) -> Vec<Rc<ScenarioNode>>{
+        scenario_tree::with(|a| a.children(self.handle).into_iter().filter_map(|c| a.node(c)).collect())
+    }
+    pub fn get_child_count(&self) -> usize{
+        scenario_tree::with(|a| a.child_count(self.handle))
+    }
+    pub fn get_child_at(&self, i: usize) -> Option<Rc<ScenarioNode>>{
+        scenario_tree::with(|a| a.child_at(self.handle, i).and_then(|c| a.node(c)))
+    }
+    /// the index in the siblings
+    pub fn get_position(&self) -> usize{
+        scenario_tree::with(|a| a.position(self.handle))
+    }
+    /// the first of the siblings (the first root at the root level)
+    pub fn get_first_sibling(&self) -> Option<Rc<ScenarioNode>>{
+        scenario_tree::with(|a| a.node( a.first_sibling(self.handle) ))
+    }
+    // index_path //////////////////////////////////////////
+    /// indices from the root level to self,
+    /// e.g. [2, 0] is the first child of the third root node
+    pub fn index_path(&self) -> Vec<usize>{
+        let mut path= scenario_tree::with(|a| {
+            let mut path= vec![ a.position(self.handle) ];
+            let mut n= a.parent(self.handle);
+            while let Some(p) = n {
+                path.push( a.position(p) );
+                n= a.parent(p);
             }
-            if let Some(self_n) = (*self.neighbor.borrow_mut()).as_ref(){
-                *self_p_cn= Some(self_n.clone());
-            } else {
-                *self_p_cn= None;
+            path
+        });
+        path.reverse();
+        path
+    }
+    // node_at_path ////////////////////////////////////////
+    /// the node at `path` in the forest which starts with `first_root`
+    pub fn node_at_path(first_root: Rc<ScenarioNode>, path: &[usize]) -> Option<Rc<ScenarioNode>>{
+        let (first, rest)= path.split_first()?;
+        scenario_tree::with(|a| {
+            let mut n= a.nth_next(first_root.handle, *first)?;
+            for i in rest {
+                n= a.child_at(n, *i)?;
             }
-            if let Some(self_n) = (*self.neighbor.borrow_mut()).as_ref() {
-                self_n.set_bt( self.bt.get() );
-                self_n.set_parent(self.parent.clone().take());
+            a.node(n)
+        })
+    }
+    // insert_at_path //////////////////////////////////////
+    /// link B (already removed) so that its index_path becomes `path`.
+    /// returns false if the parent or the previous neighbor of `path` does not exist
+    pub fn insert_at_path(first_root: Option<Rc<ScenarioNode>>,
+                          path      : &[usize],
+                          b         : Rc<ScenarioNode>) -> bool{
+        let (idx, parent_path)= match path.split_last() {
+            Some((i, p)) => (*i, p),
+            None         => return false,
+        };
+        if idx > 0 {
+            let mut prev_path= parent_path.to_vec();
+            prev_path.push(idx - 1);
+            match first_root.and_then(|r| ScenarioNode::node_at_path(r, &prev_path)) {
+                Some(prev) => ScenarioNode::mv_to_neighbor(prev, b),
+                None       => return false,
             }
-        } else { // rootの場合
-            if let Some(self_n) = (*self.neighbor.borrow_mut()).as_ref() {
-                self_n.set_bt(BranchType::Child);
-                self_n.set_parent(Weak::new());
+        } else if parent_path.is_empty() { // the first root
+            if let Some(r) = first_root {
+                ScenarioNode::mv_to_parent(r, b);
+            }
+        } else {
+            match first_root.and_then(|r| ScenarioNode::node_at_path(r, parent_path)) {
+                Some(parent) => ScenarioNode::mv_to_child(parent, b),
+                None         => return false,
             }
         }
+        true
     }
+    /// self, the descendants and the following neighbors
     pub fn dump (&self, depth: usize){
-        println!("{}{}", " ".repeat(depth), self);
-        if let Some(c) = (*self.child.borrow_mut()).as_ref(){
-            c.dump(depth + 2);
-        }
-        if let Some(n) = (*self.neighbor.borrow_mut()).as_ref(){
-            n.dump(depth);
+        let _= self.dump_to(depth, &mut io::stdout().lock());
+    }
+    /// dump() to `w`, walked by a loop
+    pub fn dump_to<W: io::Write>(&self, depth: usize, w: &mut W) -> io::Result<()>{
+        let mut indent= String::new();
+        let mut stack= vec![ (ScenarioNode::from_handle(self.handle), depth) ]; // (the next node, its indent)
+        while let Some((n, d)) = stack.pop() {
+            let Some(x) = n else { continue };
+            if indent.len() < d {
+                indent.push_str( &" ".repeat(d - indent.len()) ); }
+            writeln!(w, "{}{}", &indent[..d], x)?;
+            stack.push( (x.get_neighbor(), d) );
+            stack.push( (x.get_child(), d + 2) ); // the descendants before the neighbors
         }
+        Ok(())
+    }
+    // movable /////////////////////////////////////////////
+    /// B can be linked around A unless A is B or a descendant of B
+    fn movable(a: &ScenarioNode, b: &ScenarioNode) -> bool{
+        !scenario_tree::with(|t| t.is_within(a.handle, b.handle))
     }
     // mv_to_parent ////////////////////////////////////////
-    /// make B a child/neighbor of A's parent
+    /// make B the previous sibling of A (a child/neighbor of A's parent)
     pub fn mv_to_parent(a: Rc::<ScenarioNode>, b: Rc<ScenarioNode>){
-        if Rc::ptr_eq(&a, &b){ return; }
+        if !ScenarioNode::movable(&a, &b){ return; }
 
-        // 1. remove B
         b.remove();
-        // 2. set the parent of A to B,
-        //   and the branch type of B to A's bt,
-        //   and the branch tyep of A to neighbor
-        b.set_bt(a.bt.get());
-        a.set_bt(BranchType::Neighbor);
-        // 3. set the neighbor of B to A
-        b.set_neighbor(a.clone());
-
-        if let Some(a_p) = (*a.parent.borrow_mut()).upgrade(){
-            // 4. if exists, the neighbor of A's parent to B
-            a_p.set_neighbor( b.clone() );
-            // 5. if exists, set the parent of B to A's parent or empty.
-            b.set_parent(Rc::downgrade(&a_p));
-        } else {
-            b.set_parent(Weak::new());
-        }
-        // 6. set the parent of A to B
-        a.set_parent(Rc::downgrade(&b));
-
+        scenario_tree::with(|t| t.insert_before(a.handle, b.handle));
     }
     // mv_to_child /////////////////////////////////////////
-    /// make B a child node of A
+    /// make B the first child node of A
     pub fn mv_to_child(a: Rc::<ScenarioNode>, b: Rc<ScenarioNode>){
-        if Rc::ptr_eq(&a, &b){ return; }
+        if !ScenarioNode::movable(&a, &b){ return; }
 
-        // 1. remove B
         b.remove();
-        // 2. set the parent of B to A, and the branch type of B to child
-        b.set_parent(Rc::downgrade(&a));
-        b.set_bt(BranchType::Child);
-        // 3. set the neighbor of B to {the child of A or None},
-        if let Some(a_c) = (*a.child.borrow_mut()).as_ref(){
-            a_c.set_bt(BranchType::Neighbor);
-            b.set_neighbor(a_c.clone());
-        } else {
-            b.unset_neighbor();
-        }
-        // 4. if exists, set the parent of child of A
-        //    (in this timing, already B's neighbor) to B
-        if let Some(b_n) = (*b.neighbor.borrow_mut()).as_ref(){
-            b_n.set_parent(Rc::downgrade(&b));
-        }
-        // 5. set the child of A to B
-        a.set_child(b.clone());
+        scenario_tree::with(|t| t.insert_first_child(a.handle, b.handle));
     }
     // mv_to_neighbor //////////////////////////////////////
-    /// make B a neighbor of A
+    /// make B the next sibling of A
     pub fn mv_to_neighbor(a: Rc::<ScenarioNode>, b: Rc<ScenarioNode>){
-        if Rc::ptr_eq(&a, &b){ return; }
+        if !ScenarioNode::movable(&a, &b){ return; }
 
-        // 1. remove B
         b.remove();
-        // 2. set the parent of B to A, and the branch type of B to neighbor
-        b.set_parent(Rc::downgrade(&a));
-        b.set_bt(BranchType::Neighbor);
-        // 3. set the neighbor of B to {the neighbor of A or None},
-        if let Some(a_n) = (*a.neighbor.borrow_mut()).as_ref(){
-            a_n.set_bt(BranchType::Neighbor);
-            b.set_neighbor(a_n.clone());
-        } else {
-            b.unset_neighbor();
-        }
-        // 4. if exists, set the parent of neighbor of A
-        //    (in this timing, already B's neighbor) to B
-        if let Some(b_n) = (*b.neighbor.borrow_mut()).as_ref(){
-            b_n.set_parent(Rc::downgrade(&b));
-        }
-        // 5. set the neighbor of A to B
-        a.set_neighbor(b.clone());
+        scenario_tree::with(|t| t.insert_after(a.handle, b.handle));
     }
 }
 // BranchType //////////////////////////////////////////////
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum BranchType{ Child, Neighbor, }
 
 // Item ////////////////////////////////////////////////////
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Item{
     Group,
     Scene(Scene),
@@ -265,40 +292,101 @@ pub enum Item{
     Ovimg(Ovimg),
     Pmat(Mat)
 }
-// Color ///////////////////////////////////////////////////
-#[derive(Debug)]
-pub struct Color {
-    pub r : u32,
-    pub g : u32,
-    pub b : u32,
+impl Item {
+    pub fn kind(&self) -> ItemKind{
+        match self {
+            Item::Group    => ItemKind::Group,
+            Item::Scene(_) => ItemKind::Scene,
+            Item::Page     => ItemKind::Page,
+            Item::Mat(_)   => ItemKind::Mat,
+            Item::Ovimg(_) => ItemKind::Ovimg,
+            Item::Pmat(_)  => ItemKind::Pmat,
+        }
+    }
+    /// lbl of Scene / Mat / Pmat
+    pub fn lbl(&self) -> Option<String>{
+        match self {
+            Item::Scene(c) => c.lbl.clone(),
+            Item::Mat(m)   => m.lbl.clone(),
+            Item::Pmat(m)  => m.lbl.clone(),
+            _              => None,
+        }
+    }
+    /// lblref of Scene / Mat / Pmat
+    pub fn lblref(&self) -> Option<String>{
+        match self {
+            Item::Scene(c) => c.lblref.clone(),
+            Item::Mat(m)   => m.lblref.clone(),
+            Item::Pmat(m)  => m.lblref.clone(),
+            _              => None,
+        }
+    }
+}
+// ItemKind ////////////////////////////////////////////////
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ItemKind{ Group, Scene, Page, Mat, Ovimg, Pmat, }
+impl ItemKind {
+    pub const ALL: [ItemKind; 6] = [ItemKind::Group, ItemKind::Scene, ItemKind::Page,
+                                    ItemKind::Mat,   ItemKind::Ovimg, ItemKind::Pmat];
+    pub fn name(&self) -> &'static str{
+        match self {
+            ItemKind::Group => "group",
+            ItemKind::Scene => "scene",
+            ItemKind::Page  => "page",
+            ItemKind::Mat   => "mat",
+            ItemKind::Ovimg => "ovimg",
+            ItemKind::Pmat  => "pmat",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<ItemKind>{
+        ItemKind::ALL.iter().find(|k| k.name() == name).copied()
+    }
 }
 // Position ////////////////////////////////////////////////
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Position {
-    pub x : usize,
-    pub y : usize,
+    pub x : Length,
+    pub y : Length,
+}
+impl Position {
+    pub fn px(x: f64, y: f64) -> Position {
+        Position{ x: Length::Px(x), y: Length::Px(y) }
+    }
+    /// (x, y) in pixels on the canvas (or image) of `w` x `h`
+    pub fn resolve(&self, w: f64, h: f64) -> (f64, f64) {
+        (self.x.resolve(w), self.y.resolve(h))
+    }
 }
 // Dimension ///////////////////////////////////////////////
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Dimension {
-    pub w : usize,
-    pub h : usize,
+    pub w : Length,
+    pub h : Length,
+}
+impl Dimension {
+    pub fn px(w: f64, h: f64) -> Dimension {
+        Dimension{ w: Length::Px(w), h: Length::Px(h) }
+    }
+    /// (w, h) in pixels on the canvas (or image) of `w` x `h`
+    pub fn resolve(&self, w: f64, h: f64) -> (f64, f64) {
+        (self.w.resolve(w), self.h.resolve(h))
+    }
 }
 // Ovimg ///////////////////////////////////////////////////
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Ovimg {
     pub path  : String,
     pub pos   : Position,
     pub a     : u8,
 }
 // CropInfo ////////////////////////////////////////////////
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CropInfo {
     pub pos : Position,
     pub dim : Dimension
 }
 // Scene ///////////////////////////////////////////////////
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Scene {
     pub bgimg : Option<String>,
     pub bgcol : Color,
@@ -307,7 +395,7 @@ pub struct Scene {
     pub lblref: Option<String>,
 }
 // Mat /////////////////////////////////////////////////////
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Mat {
     pub col   : Color,
     pub pos   : Position,
@@ -318,6 +406,10 @@ pub struct Mat {
     pub lbl   : Option<String>,
     pub lblref: Option<String>,
     pub name  : String, // this field is only for debug
+    #[serde(default)]
+    pub txt   : String, // text body
+    #[serde(default)]
+    pub v     : bool,   // 縦書き (vertical writing)
 }
 impl Mat {
     fn dump(&self) {
@@ -328,6 +420,7 @@ impl Mat {
         print_opt_str(&self.src,    String::from("    src"));
         print_opt_str(&self.lbl,    String::from("    lbl"));
         print_opt_str(&self.lblref, String::from("    lblref"));
+        println!{"    v= {}, txt= {}", self.v, self.txt};
     }
 }
 // print_opt_str ///////////////////////////////////////////
@@ -344,3 +437,192 @@ fn print_opt_str(a: &Option<String>, prefix: String){
 //         println!("> Dropping {}", self.id.get());
 //     }
 // }
+
+// tests ///////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::rc::Rc;
+    use super::ScenarioNode;
+    use crate::scenario_file::{NodeRecord, to_record, from_record};
+
+    const N: usize = 1_000_000;
+
+    fn new_node(id: i32) -> Rc<ScenarioNode> {
+        let n= ScenarioNode::new();
+        n.id.set(id);
+        n
+    }
+    fn id_of(n: Option<Rc<ScenarioNode>>) -> Option<i32> {
+        n.map(|x| x.id.get())
+    }
+    /// the number of the nodes in `r`
+    fn record_len(r: &NodeRecord) -> usize {
+        let mut stack= vec![r];
+        let mut n= 0;
+        while let Some(x) = stack.pop() {
+            n+= 1;
+            stack.extend(x.children.iter());
+        }
+        n
+    }
+    /// counts the lines of dump_to() without looking at the indent
+    struct Lines(usize);
+    impl io::Write for Lines {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if buf.last() == Some(&b'\n') {
+                self.0+= 1; }
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+    fn dump_lines(n: &ScenarioNode) -> usize {
+        let mut lines= Lines(0);
+        n.dump_to(0, &mut lines).unwrap();
+        lines.0
+    }
+    /// to_record / from_record / dump_to of the subtree of `root`, which has `len` nodes
+    fn check_walks(root: &ScenarioNode, len: usize) {
+        let r= to_record(root);
+        assert_eq!(record_len(&r), len);
+        let copy= from_record(&r);
+        assert_eq!(record_len(&to_record(&copy)), len);
+        assert_eq!(dump_lines(&copy), len);
+    }
+
+    // 1M nodes ////////////////////////////////////////////
+    #[test]
+    fn root_neighbor_chain() {
+        let roots: Vec<Rc<ScenarioNode>>= (0..N as i32).map(new_node).collect(); // the holders of the root level
+        for w in roots.windows(2) {
+            ScenarioNode::mv_to_neighbor(w[0].clone(), w[1].clone()); }
+        assert_eq!(id_of(roots[0].get_neighbor()), Some(1));
+        assert_eq!(roots[N - 1].get_position(), N - 1);
+        assert_eq!(dump_lines(&roots[0]), N);
+        check_walks(&roots[0], 1);
+        drop(roots);
+    }
+    #[test]
+    fn child_chain() {
+        let parent= new_node(-1);
+        let mut prev: Option<Rc<ScenarioNode>>= None;
+        for i in 0..N as i32 {
+            let n= new_node(i);
+            match prev {
+                Some(p) => ScenarioNode::mv_to_neighbor(p, n.clone()),
+                None    => ScenarioNode::mv_to_child(parent.clone(), n.clone()),
+            }
+            prev= Some(n);
+        }
+        drop(prev); // the children are owned by the parent only
+        assert_eq!(parent.get_child_count(), N);
+        check_walks(&parent, N + 1);
+        drop(parent);
+    }
+    #[test]
+    fn deep_nesting() {
+        let root= new_node(-1);
+        let mut last= root.clone();
+        for i in 0..N as i32 {
+            let n= new_node(i);
+            ScenarioNode::mv_to_child(last, n.clone());
+            last= n;
+        }
+        assert_eq!(last.index_path().len(), N + 1);
+        drop(last);
+        check_walks(&root, N + 1);
+        drop(root);
+    }
+
+    // random moves ////////////////////////////////////////
+    /// true if `a` is `b` or a descendant of `b`
+    fn within(a: &Rc<ScenarioNode>, b: &Rc<ScenarioNode>) -> bool {
+        let mut n= Some(a.clone());
+        while let Some(x) = n {
+            if Rc::ptr_eq(&x, b) {
+                return true; }
+            n= x.get_parent_node();
+        }
+        false
+    }
+    /// (parent, parent or previous, next, the number of children) of a node
+    type Links = (Option<i32>, Option<i32>, Option<i32>, usize);
+    fn links(nodes: &[Rc<ScenarioNode>]) -> Vec<Links> {
+        nodes.iter()
+            .map(|n| (id_of(n.get_parent_node()), id_of(n.get_parent()), id_of(n.get_neighbor()), n.get_child_count()))
+            .collect()
+    }
+    /// every parent / prev / next / child_count agrees with the children of each node
+    fn check_links(nodes: &[Rc<ScenarioNode>]) {
+        let mut roots= 0;
+        for x in nodes.iter() {
+            let children= x.get_children();
+            assert_eq!(x.get_child_count(), children.len());
+            for (i, c) in children.iter().enumerate() {
+                assert_eq!(id_of(c.get_parent_node()), Some(x.id.get()));
+                assert_eq!(c.get_position(), i);
+                let prev= if i == 0 { x } else { &children[i - 1] };
+                assert_eq!(id_of(c.get_parent()), Some(prev.id.get()));
+                assert_eq!(id_of(c.get_neighbor()), children.get(i + 1).map(|n| n.id.get()));
+            }
+            if x.get_parent_node().is_none() {
+                roots+= 1;
+                if let Some(p) = x.get_parent() { // the previous root
+                    assert_eq!(id_of(p.get_neighbor()), Some(x.id.get())); }
+            }
+            let mut depth= 0; // no loop of the parents
+            let mut p= x.get_parent_node();
+            while let Some(n) = p {
+                depth+= 1;
+                assert!(depth <= nodes.len());
+                p= n.get_parent_node();
+            }
+        }
+        let children: usize= nodes.iter().map(|n| n.get_child_count()).sum();
+        assert_eq!(roots + children, nodes.len());
+    }
+    #[test]
+    fn random_moves() {
+        const M: usize = 64;
+        let nodes: Vec<Rc<ScenarioNode>>= (0..M as i32).map(new_node).collect(); // holds the root level nodes too
+        let mut seed: u64= 0x2545_f491_4f6c_dd1d;
+        let mut random= |m: usize| { // xorshift
+            seed^= seed << 13;
+            seed^= seed >> 7;
+            seed^= seed << 17;
+            (seed % m as u64) as usize
+        };
+        for _ in 0..5000 {
+            let (a, b)= (nodes[random(M)].clone(), nodes[random(M)].clone());
+            let op= random(3);
+            let before= links(&nodes);
+            let ignored= within(&a, &b);
+            match op {
+                0 => ScenarioNode::mv_to_parent  (a.clone(), b.clone()),
+                1 => ScenarioNode::mv_to_child   (a.clone(), b.clone()),
+                _ => ScenarioNode::mv_to_neighbor(a.clone(), b.clone()),
+            }
+            if ignored {
+                assert_eq!(links(&nodes), before);
+            } else {
+                match op {
+                    0 => {
+                        assert_eq!(id_of(b.get_neighbor()), Some(a.id.get()));
+                        assert_eq!(id_of(b.get_parent_node()), id_of(a.get_parent_node()));
+                    },
+                    1 => {
+                        assert_eq!(id_of(a.get_child()), Some(b.id.get()));
+                        assert_eq!(id_of(b.get_parent_node()), Some(a.id.get()));
+                    },
+                    _ => {
+                        assert_eq!(id_of(a.get_neighbor()), Some(b.id.get()));
+                        assert_eq!(id_of(b.get_parent_node()), id_of(a.get_parent_node()));
+                    },
+                }
+            }
+            check_links(&nodes);
+        }
+    }
+}
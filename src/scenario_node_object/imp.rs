@@ -7,7 +7,7 @@ use std::cell::{RefCell,Cell};
 use crate::scenario_node::ScenarioNode;
 
 // Object holding the node
-#[derive(Properties, Default)]
+#[derive(Properties)]
 #[properties(wrapper_type = super::ScenarioNodeObject)]
 pub struct ScenarioNodeObject {
     pub(super) node: RefCell<Rc<ScenarioNode>>,
@@ -15,6 +15,15 @@ pub struct ScenarioNodeObject {
     pub seq: Cell<i32>,
 }
 
+impl Default for ScenarioNodeObject {
+    fn default() -> Self {
+        ScenarioNodeObject {
+            node: RefCell::new(ScenarioNode::new()),
+            seq : Cell::new(0),
+        }
+    }
+}
+
 #[glib::object_subclass]
 impl ObjectSubclass for ScenarioNodeObject {
     const NAME: &'static str = "MyGtkAppScenarioNodeObject";
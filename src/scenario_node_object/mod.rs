@@ -1,7 +1,8 @@
 pub(crate) mod imp;
 
-use gtk::{gio, glib, TreeListRow};
+use gtk::{gio, glib, TreeListRow, TreeListModel, SingleSelection};
 use gtk::prelude::Cast;
+use gtk::prelude::CastNone;
 use gtk::prelude::ListModelExt;
 use glib::Object;
 use glib::subclass::types::ObjectSubclassIsExt;
@@ -9,6 +10,7 @@ use std::rc::Rc;
 use crate::scenario_node::ScenarioNode;
 use crate::scenario_node::BranchType;
 use crate::scenario_node::Item;
+use crate::tree_error::{TreeError, TreeResult};
 
 glib::wrapper! {
     pub struct ScenarioNodeObject(ObjectSubclass<imp::ScenarioNodeObject>);
@@ -16,10 +18,7 @@ glib::wrapper! {
 
 impl ScenarioNodeObject {
     pub fn new() -> Self {
-        let obj: ScenarioNodeObject= Object::builder().build();
-        let node= ScenarioNode::new();
-        obj.set_node(Rc::new(node));
-        obj
+        Object::builder().build()
     }
     pub fn new_with_seq(seq: i32) -> Self {
         let obj= ScenarioNodeObject::new();
@@ -40,51 +39,62 @@ impl ScenarioNodeObject {
     pub fn get_node     (&self) -> Rc<ScenarioNode>  { self.imp().node.borrow().clone() }
     pub fn set_node     (&self, r: Rc<ScenarioNode>) { *self.imp().node.borrow_mut()= r; }
     pub fn set_vaue     (&self, v:Item)              { self.imp().node.borrow().set_value(v); }
-    pub fn set_child    (&self, c: Rc<ScenarioNode>) { self.imp().node.borrow().set_child(c); }
-    pub fn set_neighbor (&self, n: Rc<ScenarioNode>) { self.imp().node.borrow().set_neighbor(n); }
-    pub fn set_parent   (&self, p: Rc<ScenarioNode>) { self.imp().node.borrow().set_parent(Rc::downgrade(&p)); }
     // set_seq is implemented by derive(Properties)
     pub fn get_seq      (&self) -> i32               { self.imp().seq.get() }
     pub fn set_id       (&self, id: i32)             { self.imp().node.borrow().id.set(id); }
     pub fn get_id       (&self) -> i32               { self.imp().node.borrow().id.get() }
-    pub fn set_bt       (&self, b: BranchType)       { self.imp().node.borrow().set_bt(b); }
-    pub fn get_bt       (&self) -> BranchType        { self.imp().node.borrow().bt.get() }
+    pub fn get_bt       (&self) -> BranchType        { self.imp().node.borrow().get_bt() }
 }
 
+// check_store /////////////////////////////////////////////
+/// every item of `store` is a ScenarioNodeObject, so that adj_seq() on it does not fail half way
+pub fn check_store(store: &gio::ListStore) -> TreeResult<()> {
+    for item in store {
+        item.ok().and_downcast::<ScenarioNodeObject>().ok_or(TreeError::NotNode)?; }
+    Ok(())
+}
 // adj_seq ///////////////////////////////////////////////
-pub fn adj_seq (model: &gio::ListStore, greater_than: i32, i: i32) {
-    for scn in model {
-        let exp= scn.expect("scenario node");
-        let scn_object= exp.downcast_ref::<ScenarioNodeObject>().unwrap();
+/// add `i` to the seq of the items from `greater_than`, the store is checked before any change
+pub fn adj_seq (model: &gio::ListStore, greater_than: i32, i: i32) -> TreeResult<()> {
+    check_store(model)?;
+    for scn_object in model.into_iter().filter_map(|scn| scn.ok().and_downcast::<ScenarioNodeObject>()) {
         if scn_object.get_seq() >= greater_than {
             scn_object.set_seq( scn_object.get_seq() + i );
         }
     }
+    Ok(())
 }
 // add_neighbor ////////////////////////////////////////////
 pub fn add_neighbor(dest_sno   : &ScenarioNodeObject,
                     new_node   : &ScenarioNodeObject,
-                    dest_store : &gio::ListStore){
+                    dest_store : &gio::ListStore) -> TreeResult<()>{
+    check_store(dest_store)?; // before any change
 
     ScenarioNode::mv_to_neighbor( dest_sno.get_node(), new_node.get_node() );
 
     new_node.set_seq( dest_sno.get_seq() + 1 );
-    adj_seq( dest_store, dest_sno.get_seq() + 1, 1 );
+    adj_seq( dest_store, dest_sno.get_seq() + 1, 1 )?;
     if dest_store.n_items() >= ((dest_sno.get_seq() + 1) as u32) {
         dest_store.insert( (dest_sno.get_seq() as u32) + 1, new_node ); }
-
+    Ok(())
 }
 // add_child ///////////////////////////////////////////////
 pub fn add_child(dest_sno   : &ScenarioNodeObject,
                  new_node   : &ScenarioNodeObject,
                  dest_row   : &TreeListRow,
-                 dest_store : &gio::ListStore){
+                 dest_store : &gio::ListStore) -> TreeResult<()>{
+    let children= match dest_row.children() { // None while dest is collapsed
+        Some(m) => Some( m.downcast::<gio::ListStore>().map_err(|_| TreeError::NoStore)? ),
+        None    => None,
+    };
+    if let Some(s) = &children { // before any change
+        check_store(s)?; }
+
     ScenarioNode::mv_to_child(dest_sno.get_node(), new_node.get_node());
     new_node.set_seq( 0 );
 
-    if let Some(m) = dest_row.children(){
-        let s= m.downcast::<gio::ListStore>().expect("ListStore");
-        adj_seq( &s, 0, 1 );
+    if let Some(s) = children {
+        adj_seq( &s, 0, 1 )?;
         s.insert( 0, new_node );
     } else {
         if dest_store.n_items() >= (dest_sno.get_seq() as u32) {
@@ -92,11 +102,171 @@ pub fn add_child(dest_sno   : &ScenarioNodeObject,
             list_model.items_changed(dest_sno.get_seq() as u32, 1, 1);
         }
     }
+    Ok(())
 }
 // remove_node /////////////////////////////////////////////
 pub fn remove_node(dest_store: &gio::ListStore,
-                   dest_sno  : &ScenarioNodeObject){
-    adj_seq(dest_store, dest_sno.get_seq() + 1, -1);
+                   dest_sno  : &ScenarioNodeObject) -> TreeResult<()>{
+    adj_seq(dest_store, dest_sno.get_seq() + 1, -1)?; // checks the store first
     dest_sno.get_node().remove();
     dest_store.remove( dest_sno.get_seq() as u32 );
+    Ok(())
+}
+
+// Path based manipulation /////////////////////////////////
+// the node tree is changed first, then the ListStores in `tlm` follow it.
+// a ListStore of a collapsed (or not yet created) row does not exist,
+// in that case my_creator builds it from the node tree later.
+// the rows which need to be re-created are returned (Touched), the caller refreshes them once
+// with refresh_rows() after a whole step, e.g. an undo of a transaction.
+
+// root_store //////////////////////////////////////////////
+pub fn root_store(tlm: &TreeListModel) -> gio::ListStore {
+    tlm.model().downcast::<gio::ListStore>().expect("ListStore")
+}
+// first_root //////////////////////////////////////////////
+pub fn first_root(tlm: &TreeListModel) -> Option<Rc<ScenarioNode>> {
+    root_store(tlm).item(0).and_downcast::<ScenarioNodeObject>().map(|s| s.get_node())
+}
+// row_at_path /////////////////////////////////////////////
+pub fn row_at_path(tlm: &TreeListModel, path: &[usize]) -> Option<TreeListRow> {
+    let (first, rest)= path.split_first()?;
+    let mut row= tlm.child_row(*first as u32)?;
+    for i in rest {
+        row= row.child_row(*i as u32)?;
+    }
+    Some(row)
+}
+// store_at_path ///////////////////////////////////////////
+/// the ListStore which holds the node at `path`
+pub fn store_at_path(tlm: &TreeListModel, path: &[usize]) -> Option<gio::ListStore> {
+    let (_, parent_path)= path.split_last()?;
+    if parent_path.is_empty() {
+        return Some(root_store(tlm)); }
+    row_at_path(tlm, parent_path)?
+        .children()?
+        .downcast::<gio::ListStore>().ok()
+}
+// Touched /////////////////////////////////////////////////
+/// the nodes whose rows are re-created after a path based manipulation, by refresh_rows().
+/// a row keeps whether it is expandable until it is re-created
+pub type Touched = Vec<Rc<ScenarioNode>>;
+
+// refresh_row /////////////////////////////////////////////
+/// re-create the row at `path`, e.g. when its children are changed
+/// while the row has no children model
+pub fn refresh_row(tlm: &TreeListModel, path: &[usize]) {
+    if let (Some(store), Some(idx)) = (store_at_path(tlm, path), path.last()) {
+        if (*idx as u32) < store.n_items() {
+            store.items_changed(*idx as u32, 1, 1);
+        }
+    }
+}
+// refresh_rows ////////////////////////////////////////////
+/// re-create the row of each touched node once, the nodes which are not in the tree any more are skipped
+pub fn refresh_rows(tlm: &TreeListModel, touched: &[Rc<ScenarioNode>]) {
+    let root= first_root(tlm);
+    for (i, n) in touched.iter().enumerate() {
+        if touched[..i].iter().any(|m| Rc::ptr_eq(m, n)) {
+            continue; }
+        let path= n.index_path();
+        let in_tree= root.clone().and_then(|r| ScenarioNode::node_at_path(r, &path)).is_some_and(|m| Rc::ptr_eq(&m, n));
+        if in_tree {
+            refresh_row(tlm, &path); }
+    }
+}
+// refresh_emptied /////////////////////////////////////////
+/// re-create the row of `parent` when `store`, the ListStore of its children, became empty.
+/// otherwise the row keeps an expander to the empty store
+pub fn refresh_emptied(tlm: &TreeListModel, store: &gio::ListStore, parent: Option<Rc<ScenarioNode>>) {
+    if let Some(p) = parent {
+        if store.n_items() == 0 {
+            refresh_row(tlm, &p.index_path()); }
+    }
+}
+// insert_node_at //////////////////////////////////////////
+/// insert the detached `node` so that its index path becomes `path`.
+/// the parent is touched when the row of its children is not created
+pub fn insert_node_at(tlm: &TreeListModel, path: &[usize], node: Rc<ScenarioNode>) -> TreeResult<Touched> {
+    let store= store_at_path(tlm, path);
+    if let Some(s) = &store { // before any change
+        check_store(s)?; }
+    if !ScenarioNode::insert_at_path(first_root(tlm), path, node.clone()) {
+        return Err(TreeError::NotInserted(path.to_vec())); }
+
+    let idx= *path.last().expect("path is not empty") as i32;
+    match store {
+        Some(store) => {
+            let sno= ScenarioNodeObject::new_from(node);
+            sno.set_seq(idx);
+            adj_seq(&store, idx, 1)?;
+            store.insert(idx as u32, &sno);
+            Ok( Touched::new() )
+        },
+        None => Ok( node.get_parent_node().into_iter().collect() ),
+    }
+}
+// remove_node_at //////////////////////////////////////////
+/// remove the node at `path` and return it detached.
+/// the parent is touched when it has no children left
+pub fn remove_node_at(tlm: &TreeListModel, path: &[usize]) -> TreeResult<(Rc<ScenarioNode>, Touched)> {
+    let node  = first_root(tlm).and_then(|r| ScenarioNode::node_at_path(r, path))
+        .ok_or_else(|| TreeError::NoNode(path.to_vec()))?;
+    let store = store_at_path(tlm, path);
+    if let Some(s) = &store { // before any change
+        check_store(s)?; }
+    let parent= node.get_parent_node();
+
+    node.remove();
+    if let Some(store) = store {
+        let idx= *path.last().expect("path is not empty") as i32;
+        let sno= store.item(idx as u32).and_downcast::<ScenarioNodeObject>();
+        if sno.is_some_and(|s| Rc::ptr_eq(&s.get_node(), &node)) {
+            adj_seq(&store, idx + 1, -1)?;
+            store.remove(idx as u32);
+        }
+    }
+    Ok( (node, parent.into_iter().filter(|p| p.get_child_count() == 0).collect()) )
+}
+// move_node_at ////////////////////////////////////////////
+/// move the node at `from` to `to`, `to` is the path after the move
+pub fn move_node_at(tlm: &TreeListModel, from: &[usize], to: &[usize]) -> TreeResult<Touched> {
+    let (node, mut touched)= remove_node_at(tlm, from)?;
+    match insert_node_at(tlm, to, node.clone()) {
+        Ok(t) => {
+            touched.extend(t);
+            Ok(touched)
+        },
+        Err(_) => {
+            insert_node_at(tlm, from, node)?; // restore, the parent has a child again
+            Err(TreeError::NotMoved(from.to_vec(), to.to_vec()))
+        },
+    }
+}
+// set_value_at ////////////////////////////////////////////
+/// the node is touched, its row shows the value
+pub fn set_value_at(tlm: &TreeListModel, path: &[usize], v: Item) -> TreeResult<Touched> {
+    let node= first_root(tlm).and_then(|r| ScenarioNode::node_at_path(r, path))
+        .ok_or_else(|| TreeError::NoNode(path.to_vec()))?;
+    node.set_value(v);
+    Ok( vec![node] )
+}
+// selected_node ///////////////////////////////////////////
+pub fn selected_node(selection: &SingleSelection) -> Option<Rc<ScenarioNode>> {
+    selection.selected_item()
+        .and_downcast::<TreeListRow>()?
+        .item()
+        .and_downcast::<ScenarioNodeObject>()
+        .map(|s| s.get_node())
+}
+// select_node /////////////////////////////////////////////
+/// select the row of `node`, its ancestors are expanded
+pub fn select_node(tlm: &TreeListModel, selection: &SingleSelection, node: &ScenarioNode) {
+    let path= node.index_path();
+    for i in 1..path.len() {
+        if let Some(row) = row_at_path(tlm, &path[..i]) {
+            row.set_expanded(true); }
+    }
+    if let Some(row) = row_at_path(tlm, &path) {
+        selection.set_selected( row.position() ); }
 }
@@ -0,0 +1,279 @@
+//! addressing of nodes
+//!
+//! - index path: `[2, 0]` is the first child of the third root node
+//! - label path: lbl of the labeled ancestors and the node itself, e.g. `["intro", "m1"]`
+//! - selector  : `scene[lbl=intro]/page[1]/mat[2]`,
+//!   the third mat of the second page of the scene labeled "intro"
+//!
+//! selector syntax
+//!   selector  := ['/'] step ('/' step)*
+//!   step      := kind ('[' predicate ']')*
+//!   kind      := group | scene | page | mat | ovimg | pmat | *
+//!   predicate := lbl=VALUE | lblref=VALUE | id=N | N
+//!   the first step matches any node, or only the root level nodes with the leading '/'.
+//!   the following steps match the children of the previous matches.
+//!   N (0 origin) is the position among the siblings which passed the preceding filters.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::scenario_node::{ScenarioNode, ItemKind};
+
+// Children ////////////////////////////////////////////////
+/// a node and its neighbors
+pub struct Children {
+    next: Option<Rc<ScenarioNode>>,
+}
+impl Iterator for Children {
+    type Item = Rc<ScenarioNode>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let n= self.next.take()?;
+        self.next= n.get_neighbor();
+        Some(n)
+    }
+}
+pub fn children(node: &ScenarioNode) -> Children {
+    Children{ next: node.get_child() }
+}
+/// the root level nodes
+pub fn roots(first_root: Option<Rc<ScenarioNode>>) -> Children {
+    Children{ next: first_root }
+}
+// PreOrder ////////////////////////////////////////////////
+/// pre-order walk without recursion
+pub struct PreOrder {
+    stack: Vec<Rc<ScenarioNode>>,
+    top  : Option<Rc<ScenarioNode>>, // the neighbors of `top` are not visited
+}
+impl Iterator for PreOrder {
+    type Item = Rc<ScenarioNode>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let n= self.stack.pop()?;
+        let is_top= self.top.as_ref().is_some_and(|t| Rc::ptr_eq(t, &n));
+        if !is_top {
+            if let Some(nb) = n.get_neighbor() {
+                self.stack.push(nb); }
+        }
+        if let Some(c) = n.get_child() {
+            self.stack.push(c); }
+        Some(n)
+    }
+}
+/// `node` and its descendants
+pub fn pre_order(node: Rc<ScenarioNode>) -> PreOrder {
+    PreOrder{ stack: vec![node.clone()], top: Some(node) }
+}
+/// every node of the forest which starts with `first_root`
+pub fn pre_order_forest(first_root: Option<Rc<ScenarioNode>>) -> PreOrder {
+    PreOrder{ stack: first_root.into_iter().collect(), top: None }
+}
+// PreOrderDepth ///////////////////////////////////////////
+/// pre-order walk of a forest with the depth of each node (0 at the root level), without index_path()
+pub struct PreOrderDepth {
+    stack: Vec<(Rc<ScenarioNode>, usize)>,
+}
+impl Iterator for PreOrderDepth {
+    type Item = (Rc<ScenarioNode>, usize);
+    fn next(&mut self) -> Option<Self::Item> {
+        let (n, depth)= self.stack.pop()?;
+        if let Some(nb) = n.get_neighbor() {
+            self.stack.push( (nb, depth) ); }
+        if let Some(c) = n.get_child() {
+            self.stack.push( (c, depth + 1) ); }
+        Some( (n, depth) )
+    }
+}
+pub fn pre_order_forest_depth(first_root: Option<Rc<ScenarioNode>>) -> PreOrderDepth {
+    PreOrderDepth{ stack: first_root.map(|r| (r, 0)).into_iter().collect() }
+}
+// Ancestors ///////////////////////////////////////////////
+/// the parent, the grand parent, ... (not including the node itself)
+pub struct Ancestors {
+    next: Option<Rc<ScenarioNode>>,
+}
+impl Iterator for Ancestors {
+    type Item = Rc<ScenarioNode>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let n= self.next.take()?;
+        self.next= n.get_parent_node();
+        Some(n)
+    }
+}
+pub fn ancestors(node: &ScenarioNode) -> Ancestors {
+    Ancestors{ next: node.get_parent_node() }
+}
+
+// index path //////////////////////////////////////////////
+/// "2/0/1" -> [2, 0, 1]
+pub fn parse_index_path(s: &str) -> Option<Vec<usize>> {
+    s.trim().trim_matches('/').split('/').map(|i| i.trim().parse::<usize>().ok()).collect()
+}
+pub fn node_at_index_path(first_root: Option<Rc<ScenarioNode>>, path: &[usize]) -> Option<Rc<ScenarioNode>> {
+    ScenarioNode::node_at_path(first_root?, path)
+}
+// label path //////////////////////////////////////////////
+pub fn label_path(node: &Rc<ScenarioNode>) -> Vec<String> {
+    let mut path: Vec<String>= std::iter::once(node.clone())
+        .chain(ancestors(node))
+        .filter_map(|n| n.value.borrow().lbl())
+        .collect();
+    path.reverse();
+    path
+}
+/// the nodes whose label path is `labels`
+pub fn nodes_at_label_path(first_root: Option<Rc<ScenarioNode>>, labels: &[&str]) -> Vec<Rc<ScenarioNode>> {
+    pre_order_forest(first_root)
+        .filter(|n| n.value.borrow().lbl().is_some())
+        .filter(|n| label_path(n).iter().map(|l| l.as_str()).eq(labels.iter().copied()))
+        .collect()
+}
+
+// QueryError //////////////////////////////////////////////
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryError {
+    pub pos: usize, // byte offset in the selector
+    pub msg: String,
+}
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {}", self.msg, self.pos)
+    }
+}
+// Predicate / Step / Selector /////////////////////////////
+#[derive(Debug, Clone, PartialEq)]
+enum Predicate {
+    Lbl(String),
+    LblRef(String),
+    Id(i32),
+    Index(usize),
+}
+#[derive(Debug, Clone, PartialEq)]
+struct Step {
+    kind : Option<ItemKind>, // None: '*'
+    preds: Vec<Predicate>,
+}
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selector {
+    anchored: bool, // leading '/'
+    steps   : Vec<Step>,
+}
+// parse_predicate /////////////////////////////////////////
+fn parse_predicate(s: &str, pos: usize) -> Result<Predicate, QueryError> {
+    let s= s.trim();
+    if let Ok(i) = s.parse::<usize>() {
+        return Ok(Predicate::Index(i)); }
+    let (key, value)= s.split_once('=')
+        .ok_or(QueryError{ pos, msg: format!("'=' or an index is expected in [{}]", s) })?;
+    let value= value.trim().trim_matches('"').to_string();
+    match key.trim() {
+        "lbl"    => Ok(Predicate::Lbl(value)),
+        "lblref" => Ok(Predicate::LblRef(value)),
+        "id"     => value.parse::<i32>()
+            .map(Predicate::Id)
+            .map_err(|_| QueryError{ pos, msg: format!("id must be a number: {}", value) }),
+        k => Err(QueryError{ pos, msg: format!("unknown key: {}", k) }),
+    }
+}
+// parse_step //////////////////////////////////////////////
+fn parse_step(s: &str, pos: usize) -> Result<Step, QueryError> {
+    let name_end= s.find('[').unwrap_or(s.len());
+    let name= s[..name_end].trim();
+    let kind= match name {
+        ""  => return Err(QueryError{ pos, msg: String::from("empty step") }),
+        "*" => None,
+        n   => Some( ItemKind::from_name(n)
+                     .ok_or(QueryError{ pos, msg: format!("unknown kind: {}", n) })? ),
+    };
+    let mut preds= Vec::new();
+    let mut rest = &s[name_end..];
+    let mut p    = pos + name_end;
+    while !rest.is_empty() {
+        if !rest.starts_with('[') {
+            return Err(QueryError{ pos: p, msg: String::from("'[' is expected") }); }
+        let close= rest.find(']')
+            .ok_or(QueryError{ pos: p, msg: String::from("']' is missing") })?;
+        preds.push( parse_predicate(&rest[1..close], p + 1)? );
+        p   += close + 1;
+        rest = &rest[close + 1..];
+    }
+    Ok( Step{ kind, preds } )
+}
+// split_steps /////////////////////////////////////////////
+/// split by '/' outside of [...], with the byte offset of each step
+fn split_steps(s: &str) -> Vec<(usize, &str)> {
+    let mut steps= Vec::new();
+    let mut depth= 0;
+    let mut start= 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '[' => depth+= 1,
+            ']' => depth-= 1,
+            '/' if depth == 0 => {
+                steps.push( (start, &s[start..i]) );
+                start= i + 1;
+            },
+            _ => (),
+        }
+    }
+    steps.push( (start, &s[start..]) );
+    steps
+}
+impl Selector {
+    // parse ///////////////////////////////////////////////
+    pub fn parse(s: &str) -> Result<Selector, QueryError> {
+        let trimmed= s.trim_start();
+        let offset = s.len() - trimmed.len();
+        let anchored= trimmed.starts_with('/');
+        let body= if anchored { &trimmed[1..] } else { trimmed };
+        let body_offset= offset + if anchored { 1 } else { 0 };
+
+        let steps= split_steps(body).into_iter()
+            .map(|(p, st)| parse_step(st.trim_end(), body_offset + p))
+            .collect::<Result<Vec<Step>, QueryError>>()?;
+        Ok( Selector{ anchored, steps } )
+    }
+    // select //////////////////////////////////////////////
+    /// the matched nodes in pre-order
+    pub fn select(&self, first_root: Option<Rc<ScenarioNode>>) -> Vec<Rc<ScenarioNode>> {
+        let mut matched= Vec::new();
+        for (i, step) in self.steps.iter().enumerate() {
+            let groups: Vec<Vec<Rc<ScenarioNode>>>= if i > 0 {
+                matched.iter().map(|m: &Rc<ScenarioNode>| children(m).collect()).collect()
+            } else if self.anchored {
+                vec![ roots(first_root.clone()).collect() ]
+            } else {
+                std::iter::once( roots(first_root.clone()).collect() )
+                    .chain( pre_order_forest(first_root.clone()).map(|n| children(&n).collect()) )
+                    .collect()
+            };
+            matched= groups.into_iter().flat_map(|g| step.filter(g)).collect();
+        }
+        // pre-order and no duplication
+        let set: HashSet<*const ScenarioNode>= matched.iter().map(Rc::as_ptr).collect();
+        pre_order_forest(first_root).filter(|n| set.contains(&Rc::as_ptr(n))).collect()
+    }
+}
+impl Step {
+    /// filter the siblings by the kind and the predicates in order
+    fn filter(&self, siblings: Vec<Rc<ScenarioNode>>) -> Vec<Rc<ScenarioNode>> {
+        let mut v: Vec<Rc<ScenarioNode>>= siblings.into_iter()
+            .filter(|n| self.kind.is_none_or(|k| n.value.borrow().kind() == k))
+            .collect();
+        for p in self.preds.iter() {
+            v= match p {
+                Predicate::Index(i) => v.into_iter().nth(*i).into_iter().collect(),
+                Predicate::Id(id)   => v.into_iter().filter(|n| n.id.get() == *id).collect(),
+                Predicate::Lbl(l)   => v.into_iter()
+                    .filter(|n| n.value.borrow().lbl().as_deref() == Some(l.as_str())).collect(),
+                Predicate::LblRef(l)=> v.into_iter()
+                    .filter(|n| n.value.borrow().lblref().as_deref() == Some(l.as_str())).collect(),
+            };
+        }
+        v
+    }
+}
+// query ///////////////////////////////////////////////////
+pub fn query(first_root: Option<Rc<ScenarioNode>>, selector: &str) -> Result<Vec<Rc<ScenarioNode>>, QueryError> {
+    Ok( Selector::parse(selector)?.select(first_root) )
+}
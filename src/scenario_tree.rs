@@ -0,0 +1,232 @@
+//! links of the ScenarioNode tree, kept in an arena
+//!
+//! each ScenarioNode has a NodeHandle (slot index + generation) of its slot,
+//! a slot holds the parent, the previous/next siblings and the first/last
+//! child of the node, so that every link operation is O(1).
+//! a node owns its children: the slot of a node which has a parent holds the
+//! Rc of the node. the root level nodes are owned by their holders
+//! (e.g. the root ListStore) and are only linked as siblings.
+//!
+//! the arena is borrowed only inside this module and an Rc<ScenarioNode> is
+//! never dropped while it is borrowed (Drop of ScenarioNode uses the arena),
+//! the detached Rcs are returned to the caller instead.
+
+use std::cell::RefCell;
+use std::rc::{Rc, Weak};
+
+use crate::scenario_node::ScenarioNode;
+
+// NodeHandle //////////////////////////////////////////////
+/// a handle to a slot, stale after the slot is freed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeHandle {
+    index     : u32,
+    generation: u32,
+}
+// Slot ////////////////////////////////////////////////////
+#[derive(Default)]
+struct Slot {
+    generation : u32,
+    used       : bool,
+    node       : Weak<ScenarioNode>,
+    held       : Option<Rc<ScenarioNode>>, // Some while the node has a parent
+    parent     : Option<NodeHandle>,
+    prev       : Option<NodeHandle>,
+    next       : Option<NodeHandle>,
+    first_child: Option<NodeHandle>,
+    last_child : Option<NodeHandle>,
+    child_count: usize,
+}
+// Arena ///////////////////////////////////////////////////
+#[derive(Default)]
+pub struct Arena {
+    slots: Vec<Slot>,
+    free : Vec<u32>,
+}
+
+thread_local! {
+    static ARENA: RefCell<Arena> = RefCell::new(Arena::default());
+}
+/// run `f` with the arena of this thread
+pub fn with<R, F: FnOnce(&mut Arena) -> R>(f: F) -> R {
+    ARENA.with(|a| f(&mut a.borrow_mut()))
+}
+/// same as with(), but does nothing after the arena is destroyed (at the thread exit)
+pub fn try_with<R, F: FnOnce(&mut Arena) -> R>(f: F) -> Option<R> {
+    ARENA.try_with(|a| f(&mut a.borrow_mut())).ok()
+}
+
+impl Arena {
+    // slot access /////////////////////////////////////////
+    fn slot(&self, h: NodeHandle) -> Option<&Slot> {
+        self.slots.get(h.index as usize)
+            .filter(|s| s.used && (s.generation == h.generation))
+    }
+    fn slot_mut(&mut self, h: NodeHandle) -> Option<&mut Slot> {
+        self.slots.get_mut(h.index as usize)
+            .filter(|s| s.used && (s.generation == h.generation))
+    }
+    // alloc_node //////////////////////////////////////////
+    pub fn alloc_node(&mut self, node: Weak<ScenarioNode>) -> NodeHandle {
+        let index= match self.free.pop() {
+            Some(i) => i,
+            None    => {
+                self.slots.push( Slot::default() );
+                (self.slots.len() - 1) as u32
+            },
+        };
+        let s= &mut self.slots[index as usize];
+        s.used= true;
+        s.node= node;
+        NodeHandle{ index, generation: s.generation }
+    }
+    fn free_slot(&mut self, h: NodeHandle) {
+        if let Some(s) = self.slot_mut(h) {
+            let generation= s.generation.wrapping_add(1);
+            *s= Slot{ generation, ..Slot::default() };
+            self.free.push(h.index);
+        }
+    }
+    // queries /////////////////////////////////////////////
+    pub fn node(&self, h: NodeHandle) -> Option<Rc<ScenarioNode>> {
+        self.slot(h)?.node.upgrade()
+    }
+    /// the parent node, None at the root level
+    pub fn parent(&self, h: NodeHandle) -> Option<NodeHandle> {
+        self.slot(h)?.parent
+    }
+    pub fn prev(&self, h: NodeHandle) -> Option<NodeHandle> {
+        self.slot(h)?.prev
+    }
+    pub fn next(&self, h: NodeHandle) -> Option<NodeHandle> {
+        self.slot(h)?.next
+    }
+    pub fn first_child(&self, h: NodeHandle) -> Option<NodeHandle> {
+        self.slot(h)?.first_child
+    }
+    pub fn child_count(&self, h: NodeHandle) -> usize {
+        self.slot(h).map_or(0, |s| s.child_count)
+    }
+    /// the index in the siblings, O(index)
+    pub fn position(&self, h: NodeHandle) -> usize {
+        let mut i= 0;
+        let mut p= self.prev(h);
+        while let Some(x) = p {
+            i+= 1;
+            p= self.prev(x);
+        }
+        i
+    }
+    /// the first of the siblings
+    pub fn first_sibling(&self, h: NodeHandle) -> NodeHandle {
+        if let Some(f) = self.parent(h).and_then(|p| self.first_child(p)) {
+            return f; }
+        let mut f= h;
+        while let Some(p) = self.prev(f) {
+            f= p;
+        }
+        f
+    }
+    /// the sibling `n` ahead
+    pub fn nth_next(&self, h: NodeHandle, n: usize) -> Option<NodeHandle> {
+        let mut x= h;
+        for _ in 0..n {
+            x= self.next(x)?;
+        }
+        Some(x)
+    }
+    pub fn child_at(&self, h: NodeHandle, i: usize) -> Option<NodeHandle> {
+        self.nth_next(self.first_child(h)?, i)
+    }
+    pub fn children(&self, h: NodeHandle) -> Vec<NodeHandle> {
+        let mut v= Vec::with_capacity(self.child_count(h));
+        let mut c= self.first_child(h);
+        while let Some(x) = c {
+            v.push(x);
+            c= self.next(x);
+        }
+        v
+    }
+    /// true if `h` is `ancestor` or one of its descendants
+    pub fn is_within(&self, h: NodeHandle, ancestor: NodeHandle) -> bool {
+        if self.first_child(ancestor).is_none() { // a leaf, no need to walk up
+            return h == ancestor; }
+        let mut n= Some(h);
+        while let Some(x) = n {
+            if x == ancestor {
+                return true; }
+            n= self.parent(x);
+        }
+        false
+    }
+    // detach //////////////////////////////////////////////
+    /// unlink `h` from its parent and siblings, `h` becomes a lone root.
+    /// returns the Rc which was held for the parent, drop it after the arena is released
+    pub fn detach(&mut self, h: NodeHandle) -> Option<Rc<ScenarioNode>> {
+        let (parent, prev, next, held)= {
+            let s= self.slot_mut(h)?;
+            (s.parent.take(), s.prev.take(), s.next.take(), s.held.take())
+        };
+        if let Some(p) = prev.and_then(|p| self.slot_mut(p)) {
+            p.next= next; }
+        if let Some(n) = next.and_then(|n| self.slot_mut(n)) {
+            n.prev= prev; }
+        if let Some(ps) = parent.and_then(|p| self.slot_mut(p)) {
+            if ps.first_child == Some(h) { ps.first_child= next; }
+            if ps.last_child  == Some(h) { ps.last_child = prev; }
+            ps.child_count-= 1;
+        }
+        held
+    }
+    // link ////////////////////////////////////////////////
+    /// link the lone root `h` between `prev` and `next` under `parent`
+    fn link(&mut self, h: NodeHandle, parent: Option<NodeHandle>,
+            prev: Option<NodeHandle>, next: Option<NodeHandle>) {
+        let held= parent.and_then(|_| self.node(h));
+        if let Some(s) = self.slot_mut(h) {
+            s.parent= parent;
+            s.prev  = prev;
+            s.next  = next;
+            s.held  = held;
+        }
+        if let Some(p) = prev.and_then(|p| self.slot_mut(p)) {
+            p.next= Some(h); }
+        if let Some(n) = next.and_then(|n| self.slot_mut(n)) {
+            n.prev= Some(h); }
+        if let Some(ps) = parent.and_then(|p| self.slot_mut(p)) {
+            if prev.is_none() { ps.first_child= Some(h); }
+            if next.is_none() { ps.last_child = Some(h); }
+            ps.child_count+= 1;
+        }
+    }
+    // insert //////////////////////////////////////////////
+    /// link the lone root `b` before `a`
+    pub fn insert_before(&mut self, a: NodeHandle, b: NodeHandle) {
+        self.link(b, self.parent(a), self.prev(a), Some(a));
+    }
+    /// link the lone root `b` after `a`
+    pub fn insert_after(&mut self, a: NodeHandle, b: NodeHandle) {
+        self.link(b, self.parent(a), Some(a), self.next(a));
+    }
+    /// link the lone root `b` as the first child of `a`
+    pub fn insert_first_child(&mut self, a: NodeHandle, b: NodeHandle) {
+        self.link(b, Some(a), None, self.first_child(a));
+    }
+    // release /////////////////////////////////////////////
+    /// free the slot of a dropped node.
+    /// returns the Rcs of its children, they are lone roots now
+    pub fn release(&mut self, h: NodeHandle) -> Vec<Rc<ScenarioNode>> {
+        let held    = self.detach(h);
+        let children= self.take_children(h);
+        self.free_slot(h);
+        children.into_iter().chain(held).collect()
+    }
+    /// unlink every child of `h`
+    pub fn take_children(&mut self, h: NodeHandle) -> Vec<Rc<ScenarioNode>> {
+        let mut owners= Vec::with_capacity(self.child_count(h));
+        while let Some(c) = self.first_child(h) {
+            owners.extend( self.detach(c) );
+        }
+        owners
+    }
+}
@@ -0,0 +1,265 @@
+//! the XML form of a scenario file (the same records as scenario_file.rs)
+//!
+//!   <scenario version="1" next_id="12">
+//!     <group id="1">
+//!       <scene id="2" bgimg="bg/a.png" bgcol="#000000" crop="0,0,50%,100%" lbl="a">
+//!         <page id="3">
+//!           <mat id="4" col="#202040" x="40" y="80%" w="1200" h="120" r="8" a="200" name="m1" v="true">
+//!             <txt>text body</txt>
+//!           </mat>
+//!           <ovimg id="5" path="chara/b.png" x="10%" y="0" a="255"/>
+//!
+//! an element is named by ItemKind::name() and its children are the child nodes.
+//! a missing attribute is the default (0, #000000, a= 255, none),
+//! so a file can be written by hand.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Write};
+use std::path::Path;
+use xml::common::Position as _;
+use xml::reader::{EventReader, XmlEvent};
+use xml::writer::{EmitterConfig, EventWriter, XmlEvent as WriterEvent};
+
+use crate::scenario_node::{Item, ItemKind, Scene, Mat, Ovimg, CropInfo, Position, Dimension,
+                           Color, Length};
+use crate::scenario_file::{NodeRecord, ScenarioFile, SCENARIO_FILE_VERSION};
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+// attributes //////////////////////////////////////////////
+/// the attributes of `v` in the order of the fields
+pub fn attributes(v: &Item) -> Vec<(&'static str, String)> {
+    let mut a= Vec::new();
+    let opt= |a: &mut Vec<(&'static str, String)>, k, s: &Option<String>| {
+        if let Some(s) = s { a.push((k, s.clone())); }
+    };
+    match v {
+        Item::Scene(c) => {
+            opt(&mut a, "bgimg", &c.bgimg);
+            a.push(("bgcol", c.bgcol.to_string()));
+            if let Some(cr) = &c.crop {
+                a.push(("crop", format!("{},{},{},{}", cr.pos.x, cr.pos.y, cr.dim.w, cr.dim.h))); }
+            opt(&mut a, "lbl",    &c.lbl);
+            opt(&mut a, "lblref", &c.lblref);
+        }
+        Item::Mat(m) | Item::Pmat(m) => {
+            a.push(("col", m.col.to_string()));
+            a.push(("x",   m.pos.x.to_string()));
+            a.push(("y",   m.pos.y.to_string()));
+            a.push(("w",   m.dim.w.to_string()));
+            a.push(("h",   m.dim.h.to_string()));
+            a.push(("r",   m.r.to_string()));
+            a.push(("a",   m.a.to_string()));
+            opt(&mut a, "src",    &m.src);
+            opt(&mut a, "lbl",    &m.lbl);
+            opt(&mut a, "lblref", &m.lblref);
+            a.push(("name", m.name.clone()));
+            if m.v {
+                a.push(("v", String::from("true"))); }
+        }
+        Item::Ovimg(o) => {
+            a.push(("path", o.path.clone()));
+            a.push(("x",    o.pos.x.to_string()));
+            a.push(("y",    o.pos.y.to_string()));
+            a.push(("a",    o.a.to_string()));
+        }
+        Item::Group | Item::Page => (),
+    }
+    a
+}
+// Attrs ///////////////////////////////////////////////////
+/// the attributes of an element being read
+struct Attrs(HashMap<String, String>);
+impl Attrs {
+    fn opt(&self, k: &str) -> Option<String> {
+        self.0.get(k).cloned()
+    }
+    fn text(&self, k: &str) -> String {
+        self.opt(k).unwrap_or_default()
+    }
+    fn parse<T: std::str::FromStr>(&self, k: &str, default: T) -> Result<T, String> {
+        match self.0.get(k) {
+            Some(s) => s.trim().parse().map_err(|_| format!("bad {}: {}", k, s)),
+            None    => Ok(default),
+        }
+    }
+    fn length(&self, k: &str) -> Result<Length, String> {
+        match self.0.get(k) {
+            Some(s) => Length::parse(s).map_err(|e| format!("{}: {}", k, e)),
+            None    => Ok(Length::default()),
+        }
+    }
+    fn color(&self, k: &str) -> Result<Color, String> {
+        match self.0.get(k) {
+            Some(s) => Color::parse(s).map_err(|e| format!("{}: {}", k, e)),
+            None    => Ok(Color::BLACK),
+        }
+    }
+    fn crop(&self) -> Result<Option<CropInfo>, String> {
+        let Some(s) = self.0.get("crop") else { return Ok(None) };
+        let l: Vec<Length>= s.split(',').map(Length::parse).collect::<Result<_, _>>()
+            .map_err(|e| format!("crop: {}", e))?;
+        let [x, y, w, h] = l[..] else { return Err(format!("crop: x,y,w,h is expected: {}", s)) };
+        Ok( Some(CropInfo{ pos: Position{ x, y }, dim: Dimension{ w, h } }) )
+    }
+    // item ////////////////////////////////////////////////
+    fn item(&self, kind: ItemKind) -> Result<Item, String> {
+        let mat= || -> Result<Mat, String> { Ok( Mat{
+            col   : self.color("col")?,
+            pos   : Position{ x: self.length("x")?, y: self.length("y")? },
+            dim   : Dimension{ w: self.length("w")?, h: self.length("h")? },
+            r     : self.parse("r", 0)?,
+            a     : self.parse("a", 255)?,
+            src   : self.opt("src"),
+            lbl   : self.opt("lbl"),
+            lblref: self.opt("lblref"),
+            name  : self.text("name"),
+            txt   : String::new(), // <txt>
+            v     : self.parse("v", false)?,
+        } ) };
+        Ok( match kind {
+            ItemKind::Group => Item::Group,
+            ItemKind::Scene => Item::Scene( Scene{
+                bgimg : self.opt("bgimg"),
+                bgcol : self.color("bgcol")?,
+                crop  : self.crop()?,
+                lbl   : self.opt("lbl"),
+                lblref: self.opt("lblref"),
+            } ),
+            ItemKind::Page  => Item::Page,
+            ItemKind::Mat   => Item::Mat( mat()? ),
+            ItemKind::Ovimg => Item::Ovimg( Ovimg{
+                path: self.text("path"),
+                pos : Position{ x: self.length("x")?, y: self.length("y")? },
+                a   : self.parse("a", 255)?,
+            } ),
+            ItemKind::Pmat  => Item::Pmat( mat()? ),
+        } )
+    }
+}
+/// the item of `kind` from its attributes, the inverse of attributes()
+pub fn item_from_attributes(kind: ItemKind, attrs: HashMap<String, String>) -> Result<Item, String> {
+    Attrs(attrs).item(kind)
+}
+// write ///////////////////////////////////////////////////
+/// the start element of `r` with its txt, the children follow
+fn write_start<W: Write>(w: &mut EventWriter<W>, r: &NodeRecord) -> xml::writer::Result<()> {
+    let id   = r.id.to_string();
+    let attrs= attributes(&r.value);
+    let mut e= WriterEvent::start_element(r.value.kind().name()).attr("id", &id);
+    for (k, v) in attrs.iter() {
+        e= e.attr(*k, v); }
+    w.write(e)?;
+    if let Item::Mat(m) | Item::Pmat(m) = &r.value {
+        if !m.txt.is_empty() {
+            w.write(WriterEvent::start_element("txt"))?;
+            w.write(WriterEvent::characters(&m.txt))?;
+            w.write(WriterEvent::end_element())?;
+        }
+    }
+    Ok(())
+}
+/// `r` and its descendants, by a loop as read()
+fn write_record<W: Write>(w: &mut EventWriter<W>, r: &NodeRecord) -> xml::writer::Result<()> {
+    write_start(w, r)?;
+    let mut stack= vec![ (r, 0) ]; // (an open record, the index of its next child)
+    while let Some(top) = stack.last_mut() {
+        let (r, i)= *top;
+        match r.children.get(i) {
+            Some(c) => {
+                top.1+= 1;
+                write_start(w, c)?;
+                stack.push( (c, 0) );
+            },
+            None    => {
+                w.write(WriterEvent::end_element())?;
+                stack.pop();
+            },
+        }
+    }
+    Ok(())
+}
+pub fn write<W: Write>(out: W, file: &ScenarioFile) -> io::Result<()> {
+    let mut w= EmitterConfig::new().perform_indent(true).create_writer(out);
+    let version= file.version.to_string();
+    let next_id= file.next_id.to_string();
+    let xml= |e: xml::writer::Error| io::Error::other(e.to_string());
+    w.write(WriterEvent::start_element("scenario").attr("version", &version).attr("next_id", &next_id))
+        .map_err(xml)?;
+    for r in file.nodes.iter() {
+        write_record(&mut w, r).map_err(xml)?; }
+    w.write(WriterEvent::end_element()).map_err(xml)?;
+    w.into_inner().write_all(b"\n")
+}
+// read ////////////////////////////////////////////////////
+pub fn read<R: Read>(input: R) -> io::Result<ScenarioFile> {
+    let mut reader= EventReader::new(input);
+    let mut file : Option<ScenarioFile>= None;
+    let mut stack: Vec<NodeRecord>= Vec::new(); // the open nodes
+    let mut txt  : Option<String>= None;        // in <txt>
+    loop {
+        let event= reader.next().map_err(|e| invalid(e.to_string()))?;
+        let at= |msg: String| invalid(format!("{}: {}", reader.position(), msg));
+        match event {
+            XmlEvent::StartElement{ name, attributes, .. } => {
+                let attrs= Attrs( attributes.into_iter().map(|a| (a.name.local_name, a.value)).collect() );
+                let name= name.local_name;
+                if txt.is_some() {
+                    return Err( at(format!("<{}> in <txt>", name)) ); }
+                match (&file, name.as_str()) {
+                    (None, "scenario") => {
+                        file= Some( ScenarioFile{
+                            version: attrs.parse("version", SCENARIO_FILE_VERSION).map_err(at)?,
+                            next_id: attrs.parse("next_id", 0).map_err(at)?,
+                            nodes  : Vec::new(),
+                        } );
+                    }
+                    (None, _)          => return Err( at(format!("<scenario> is expected: <{}>", name)) ),
+                    (Some(_), "txt")   => match stack.last().map(|r| r.value.kind()) {
+                        Some(ItemKind::Mat | ItemKind::Pmat) => txt= Some(String::new()),
+                        _ => return Err( at(String::from("<txt> is only in a mat or a pmat")) ),
+                    },
+                    (Some(_), _)       => {
+                        let kind= ItemKind::from_name(&name).ok_or_else(|| at(format!("unknown element <{}>", name)))?;
+                        let id  = attrs.0.get("id").and_then(|s| s.trim().parse().ok())
+                            .ok_or_else(|| at(format!("<{}> without an id", name)))?;
+                        let value= attrs.item(kind).map_err(at)?;
+                        stack.push( NodeRecord{ id, value, children: Vec::new() } );
+                    }
+                }
+            }
+            XmlEvent::Characters(s) | XmlEvent::CData(s) | XmlEvent::Whitespace(s) => {
+                if let Some(t) = txt.as_mut() {
+                    t.push_str(&s);
+                } else if !s.trim().is_empty() {
+                    return Err( at(format!("unexpected text: {}", s.trim())) );
+                }
+            }
+            XmlEvent::EndElement{ .. } => {
+                if let Some(t) = txt.take() {
+                    if let Some(NodeRecord{ value: Item::Mat(m) | Item::Pmat(m), .. }) = stack.last_mut() {
+                        m.txt= t; }
+                } else if let Some(r) = stack.pop() {
+                    match stack.last_mut() {
+                        Some(p) => p.children.push(r),
+                        None    => file.as_mut().expect("in <scenario>").nodes.push(r),
+                    }
+                }
+            }
+            XmlEvent::EndDocument => break,
+            _ => (),
+        }
+    }
+    file.ok_or_else(|| invalid(String::from("no <scenario>")))
+}
+// read_file / write_file /////////////////////////////////
+pub fn read_file(path: &Path) -> io::Result<ScenarioFile> {
+    read( BufReader::new(File::open(path)?) )
+}
+pub fn write_file(path: &Path, file: &ScenarioFile) -> io::Result<()> {
+    let mut buf= Vec::new();
+    write(&mut buf, file)?;
+    fs::write(path, buf)
+}
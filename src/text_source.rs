@@ -0,0 +1,212 @@
+//! external text of a mat (Mat.src)
+//!
+//!   story.txt           the whole file
+//!   story.txt#L10       line 10 (1 origin)
+//!   story.txt#L10-L14   lines 10 to 14
+//!   story.txt#intro     the section of the anchor "intro": the lines after a line `@intro`
+//!                       up to the next anchor line (a line starting with '@') or the end
+//!
+//! a relative path is resolved from the directory of the scenario file.
+//! SourceCache reads each file once and watches it with gio::FileMonitor,
+//! the listeners are called with the path when the file is changed.
+//!
+//! the source text is shown in the preview, `txt` keeps a copy of it:
+//! a mat is stale when its txt differs from the source, missing when the source cannot be read.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::{Rc, Weak};
+
+use gtk::gio;
+use gtk::prelude::*;
+
+use crate::scenario_node::Mat;
+
+// Selection ///////////////////////////////////////////////
+#[derive(Debug, Clone, PartialEq)]
+pub enum Selection {
+    All,
+    Lines(usize, usize), // first, last (1 origin, inclusive)
+    Anchor(String),
+}
+// SourceRef ///////////////////////////////////////////////
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceRef {
+    pub path: PathBuf,
+    pub sel : Selection,
+}
+// SourceError /////////////////////////////////////////////
+#[derive(Debug, Clone, PartialEq)]
+pub enum SourceError {
+    BadRef(String),
+    Io(PathBuf, String),
+    NoLines(usize, usize, usize), // first, last, number of lines in the file
+    NoAnchor(String),
+}
+impl fmt::Display for SourceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SourceError::BadRef(s)        => write!(f, "bad src: {}", s),
+            SourceError::Io(p, e)         => write!(f, "{}: {}", p.display(), e),
+            SourceError::NoLines(a, b, n) => write!(f, "no lines L{}-L{} ({} lines)", a, b, n),
+            SourceError::NoAnchor(a)      => write!(f, "no anchor @{}", a),
+        }
+    }
+}
+// parse ///////////////////////////////////////////////////
+/// "L10" -> 10
+fn parse_line(s: &str) -> Option<usize> {
+    s.strip_prefix('L').unwrap_or(s).parse::<usize>().ok().filter(|n| *n > 0)
+}
+impl SourceRef {
+    pub fn parse(src: &str) -> Result<SourceRef, SourceError> {
+        let bad= || SourceError::BadRef(src.to_string());
+        let (path, frag)= match src.trim().split_once('#') {
+            Some((p, f)) => (p, Some(f)),
+            None         => (src.trim(), None),
+        };
+        if path.is_empty() {
+            return Err(bad()); }
+        let sel= match frag {
+            None => Selection::All,
+            Some(f) if f.starts_with('L') && f[1..].starts_with(|c: char| c.is_ascii_digit()) => {
+                let (a, b)= match f.split_once('-') {
+                    Some((a, b)) => (parse_line(a).ok_or_else(bad)?, parse_line(b).ok_or_else(bad)?),
+                    None         => { let a= parse_line(f).ok_or_else(bad)?; (a, a) },
+                };
+                if a > b {
+                    return Err(bad()); }
+                Selection::Lines(a, b)
+            },
+            Some(f) if !f.is_empty() && !f.contains(char::is_whitespace) => Selection::Anchor(f.to_string()),
+            Some(_) => return Err(bad()),
+        };
+        Ok( SourceRef{ path: PathBuf::from(path), sel } )
+    }
+    /// the selected part of the `content` of the file
+    pub fn extract(&self, content: &str) -> Result<String, SourceError> {
+        let lines: Vec<&str>= content.lines().collect();
+        match &self.sel {
+            Selection::All => Ok( content.trim_end_matches('\n').to_string() ),
+            Selection::Lines(a, b) => {
+                if *b > lines.len() {
+                    return Err(SourceError::NoLines(*a, *b, lines.len())); }
+                Ok( lines[a - 1..*b].join("\n") )
+            },
+            Selection::Anchor(name) => {
+                let start= lines.iter()
+                    .position(|l| l.trim_end().strip_prefix('@') == Some(name.as_str()))
+                    .ok_or(SourceError::NoAnchor(name.clone()))? + 1;
+                let end= lines[start..].iter()
+                    .position(|l| l.starts_with('@'))
+                    .map_or(lines.len(), |i| start + i);
+                Ok( lines[start..end].join("\n").trim_end().to_string() )
+            },
+        }
+    }
+}
+// SourceState /////////////////////////////////////////////
+#[derive(Debug, Clone, PartialEq)]
+pub enum SourceState {
+    None,                 // no src
+    Ok,
+    Stale,                // txt differs from the source
+    Missing(SourceError),
+}
+// SourceCache /////////////////////////////////////////////
+type Listener = Box<dyn Fn(&Path)>;
+struct Entry {
+    content : Result<String, String>,
+    _monitor: Option<gio::FileMonitor>,
+}
+pub struct SourceCache {
+    base     : PathBuf,
+    entries  : RefCell<HashMap<PathBuf, Entry>>,
+    listeners: RefCell<Vec<Listener>>,
+    weak     : Weak<SourceCache>,
+}
+impl SourceCache {
+    /// relative paths are resolved from `base`
+    pub fn new(base: &Path) -> Rc<SourceCache> {
+        Rc::new_cyclic(|weak| SourceCache{
+            base     : base.to_path_buf(),
+            entries  : RefCell::new(HashMap::new()),
+            listeners: RefCell::new(Vec::new()),
+            weak     : weak.clone(),
+        })
+    }
+    pub fn resolve(&self, path: &Path) -> PathBuf {
+        self.base.join(path)
+    }
+    // connect_changed /////////////////////////////////////
+    /// `f` is called with the resolved path when a watched file is changed
+    pub fn connect_changed<F: Fn(&Path) + 'static>(&self, f: F) {
+        self.listeners.borrow_mut().push(Box::new(f));
+    }
+    fn notify_changed(&self, path: &Path) {
+        for f in self.listeners.borrow().iter() {
+            f(path);
+        }
+    }
+    // watch ///////////////////////////////////////////////
+    fn watch(&self, path: &Path) -> Option<gio::FileMonitor> {
+        let monitor= gio::File::for_path(path)
+            .monitor_file(gio::FileMonitorFlags::NONE, gio::Cancellable::NONE).ok()?;
+        let weak= self.weak.clone();
+        let path= path.to_path_buf();
+        monitor.connect_changed(move |_, _, _, event| {
+            if event == gio::FileMonitorEvent::AttributeChanged {
+                return; }
+            if let Some(cache) = weak.upgrade() {
+                cache.reload(&path);
+            }
+        });
+        Some(monitor)
+    }
+    /// read the file again and notify the listeners
+    pub fn reload(&self, path: &Path) {
+        let content= fs::read_to_string(path).map_err(|e| e.to_string());
+        if let Some(e) = self.entries.borrow_mut().get_mut(path) {
+            e.content= content; }
+        self.notify_changed(path);
+    }
+    // content /////////////////////////////////////////////
+    /// the content of the file, read and watched at the first access
+    fn content(&self, path: &Path) -> Result<String, String> {
+        if let Some(e) = self.entries.borrow().get(path) {
+            return e.content.clone(); }
+        let content= fs::read_to_string(path).map_err(|e| e.to_string());
+        let entry  = Entry{ content: content.clone(), _monitor: self.watch(path) };
+        self.entries.borrow_mut().insert(path.to_path_buf(), entry);
+        content
+    }
+    // text ////////////////////////////////////////////////
+    /// the text referred by `src`
+    pub fn text(&self, src: &str) -> Result<String, SourceError> {
+        let r   = SourceRef::parse(src)?;
+        let path= self.resolve(&r.path);
+        let content= self.content(&path).map_err(|e| SourceError::Io(path.clone(), e))?;
+        r.extract(&content)
+    }
+    /// the text shown for `m`: the source if it can be read, txt otherwise
+    pub fn text_of(&self, m: &Mat) -> String {
+        m.src.as_ref()
+            .and_then(|s| self.text(s).ok())
+            .unwrap_or_else(|| m.txt.clone())
+    }
+    pub fn state(&self, m: &Mat) -> SourceState {
+        match m.src.as_ref().map(|s| self.text(s)) {
+            None                      => SourceState::None,
+            Some(Err(e))              => SourceState::Missing(e),
+            Some(Ok(t)) if t != m.txt => SourceState::Stale,
+            Some(Ok(_))               => SourceState::Ok,
+        }
+    }
+    /// true if `src` refers to the resolved `path`
+    pub fn refers_to(&self, src: &str, path: &Path) -> bool {
+        SourceRef::parse(src).is_ok_and(|r| self.resolve(&r.path) == path)
+    }
+}
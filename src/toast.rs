@@ -0,0 +1,46 @@
+//! a message shown over a widget for a few seconds, e.g. a drop which failed (OperationHistory::connect_error)
+
+use std::cell::Cell;
+use std::rc::Rc;
+use gtk::prelude::*;
+use gtk::glib;
+use gtk::{Align, Label, Overlay, Revealer, RevealerTransitionType, Widget};
+
+pub const TOAST_SECONDS: u32 = 3;
+
+// Toast ///////////////////////////////////////////////////
+pub struct Toast {
+    revealer: Revealer,
+    label   : Label,
+    serial  : Cell<u32>, // the last show(), the timeout of an older one does not hide it
+}
+impl Toast {
+    /// `child` with a toast at the bottom of it
+    pub fn new(child: &impl IsA<Widget>) -> (Overlay, Rc<Toast>) {
+        let label= Label::builder().wrap(true).build();
+        label.add_css_class("toast");
+        let revealer= Revealer::builder()
+            .transition_type(RevealerTransitionType::Crossfade)
+            .halign(Align::Center)
+            .valign(Align::End)
+            .can_target(false) // the rows below are still dropped on
+            .child(&label)
+            .build();
+        let overlay= Overlay::builder().child(child).build();
+        overlay.add_overlay(&revealer);
+        (overlay, Rc::new( Toast{ revealer, label, serial: Cell::new(0) } ))
+    }
+    pub fn show(self: &Rc<Self>, msg: &str) {
+        self.label.set_label(msg);
+        self.revealer.set_reveal_child(true);
+        let serial= self.serial.get() + 1;
+        self.serial.set(serial);
+        let t= Rc::downgrade(self);
+        glib::timeout_add_seconds_local_once(TOAST_SECONDS, move || {
+            if let Some(t) = t.upgrade() {
+                if t.serial.get() == serial {
+                    t.revealer.set_reveal_child(false); }
+            }
+        });
+    }
+}
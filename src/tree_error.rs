@@ -0,0 +1,48 @@
+//! errors of the tree manipulation: drops, add / remove and undo / redo
+//!
+//! a manipulation finds every row, store and node it needs before it changes anything,
+//! so that a failure leaves the tree as it was. undo / redo of a transaction rolls back
+//! the items already applied. the error is reported by OperationHistory::report and shown as a toast.
+
+use std::fmt;
+
+// TreeError ///////////////////////////////////////////////
+#[derive(Debug, Clone, PartialEq)]
+pub enum TreeError {
+    NoDragObject,                      // the dropped value is not a ScenarioItemDragObject
+    NoRow,                             // the widget is not bound to a row
+    NoParentRow,                       // a row below the root level has no parent row
+    NoStore,                           // the ListStore holding a row is not created
+    NotNode,                           // an item of a model is not a ScenarioNodeObject
+    StaleRow(i32),                     // the seq of the row of the node id does not match its ListStore
+    Descendant(i32),                   // the node id cannot be moved into itself or its descendant
+    NotSelected,
+    NoNode(Vec<usize>),                // nothing at the path
+    NoId(i32),                         // no node of the id in the tree, e.g. removed after a dialog was opened
+    NotInserted(Vec<usize>),           // the path cannot be made, e.g. out of the range of the siblings
+    NotMoved(Vec<usize>, Vec<usize>),  // from, to
+    NoSnapshot(i32),                   // the history item has no record of the node id
+    Refused(String),                   // an operation refused with the reason, e.g. a conversion
+}
+pub type TreeResult<T> = Result<T, TreeError>;
+
+impl fmt::Display for TreeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TreeError::NoDragObject      => write!(f, "nothing to drop"),
+            TreeError::NoRow             => write!(f, "the row is gone"),
+            TreeError::NoParentRow       => write!(f, "the row has no parent row"),
+            TreeError::NoStore           => write!(f, "the siblings of the row are not shown"),
+            TreeError::NotNode           => write!(f, "not a scenario node"),
+            TreeError::StaleRow(id)      => write!(f, "the row of id:{} is out of date", id),
+            TreeError::Descendant(id)    => write!(f, "id:{} cannot be moved into itself", id),
+            TreeError::NotSelected       => write!(f, "nothing is selected"),
+            TreeError::NoNode(p)         => write!(f, "no node at {:?}", p),
+            TreeError::NoId(id)          => write!(f, "no node of id:{}", id),
+            TreeError::NotInserted(p)    => write!(f, "no place at {:?}", p),
+            TreeError::NotMoved(a, b)    => write!(f, "{:?} cannot be moved to {:?}", a, b),
+            TreeError::NoSnapshot(id)    => write!(f, "no record of id:{}", id),
+            TreeError::Refused(reason)   => write!(f, "{}", reason),
+        }
+    }
+}
@@ -0,0 +1,73 @@
+//! checks of a scenario tree
+//!
+//! - hierarchy: the kind of each node is allowed under its parent (hierarchy.rs)
+//! - labels: a lbl is unique, a lblref refers to an existing lbl, a label is not empty
+
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::scenario_node::ScenarioNode;
+use crate::scenario_query::pre_order_forest;
+use crate::hierarchy;
+
+// Issue ///////////////////////////////////////////////////
+#[derive(Debug, Clone, PartialEq)]
+pub struct Issue {
+    pub id  : i32,
+    pub path: Vec<usize>, // index path of the node
+    pub msg : String,
+}
+impl Issue {
+    fn new(node: &ScenarioNode, msg: String) -> Issue {
+        Issue{ id: node.id.get(), path: node.index_path(), msg }
+    }
+}
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let path: Vec<String>= self.path.iter().map(|i| i.to_string()).collect();
+        write!(f, "id:{} ({}): {}", self.id, path.join("/"), self.msg)
+    }
+}
+// check_hierarchy /////////////////////////////////////////
+pub fn check_hierarchy(first_root: Option<Rc<ScenarioNode>>) -> Vec<Issue> {
+    pre_order_forest(first_root)
+        .filter_map(|n| {
+            let parent= n.get_parent_node().map(|p| p.value.borrow().kind());
+            let kind  = n.value.borrow().kind();
+            if hierarchy::allows(parent, kind) {
+                return None; }
+            let place= parent.map_or(String::from("the root level"), |p| format!("a {}", p.name()));
+            Some( Issue::new(&n, format!("{} is not allowed in {}", kind.name(), place)) )
+        })
+        .collect()
+}
+// check_labels ////////////////////////////////////////////
+pub fn check_labels(first_root: Option<Rc<ScenarioNode>>) -> Vec<Issue> {
+    let nodes: Vec<Rc<ScenarioNode>>= pre_order_forest(first_root).collect();
+    let mut first: HashMap<String, i32>= HashMap::new();
+    let mut issues= Vec::new();
+    for n in nodes.iter() {
+        let Some(l) = n.value.borrow().lbl() else { continue };
+        if l.is_empty() {
+            issues.push( Issue::new(n, String::from("empty lbl")) );
+        } else if let Some(id) = first.get(&l) {
+            issues.push( Issue::new(n, format!("lbl {} is also defined by id:{}", l, id)) );
+        } else {
+            first.insert(l, n.id.get());
+        }
+    }
+    for n in nodes.iter() {
+        let Some(r) = n.value.borrow().lblref() else { continue };
+        if !first.contains_key(&r) {
+            issues.push( Issue::new(n, format!("lblref {} refers to no lbl", r)) ); }
+    }
+    issues
+}
+// validate ////////////////////////////////////////////////
+/// all the checks
+pub fn validate(first_root: Option<Rc<ScenarioNode>>) -> Vec<Issue> {
+    let mut issues= check_hierarchy(first_root.clone());
+    issues.extend( check_labels(first_root) );
+    issues
+}